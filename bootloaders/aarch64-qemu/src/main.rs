@@ -4,25 +4,228 @@
 #[cfg(not(target_arch = "aarch64"))]
 compile_error!("This binary needs to be compiled for aarch64.");
 
-use core::{arch::global_asm, fmt::Write};
+use core::{
+    arch::{asm, global_asm},
+    fmt::Write,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "semihosting")]
+use developing_modules::aarch64::semihosting::{self, SemihostingConsole};
+use developing_modules::{
+    aarch64::{
+        exceptions,
+        gic::Gic,
+        psci::Psci,
+        registers::{current_el, mpidr_affinity},
+        smp,
+    },
+    fdt::Fdt,
+    sections,
+    serial::probe::{probe, ConsoleUart},
+};
 
 // Include the start procedure
 global_asm!(include_str!("entry.S"));
 
+// How long to wait for secondary CPUs to check in, in spin loop iterations
+const SECONDARY_TIMEOUT: usize = 10_000_000;
+
+// The number of CPUs that are running, including the boot CPU
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(1);
+
+// The device tree from the boot CPU, so that secondary CPUs can find the GIC
+static DEVICE_TREE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+
+// Where boot messages go: the UART when there is one, otherwise semihosting if it is enabled
+enum Log {
+    Uart(ConsoleUart),
+    #[cfg(feature = "semihosting")]
+    Semihosting(SemihostingConsole),
+    #[cfg(not(feature = "semihosting"))]
+    None,
+}
+
+impl Write for Log {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        match self {
+            Log::Uart(uart) => uart.write_str(s),
+            #[cfg(feature = "semihosting")]
+            Log::Semihosting(console) => console.write_str(s),
+            #[cfg(not(feature = "semihosting"))]
+            Log::None => Ok(()),
+        }
+    }
+}
+
 #[panic_handler]
-fn handle_panic(_info: &core::panic::PanicInfo) -> ! {
-    //let uart = unsafe { Pl011Uart::new(UART0_BASE_ADDRESS).unwrap() };
-    //writeln!(uart, "[PANIC] {}", info).unwrap();
-    loop {}
+#[cfg_attr(not(feature = "semihosting"), allow(unused_variables))]
+fn handle_panic(info: &core::panic::PanicInfo) -> ! {
+    #[cfg(feature = "semihosting")]
+    unsafe {
+        let _ = writeln!(SemihostingConsole::new(), "[PANIC] {}", info);
+        semihosting::exit(1);
+    }
+
+    #[cfg(not(feature = "semihosting"))]
+    loop {
+        core::hint::spin_loop();
+    }
 }
 
 #[no_mangle]
 #[link_section = ".text.boot"]
-pub unsafe extern "C" fn _entry_stage1() -> ! {
-    // Initialize default UART
-    //let uart = Pl011Uart::new(UART0_BASE_ADDRESS).unwrap();
+pub unsafe extern "C" fn _entry_stage1(dtb: *const u8, boot_el: u64) -> ! {
+    // Report faults instead of jumping to an empty vector table
+    exceptions::install(exceptions::default_handler);
+    sections::init_stack_guard();
+
+    let fdt = Fdt::from_ptr(dtb);
+    DEVICE_TREE.store(dtb as *mut u8, Ordering::Release);
+
+    // Keep booting without a UART, so that the rest of the boot can still be reported
+    let mut log = match probe(fdt.as_ref().ok(), None) {
+        Ok(uart) => Log::Uart(uart),
+        #[cfg(feature = "semihosting")]
+        Err(err) => {
+            let mut log = Log::Semihosting(SemihostingConsole::new());
+            writeln!(log, "{}, logging through semihosting", err).unwrap();
+            log
+        }
+        #[cfg(not(feature = "semihosting"))]
+        Err(_) => Log::None,
+    };
+
+    writeln!(log, "Hello UART!").unwrap();
+    writeln!(
+        log,
+        "Booted in EL{}, running in EL{}",
+        boot_el,
+        current_el()
+    )
+    .unwrap();
+    if let Log::Uart(uart) = &log {
+        let base = uart.base();
+        writeln!(log, "Serial port initialized at {:#x}.", base).unwrap();
+    }
+    writeln!(
+        log,
+        "Program: {:#x?}, stack: {:#x?}",
+        sections::program_range(),
+        sections::stack_range()
+    )
+    .unwrap();
+
+    let psci = fdt.as_ref().ok().and_then(Psci::from_device_tree);
+    if let Some(psci) = psci {
+        let (major, minor) = psci.version();
+        writeln!(log, "PSCI {}.{} through {:?}", major, minor, psci.conduit()).unwrap();
+    }
+
+    match fdt {
+        Ok(fdt) => {
+            writeln!(log, "Device tree at {:p}, {} bytes", dtb, fdt.total_size()).unwrap();
+            for region in fdt.memory_regions() {
+                writeln!(
+                    log,
+                    "Memory: {:#x} - {:#x}",
+                    region.address,
+                    region.address + region.size
+                )
+                .unwrap();
+            }
+
+            // Interrupts stay masked in DAIF, this only brings the GIC to a known state
+            if let Some(gic) = Gic::from_device_tree(&fdt) {
+                gic.init();
+                match gic.init_cpu() {
+                    Ok(()) => writeln!(
+                        log,
+                        "GICv{} at {:#x}, {} interrupts",
+                        gic.version(),
+                        gic.distributor(),
+                        gic.interrupt_count()
+                    )
+                    .unwrap(),
+                    Err(err) => {
+                        writeln!(log, "Failed to initialize GICv{}: {}", gic.version(), err)
+                            .unwrap()
+                    }
+                };
+            }
+
+            if let Some(psci) = &psci {
+                start_secondaries(&mut log, &fdt, psci);
+            }
+        }
+        Err(err) => writeln!(log, "Could not read device tree at {:p}: {}", dtb, err).unwrap(),
+    };
+
+    if let Err(err) = sections::check_stack_guard() {
+        panic!("{}", err);
+    }
+
+    #[cfg(feature = "semihosting")]
+    semihosting::exit(0);
+
+    // Power off so that QEMU exits
+    #[cfg(not(feature = "semihosting"))]
+    match psci {
+        Some(psci) => psci.system_off(),
+        None => loop {
+            core::hint::spin_loop();
+        },
+    }
+}
+
+// Starts every other enabled CPU in the device tree and waits for them to check in. Only those
+// CPUs are numbered, in device tree order, which picks their stacks. Each one gets its number as
+// the argument of `secondary_main`.
+unsafe fn start_secondaries(log: &mut impl Write, fdt: &Fdt, psci: &Psci) {
+    let boot_mpidr = mpidr_affinity();
+    let mut started = 0;
+
+    let secondaries = fdt
+        .cpus()
+        .filter(|node| node.is_enabled())
+        .filter_map(|node| node.first_reg().map(|reg| reg.address))
+        .filter(|&mpidr| mpidr != boot_mpidr);
+
+    for (cpu, mpidr) in secondaries.enumerate() {
+        match smp::start_secondary(psci, mpidr, cpu, secondary_main, cpu as u64) {
+            Ok(()) => started += 1,
+            Err(err) => writeln!(log, "Could not start CPU {:#x}: {}", mpidr, err).unwrap(),
+        }
+    }
+
+    for _ in 0..SECONDARY_TIMEOUT {
+        if ONLINE_CPUS.load(Ordering::Acquire) > started {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    writeln!(log, "{} CPUs online", ONLINE_CPUS.load(Ordering::Acquire)).unwrap();
+}
+
+extern "C" fn secondary_main(_mpidr: u64, cpu: u64) -> ! {
+    unsafe { exceptions::install(exceptions::default_handler) };
+    if let Err(err) = sections::check_cpu_stack_guard(cpu as usize) {
+        panic!("{}", err);
+    }
+
+    // The boot CPU already initialized the distributor, but each CPU has its own interface
+    let fdt = unsafe { Fdt::from_ptr(DEVICE_TREE.load(Ordering::Acquire)) };
+    if let Some(gic) = fdt.as_ref().ok().and_then(Gic::from_device_tree) {
+        if let Err(err) = unsafe { gic.init_cpu() } {
+            panic!("Failed to initialize the GIC on CPU {}: {}", cpu, err);
+        }
+    }
 
-    //writeln!(uart, "Hello UART!").unwrap();
+    ONLINE_CPUS.fetch_add(1, Ordering::AcqRel);
 
-    loop {}
+    loop {
+        unsafe { asm!("wfe", options(nomem, nostack)) };
+    }
 }
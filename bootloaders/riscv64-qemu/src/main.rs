@@ -1,27 +1,228 @@
 #![no_std]
 #![no_main]
 
-use core::arch::global_asm;
+use core::{
+    arch::{asm, global_asm},
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use developing_modules::{
+    fdt::Fdt,
+    riscv64::{
+        clint::timebase_frequency,
+        plic::Plic,
+        registers::time,
+        sbi::{ResetReason, ResetType, Sbi},
+        smp,
+        trap::{self, Interrupt, TrapFrame},
+    },
+    sections,
+    serial::probe::{probe, ConsoleUart},
+};
 
 #[cfg(not(target_arch = "riscv64"))]
 compile_error!("This binary needs to be compiled for riscv64");
 
 global_asm!(include_str!("entry.S"));
 
+// How long to wait for secondary harts to check in, in spin loop iterations
+const SECONDARY_TIMEOUT: usize = 10_000_000;
+
+// The number of harts that are running, including the boot hart
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(1);
+
+// The number of timer interrupts taken by the boot hart
+static TIMER_TICKS: AtomicUsize = AtomicUsize::new(0);
+
+// Where boot messages go: the UART when there is one, otherwise the SBI debug console
+enum Log {
+    Uart(ConsoleUart),
+    Sbi(Sbi),
+}
+
+impl Write for Log {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        match self {
+            Log::Uart(uart) => uart.write_str(s),
+            Log::Sbi(sbi) => sbi.write_str(s),
+        }
+    }
+}
+
 #[panic_handler]
-fn handle_panic(_info: &core::panic::PanicInfo) -> ! {
+fn handle_panic(info: &core::panic::PanicInfo) -> ! {
+    // The SBI console works even when no UART could be found
+    let mut sbi = Sbi::probe();
+    let _ = writeln!(sbi, "[PANIC] {}", info);
+    sbi.system_reset(ResetType::Shutdown, ResetReason::SystemFailure);
     loop {}
 }
 
 #[no_mangle]
 #[link_section = ".text.boot"]
-pub unsafe extern "C" fn _entry_stage1() -> ! {
-    const UART: *mut u8 = 0x1000_0000 as *mut u8;
+pub unsafe extern "C" fn _entry_stage1(hart_id: u64, dtb: *const u8) -> ! {
+    // Report faults instead of jumping to whatever stvec held
+    trap::install();
+    sections::init_stack_guard();
+
+    let fdt = Fdt::from_ptr(dtb);
 
-    const HELLO: &'static str = "Hello RiscV!";
-    for byte in HELLO.bytes() {
-        core::ptr::write_volatile(UART, byte);
+    // Keep booting without a UART, so that the rest of the boot can still be reported
+    let mut log = match probe(fdt.as_ref().ok(), None) {
+        Ok(uart) => Log::Uart(uart),
+        Err(err) => {
+            let mut log = Log::Sbi(Sbi::probe());
+            writeln!(log, "{}, logging to the SBI console", err).unwrap();
+            log
+        }
+    };
+
+    writeln!(log, "Hello RiscV! Booted on hart {}", hart_id).unwrap();
+    if let Log::Uart(uart) = &log {
+        let base = uart.base();
+        writeln!(log, "Serial port initialized at {:#x}.", base).unwrap();
+    }
+    writeln!(
+        log,
+        "Program: {:#x?}, stack: {:#x?}",
+        sections::program_range(),
+        sections::stack_range()
+    )
+    .unwrap();
+
+    let sbi = Sbi::probe();
+    writeln!(
+        log,
+        "SBI {} implemented by {}",
+        sbi.spec_version,
+        sbi.impl_name().unwrap_or("unknown firmware")
+    )
+    .unwrap();
+
+    match fdt {
+        Ok(fdt) => {
+            writeln!(log, "Device tree at {:p}, {} bytes", dtb, fdt.total_size()).unwrap();
+            for region in fdt.memory_regions() {
+                writeln!(
+                    log,
+                    "Memory: {:#x} - {:#x}",
+                    region.address,
+                    region.address + region.size
+                )
+                .unwrap();
+            }
+
+            // Start with every external interrupt disabled for this hart
+            if let Some(plic) = Plic::from_device_tree(&fdt) {
+                match Plic::supervisor_context(&fdt, hart_id) {
+                    Some(context) => {
+                        plic.init_context(context);
+                        writeln!(
+                            log,
+                            "PLIC at {:#x} with {} sources, S-mode context {}",
+                            plic.base(),
+                            plic.source_count() - 1,
+                            context
+                        )
+                        .unwrap();
+                    }
+                    None => writeln!(
+                        log,
+                        "PLIC at {:#x} has no S-mode context for hart {}",
+                        plic.base(),
+                        hart_id
+                    )
+                    .unwrap(),
+                };
+            }
+
+            if let Some(frequency) = timebase_frequency(&fdt) {
+                check_timer_interrupt(&mut log, &sbi, frequency);
+            }
+
+            if sbi.has_hsm {
+                start_secondaries(&mut log, &fdt, &sbi, hart_id);
+            }
+        }
+        Err(err) => writeln!(log, "Could not read device tree at {:p}: {}", dtb, err).unwrap(),
+    };
+
+    if let Err(err) = sections::check_stack_guard() {
+        panic!("{}", err);
     }
 
     loop {}
 }
+
+// Arms the SBI timer 10ms from now and waits for its interrupt to reach the S-mode handler
+unsafe fn check_timer_interrupt(log: &mut impl Write, sbi: &Sbi, frequency: u64) {
+    trap::set_interrupt_handler(Interrupt::SupervisorTimer, handle_timer).unwrap();
+    if let Err(err) = sbi.set_timer(time() + frequency / 100) {
+        writeln!(log, "Could not set the timer: {}", err).unwrap();
+        return;
+    }
+
+    trap::enable_interrupt(Interrupt::SupervisorTimer);
+    trap::enable_interrupts();
+    for _ in 0..SECONDARY_TIMEOUT {
+        if TIMER_TICKS.load(Ordering::Acquire) > 0 {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    trap::disable_interrupts();
+    trap::disable_interrupt(Interrupt::SupervisorTimer);
+
+    match TIMER_TICKS.load(Ordering::Acquire) {
+        0 => writeln!(log, "No timer interrupt at {} Hz", frequency).unwrap(),
+        _ => writeln!(log, "Timer interrupt received at {} Hz", frequency).unwrap(),
+    };
+}
+
+fn handle_timer(_frame: &mut TrapFrame) {
+    // Pushing the deadline out clears the pending timer interrupt
+    let _ = Sbi::probe().set_timer(u64::MAX);
+    TIMER_TICKS.fetch_add(1, Ordering::AcqRel);
+}
+
+// Starts every other enabled hart in the device tree and waits for them to check in. Only those
+// harts are numbered, in device tree order, which picks their stacks. Each one gets its number as
+// the argument of `secondary_main`.
+unsafe fn start_secondaries(log: &mut impl Write, fdt: &Fdt, sbi: &Sbi, boot_hart_id: u64) {
+    let mut started = 0;
+
+    let secondaries = fdt
+        .cpus()
+        .filter(|node| node.is_enabled())
+        .filter_map(|node| node.first_reg().map(|reg| reg.address))
+        .filter(|&hart_id| hart_id != boot_hart_id);
+
+    for (cpu, hart_id) in secondaries.enumerate() {
+        match smp::start_secondary(sbi, hart_id, cpu, secondary_main, cpu as u64) {
+            Ok(()) => started += 1,
+            Err(err) => writeln!(log, "Could not start hart {}: {}", hart_id, err).unwrap(),
+        }
+    }
+
+    for _ in 0..SECONDARY_TIMEOUT {
+        if ONLINE_HARTS.load(Ordering::Acquire) > started {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    writeln!(log, "{} harts online", ONLINE_HARTS.load(Ordering::Acquire)).unwrap();
+}
+
+extern "C" fn secondary_main(_hart_id: u64, cpu: u64) -> ! {
+    unsafe { trap::install() };
+    if let Err(err) = sections::check_cpu_stack_guard(cpu as usize) {
+        panic!("{}", err);
+    }
+    ONLINE_HARTS.fetch_add(1, Ordering::AcqRel);
+
+    loop {
+        unsafe { asm!("wfi", options(nomem, nostack)) };
+    }
+}
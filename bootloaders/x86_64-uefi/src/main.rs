@@ -4,40 +4,160 @@
 use core::{arch::asm, ffi::c_void, fmt::Write, mem, ptr, slice};
 
 use developing_modules::{
-    firmware::uefi::memory_map::*,
-    serial::Serial,
-    x86_64::{gdt::Gdtr, uart::*},
+    acpi::{
+        madt::{Madt, MADT_SIGNATURE},
+        spcr::{Spcr, SPCR_SIGNATURE},
+        tables::AcpiTables,
+    },
+    firmware::uefi::{
+        console::{UefiColor, UefiConsoleOut},
+        file_system::read_boot_file,
+        graphics::{acquire_framebuffer, UefiResolution},
+        memory_map::*,
+    },
+    memory::OffsetMapper,
+    serial::probe::{probe, ConsoleUart},
+    x86_64::{cpuid::Cpuid, gdt::Gdtr, ioapic::IoApic},
 };
 
 #[cfg(not(target_arch = "x86_64"))]
 compile_error!("Target needs to be x86_64");
 
+const KERNEL_PATH: &str = "\\EFI\\BOOT\\KERNEL.ELF";
+const PREFERRED_RESOLUTION: UefiResolution = UefiResolution::Exact {
+    width: 1024,
+    height: 768,
+};
+
+// Where boot messages go: the serial port when there is one, otherwise the UEFI console
+enum Log {
+    Serial(ConsoleUart),
+    Console(UefiConsoleOut),
+}
+
+impl Write for Log {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        match self {
+            Log::Serial(serial) => serial.write_str(s),
+            // Ignore console errors, so that losing a message never stops the boot
+            Log::Console(console) => {
+                let _ = console.write_str(s);
+                Ok(())
+            }
+        }
+    }
+}
+
 #[panic_handler]
 fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
     loop {}
 }
 
 #[export_name = "efi_main"]
-unsafe extern "efiapi" fn entry(_image_handle: *const c_void, system_table: *const UefiSystemTable) -> u64 {
-    // Init serial device
-    let mut serial = UartX86::new(
-        UartX86Port::Com1,
-        UartX86Baud::Baud38400,
-        UartX86DataBits::Bits8,
-        UartX86StopBits::Bits1,
-        UartX86Parity::None,
-    );
-    if serial.init().is_ok() {
-        serial
-            .serial_write_str("Serial port initialized.\n")
-            .unwrap();
+unsafe extern "efiapi" fn entry(image_handle: UefiHandle, system_table: *const UefiSystemTable) -> u64 {
+    // UEFI identity maps all of physical memory
+    let mapper = OffsetMapper::new(0);
+    let acpi_tables = (*system_table)
+        .acpi_rsdp()
+        .map(|rsdp| (rsdp, AcpiTables::from_rsdp(&mapper, rsdp)));
+
+    // Init serial device from the ACPI SPCR, falling back to the legacy COM ports
+    let spcr = acpi_tables
+        .as_ref()
+        .and_then(|(_, tables)| tables.as_ref().ok())
+        .and_then(|tables| tables.find(&SPCR_SIGNATURE))
+        .and_then(|table| Spcr::parse(&table).ok());
+    let mut log = match probe(None, spcr.as_ref()) {
+        Ok(mut serial) => {
+            writeln!(serial, "Serial port initialized at {:#x}.", serial.base()).unwrap();
+            Log::Serial(serial)
+        }
+        Err(err) => {
+            let mut log = Log::Console((*system_table).console_out());
+            let _ = writeln!(log, "{}, logging to the UEFI console", err);
+            log
+        }
+    };
+
+    // Set up the framebuffer before printing to the console, as changing modes clears the screen
+    let boot_services = (*system_table).boot_services();
+    let framebuffer = acquire_framebuffer(&*boot_services, PREFERRED_RESOLUTION);
+    match framebuffer {
+        Ok(framebuffer) => writeln!(
+            log,
+            "Framebuffer: {}x{} at {:#x}, stride {}, format {:?}",
+            framebuffer.width,
+            framebuffer.height,
+            framebuffer.base,
+            framebuffer.stride,
+            framebuffer.format
+        )
+        .unwrap(),
+        Err(err) => writeln!(log, "Failed to get framebuffer: {:#x}", err).unwrap(),
+    };
+
+    // Init UEFI console
+    let mut console = (*system_table).console_out();
+    let _ = console.clear_screen();
+    let _ = console.set_attribute(UefiColor::LightGreen, UefiColor::Black);
+    let _ = writeln!(console, "x86_64-uefi bootloader");
+    let _ = console.set_attribute(UefiColor::LightGray, UefiColor::Black);
+
+    // Find platform tables for the kernel
+    match acpi_tables {
+        Some((rsdp, Ok(tables))) => {
+            writeln!(log, "ACPI RSDP at {:#x}", rsdp).unwrap();
+            if let Some(madt) = tables
+                .find(&MADT_SIGNATURE)
+                .and_then(|table| Madt::parse(&table).ok())
+            {
+                writeln!(
+                    log,
+                    "{} processors, local APIC at {:#x}",
+                    madt.processor_count(),
+                    madt.local_apic_address()
+                )
+                .unwrap();
+                let apic_mode = if Cpuid::new().features().x2apic {
+                    "x2APIC"
+                } else {
+                    "xAPIC"
+                };
+                writeln!(
+                    log,
+                    "Local APIC supports {}, 8259 PICs present: {}",
+                    apic_mode,
+                    madt.has_8259_pics()
+                )
+                .unwrap();
+                // Only read the IO APICs, as UEFI still owns interrupt routing until boot services are exited
+                for ioapic in IoApic::from_madt(&mapper, &madt) {
+                    writeln!(
+                        log,
+                        "IO APIC {} handles GSIs {:?}",
+                        ioapic.id(),
+                        ioapic.gsi_range()
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        Some((rsdp, Err(err))) => {
+            writeln!(log, "Invalid ACPI tables at {:#x}: {}", rsdp, err).unwrap()
+        }
+        None => writeln!(log, "ACPI RSDP not found").unwrap(),
+    };
+    if let Some(smbios) = (*system_table)
+        .smbios3_entry_point()
+        .or_else(|| (*system_table).smbios_entry_point())
+    {
+        writeln!(log, "SMBIOS entry point at {:#x}", smbios).unwrap();
     }
 
     // Get memory map size
-    let boot_services = (*system_table).boot_services();
     let map_size = (*boot_services).memory_map_size();
     if let Err(err) = map_size {
-        writeln!(serial, "Error while getting memory map size: {:#x}", err).unwrap();
+        writeln!(log, "Error while getting memory map size: {:#x}", err).unwrap();
     };
     let map_size = map_size.unwrap() + mem::size_of::<UefiMemoryDescriptor>() * 2;
 
@@ -51,27 +171,45 @@ unsafe extern "efiapi" fn entry(_image_handle: *const c_void, system_table: *con
 
     // Retrieve memory map from UEFI
     if status != SUCCESS {
-        writeln!(serial, "Failed to allocate for memory map: {:#x}", status).unwrap();
+        writeln!(log, "Failed to allocate for memory map: {:#x}", status).unwrap();
     } else {
         let mut buffer = slice::from_raw_parts_mut(buffer as *mut u8, map_size);
         let map = (*boot_services).retrieve_memory_map(&mut buffer);
         match map {
-            Ok(map) => writeln!(serial, "Got map!").unwrap(),
-            Err(err) => writeln!(serial, "Error: {:#x}", err).unwrap(),
+            Ok(map) => writeln!(log, "Got map!").unwrap(),
+            Err(err) => writeln!(log, "Error: {:#x}", err).unwrap(),
         };
     }
 
+    // Read the kernel from the boot volume
+    match read_boot_file(&*boot_services, image_handle, KERNEL_PATH) {
+        Ok(kernel) => {
+            writeln!(
+                log,
+                "Read kernel: {} bytes at {:p}",
+                kernel.len(),
+                kernel.as_ptr()
+            )
+            .unwrap();
+            let _ = writeln!(console, "Read kernel: {} bytes", kernel.len());
+        }
+        Err(err) => {
+            writeln!(log, "Failed to read kernel {}: {:#x}", KERNEL_PATH, err).unwrap();
+            let _ = writeln!(console, "Failed to read kernel {}: {:#x}", KERNEL_PATH, err);
+        }
+    };
+
     // TODO: Set the GDT
     let mut gdtr: Gdtr = Gdtr { base: 0, limit: 0 };
     unsafe {
         asm!("sgdt [{}]", in(reg) &mut gdtr, options(nostack, preserves_flags));
     }
-    //writeln!(serial, "{:?}", gdtr).unwrap();
+    //writeln!(log, "{:?}", gdtr).unwrap();
 
     let _table = unsafe { gdtr.descriptor_table() };
-    //writeln!(serial, "{} Descriptors", table.len()).unwrap();
+    //writeln!(log, "{} Descriptors", table.len()).unwrap();
     //for descriptor in table {
-    //    writeln!(serial, "{:?}", descriptor).unwrap();
+    //    writeln!(log, "{:?}", descriptor).unwrap();
     //}
 
     loop {}
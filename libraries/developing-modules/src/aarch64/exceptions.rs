@@ -0,0 +1,503 @@
+// The EL1 exception vector table. Every vector saves the general purpose registers and the
+// exception registers into a `TrapFrame` on the current stack, calls the installed handler, and
+// restores the (possibly modified) frame before returning with `eret`.
+//
+// SIMD and floating point registers are not saved, which is fine for the bootloaders since their
+// targets disable those features.
+
+use core::{
+    arch::{asm, global_asm},
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::aarch64::registers::Vbar;
+
+pub type ExceptionHandler = fn(&mut TrapFrame, ExceptionKind);
+
+/// The registers of the interrupted code, saved by the vector table.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct TrapFrame {
+    pub x: [u64; 31],
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: Esr,
+    pub far: u64,
+    /// The stack pointer before the frame was pushed. Changing it has no effect.
+    pub sp: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExceptionSource {
+    CurrentElSp0,
+    CurrentElSpx,
+    LowerElAarch64,
+    LowerElAarch32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExceptionType {
+    Synchronous,
+    Irq,
+    Fiq,
+    SError,
+}
+
+/// Which of the 16 vectors was taken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionKind {
+    pub source: ExceptionSource,
+    pub ty: ExceptionType,
+}
+
+/// The exception syndrome register, ESR_EL1.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Esr(pub u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExceptionClass {
+    Unknown,
+    WfiWfe,
+    SimdFloatingPoint,
+    PointerAuthentication,
+    IllegalExecutionState,
+    Svc,
+    Hvc,
+    Smc,
+    MsrMrs,
+    Sve,
+    InstructionAbortLowerEl,
+    InstructionAbortSameEl,
+    PcAlignment,
+    DataAbortLowerEl,
+    DataAbortSameEl,
+    SpAlignment,
+    FloatingPoint,
+    SError,
+    BreakpointLowerEl,
+    BreakpointSameEl,
+    SoftwareStepLowerEl,
+    SoftwareStepSameEl,
+    WatchpointLowerEl,
+    WatchpointSameEl,
+    Brk,
+    Other(u8),
+}
+
+/// The cause of an instruction or data abort, from the fault status code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaultStatus {
+    AddressSize { level: u8 },
+    Translation { level: u8 },
+    AccessFlag { level: u8 },
+    Permission { level: u8 },
+    SynchronousExternal,
+    Alignment,
+    TlbConflict,
+    Other(u8),
+}
+
+// The handler is stored as a `usize` so that it can be swapped atomically; 0 means none
+static HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// The offsets in the vector table below depend on this layout
+const TRAP_FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>();
+const _: () = assert!(TRAP_FRAME_SIZE == 288);
+
+global_asm!(
+    r#"
+.macro EXCEPTION_VECTOR kind
+    .balign 0x80
+    sub sp, sp, #{frame_size}
+    stp x0, x1, [sp, #0]
+    mov x0, #\kind
+    b __exception_common
+.endm
+
+.pushsection .text.exceptions, "ax"
+.balign 0x800
+.globl __exception_vectors
+__exception_vectors:
+    EXCEPTION_VECTOR 0
+    EXCEPTION_VECTOR 1
+    EXCEPTION_VECTOR 2
+    EXCEPTION_VECTOR 3
+    EXCEPTION_VECTOR 4
+    EXCEPTION_VECTOR 5
+    EXCEPTION_VECTOR 6
+    EXCEPTION_VECTOR 7
+    EXCEPTION_VECTOR 8
+    EXCEPTION_VECTOR 9
+    EXCEPTION_VECTOR 10
+    EXCEPTION_VECTOR 11
+    EXCEPTION_VECTOR 12
+    EXCEPTION_VECTOR 13
+    EXCEPTION_VECTOR 14
+    EXCEPTION_VECTOR 15
+
+// x0 holds the vector index and the old x0 and x1 are already saved
+__exception_common:
+    stp x2, x3, [sp, #16]
+    stp x4, x5, [sp, #32]
+    stp x6, x7, [sp, #48]
+    stp x8, x9, [sp, #64]
+    stp x10, x11, [sp, #80]
+    stp x12, x13, [sp, #96]
+    stp x14, x15, [sp, #112]
+    stp x16, x17, [sp, #128]
+    stp x18, x19, [sp, #144]
+    stp x20, x21, [sp, #160]
+    stp x22, x23, [sp, #176]
+    stp x24, x25, [sp, #192]
+    stp x26, x27, [sp, #208]
+    stp x28, x29, [sp, #224]
+    mrs x1, elr_el1
+    stp x30, x1, [sp, #240]
+    mrs x1, spsr_el1
+    mrs x2, esr_el1
+    stp x1, x2, [sp, #256]
+    mrs x1, far_el1
+    add x2, sp, #{frame_size}
+    stp x1, x2, [sp, #272]
+
+    mov x1, x0
+    mov x0, sp
+    bl {dispatch}
+
+    ldp x1, x2, [sp, #248]
+    msr elr_el1, x1
+    msr spsr_el1, x2
+    ldp x2, x3, [sp, #16]
+    ldp x4, x5, [sp, #32]
+    ldp x6, x7, [sp, #48]
+    ldp x8, x9, [sp, #64]
+    ldp x10, x11, [sp, #80]
+    ldp x12, x13, [sp, #96]
+    ldp x14, x15, [sp, #112]
+    ldp x16, x17, [sp, #128]
+    ldp x18, x19, [sp, #144]
+    ldp x20, x21, [sp, #160]
+    ldp x22, x23, [sp, #176]
+    ldp x24, x25, [sp, #192]
+    ldp x26, x27, [sp, #208]
+    ldp x28, x29, [sp, #224]
+    ldr x30, [sp, #240]
+    ldp x0, x1, [sp, #0]
+    add sp, sp, #{frame_size}
+    eret
+
+.popsection
+"#,
+    frame_size = const TRAP_FRAME_SIZE,
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    static __exception_vectors: u8;
+}
+
+extern "C" fn dispatch(frame: &mut TrapFrame, index: u64) {
+    let kind = ExceptionKind::from_index(index);
+
+    match HANDLER.load(Ordering::Acquire) {
+        0 => default_handler(frame, kind),
+        handler => {
+            let handler: ExceptionHandler = unsafe { core::mem::transmute(handler) };
+            handler(frame, kind)
+        }
+    }
+}
+
+/// Points VBAR_EL1 at the vector table and sends every exception to `handler`.
+///
+/// # Safety
+///
+/// Must run at EL1 with a stack that has room for a [`TrapFrame`] and the handler.
+pub unsafe fn install(handler: ExceptionHandler) {
+    HANDLER.store(handler as usize, Ordering::Release);
+    Vbar(vector_table_address()).write();
+}
+
+pub fn vector_table_address() -> u64 {
+    unsafe { &__exception_vectors as *const u8 as u64 }
+}
+
+/// Clears the IRQ mask in DAIF, so that IRQs from the interrupt controller are taken.
+///
+/// # Safety
+///
+/// The vector table must be installed, and its handler must be ready for IRQs.
+pub unsafe fn enable_interrupts() {
+    asm!("msr daifclr, #2", options(nomem, nostack));
+}
+
+pub fn disable_interrupts() {
+    unsafe { asm!("msr daifset, #2", options(nomem, nostack)) };
+}
+
+/// Panics with a description of the exception, since nothing else can handle it yet.
+pub fn default_handler(frame: &mut TrapFrame, kind: ExceptionKind) {
+    panic!("Unhandled exception\n{}", ExceptionReport { frame, kind });
+}
+
+/// Formats an exception, its syndrome and the faulting address when there is one.
+pub struct ExceptionReport<'a> {
+    pub frame: &'a TrapFrame,
+    pub kind: ExceptionKind,
+}
+
+impl fmt::Display for ExceptionReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frame = self.frame;
+
+        writeln!(f, "{} at {:#x}: {}", self.kind, frame.elr, frame.esr)?;
+        if frame.esr.far_is_valid() {
+            writeln!(f, "Fault address: {:#x}", frame.far)?;
+        }
+        writeln!(
+            f,
+            "ESR: {:#x}, SPSR: {:#x}, SP: {:#x}",
+            frame.esr.0, frame.spsr, frame.sp
+        )?;
+
+        for (i, pair) in frame.x.chunks(2).enumerate() {
+            match pair {
+                [a, b] => writeln!(
+                    f,
+                    "x{:<2} {:#018x}  x{:<2} {:#018x}",
+                    i * 2,
+                    a,
+                    i * 2 + 1,
+                    b
+                )?,
+                [a] => writeln!(f, "x{:<2} {:#018x}", i * 2, a)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ExceptionKind {
+    fn from_index(index: u64) -> Self {
+        let source = match index / 4 {
+            0 => ExceptionSource::CurrentElSp0,
+            1 => ExceptionSource::CurrentElSpx,
+            2 => ExceptionSource::LowerElAarch64,
+            _ => ExceptionSource::LowerElAarch32,
+        };
+        let ty = match index % 4 {
+            0 => ExceptionType::Synchronous,
+            1 => ExceptionType::Irq,
+            2 => ExceptionType::Fiq,
+            _ => ExceptionType::SError,
+        };
+
+        Self { source, ty }
+    }
+}
+
+impl fmt::Display for ExceptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ty = match self.ty {
+            ExceptionType::Synchronous => "Synchronous exception",
+            ExceptionType::Irq => "IRQ",
+            ExceptionType::Fiq => "FIQ",
+            ExceptionType::SError => "SError",
+        };
+        let source = match self.source {
+            ExceptionSource::CurrentElSp0 => "the current EL with SP0",
+            ExceptionSource::CurrentElSpx => "the current EL with SPx",
+            ExceptionSource::LowerElAarch64 => "a lower EL in AArch64",
+            ExceptionSource::LowerElAarch32 => "a lower EL in AArch32",
+        };
+
+        write!(f, "{} from {}", ty, source)
+    }
+}
+
+impl Esr {
+    pub fn exception_class(&self) -> ExceptionClass {
+        ExceptionClass::from_code(((self.0 >> 26) & 0x3f) as u8)
+    }
+
+    /// Returns true if the trapped instruction was 32 bits long.
+    pub fn is_32bit_instruction(&self) -> bool {
+        (self.0 & (1 << 25)) != 0
+    }
+
+    /// Returns the instruction specific syndrome.
+    pub fn iss(&self) -> u32 {
+        (self.0 & 0x1ff_ffff) as u32
+    }
+
+    pub fn is_abort(&self) -> bool {
+        matches!(
+            self.exception_class(),
+            ExceptionClass::InstructionAbortLowerEl
+                | ExceptionClass::InstructionAbortSameEl
+                | ExceptionClass::DataAbortLowerEl
+                | ExceptionClass::DataAbortSameEl
+        )
+    }
+
+    /// Returns the cause of an instruction or data abort.
+    pub fn fault_status(&self) -> Option<FaultStatus> {
+        self.is_abort()
+            .then(|| FaultStatus::from_code((self.iss() & 0x3f) as u8))
+    }
+
+    /// Returns true if a data abort was caused by a write.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self.exception_class(),
+            ExceptionClass::DataAbortLowerEl | ExceptionClass::DataAbortSameEl
+        ) && (self.iss() & (1 << 6)) != 0
+    }
+
+    /// Returns true if FAR_EL1 holds the faulting address.
+    pub fn far_is_valid(&self) -> bool {
+        match self.exception_class() {
+            // FnV is set when the address is not valid
+            _ if self.is_abort() => (self.iss() & (1 << 10)) == 0,
+            ExceptionClass::PcAlignment
+            | ExceptionClass::WatchpointLowerEl
+            | ExceptionClass::WatchpointSameEl => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Esr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.exception_class())?;
+
+        if let Some(status) = self.fault_status() {
+            write!(f, ", {}", status)?;
+        }
+        if self.is_write() {
+            write!(f, " on write")?;
+        } else if matches!(
+            self.exception_class(),
+            ExceptionClass::DataAbortLowerEl | ExceptionClass::DataAbortSameEl
+        ) {
+            write!(f, " on read")?;
+        }
+
+        match self.exception_class() {
+            ExceptionClass::Svc
+            | ExceptionClass::Hvc
+            | ExceptionClass::Smc
+            | ExceptionClass::Brk => write!(f, " #{:#x}", self.iss() & 0xffff),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ExceptionClass {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Self::Unknown,
+            0x01 => Self::WfiWfe,
+            0x07 => Self::SimdFloatingPoint,
+            0x09 => Self::PointerAuthentication,
+            0x0e => Self::IllegalExecutionState,
+            0x15 => Self::Svc,
+            0x16 => Self::Hvc,
+            0x17 => Self::Smc,
+            0x18 => Self::MsrMrs,
+            0x19 => Self::Sve,
+            0x20 => Self::InstructionAbortLowerEl,
+            0x21 => Self::InstructionAbortSameEl,
+            0x22 => Self::PcAlignment,
+            0x24 => Self::DataAbortLowerEl,
+            0x25 => Self::DataAbortSameEl,
+            0x26 => Self::SpAlignment,
+            0x2c => Self::FloatingPoint,
+            0x2f => Self::SError,
+            0x30 => Self::BreakpointLowerEl,
+            0x31 => Self::BreakpointSameEl,
+            0x32 => Self::SoftwareStepLowerEl,
+            0x33 => Self::SoftwareStepSameEl,
+            0x34 => Self::WatchpointLowerEl,
+            0x35 => Self::WatchpointSameEl,
+            0x3c => Self::Brk,
+            code => Self::Other(code),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown reason",
+            Self::WfiWfe => "Trapped WFI or WFE",
+            Self::SimdFloatingPoint => "Trapped SIMD or floating point access",
+            Self::PointerAuthentication => "Trapped pointer authentication instruction",
+            Self::IllegalExecutionState => "Illegal execution state",
+            Self::Svc => "SVC instruction",
+            Self::Hvc => "HVC instruction",
+            Self::Smc => "SMC instruction",
+            Self::MsrMrs => "Trapped MSR, MRS or system instruction",
+            Self::Sve => "Trapped SVE access",
+            Self::InstructionAbortLowerEl => "Instruction abort from a lower EL",
+            Self::InstructionAbortSameEl => "Instruction abort",
+            Self::PcAlignment => "PC alignment fault",
+            Self::DataAbortLowerEl => "Data abort from a lower EL",
+            Self::DataAbortSameEl => "Data abort",
+            Self::SpAlignment => "SP alignment fault",
+            Self::FloatingPoint => "Floating point exception",
+            Self::SError => "SError interrupt",
+            Self::BreakpointLowerEl => "Breakpoint from a lower EL",
+            Self::BreakpointSameEl => "Breakpoint",
+            Self::SoftwareStepLowerEl => "Software step from a lower EL",
+            Self::SoftwareStepSameEl => "Software step",
+            Self::WatchpointLowerEl => "Watchpoint from a lower EL",
+            Self::WatchpointSameEl => "Watchpoint",
+            Self::Brk => "BRK instruction",
+            Self::Other(_) => "Unrecognized exception class",
+        }
+    }
+}
+
+impl fmt::Display for ExceptionClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(code) => write!(f, "{} ({:#x})", self.as_str(), code),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl FaultStatus {
+    pub fn from_code(code: u8) -> Self {
+        let level = code & 0b11;
+
+        match code {
+            0b00_0000..=0b00_0011 => Self::AddressSize { level },
+            0b00_0100..=0b00_0111 => Self::Translation { level },
+            0b00_1000..=0b00_1011 => Self::AccessFlag { level },
+            0b00_1100..=0b00_1111 => Self::Permission { level },
+            0b01_0000 => Self::SynchronousExternal,
+            0b10_0001 => Self::Alignment,
+            0b11_0000 => Self::TlbConflict,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressSize { level } => write!(f, "address size fault at level {}", level),
+            Self::Translation { level } => write!(f, "translation fault at level {}", level),
+            Self::AccessFlag { level } => write!(f, "access flag fault at level {}", level),
+            Self::Permission { level } => write!(f, "permission fault at level {}", level),
+            Self::SynchronousExternal => write!(f, "synchronous external abort"),
+            Self::Alignment => write!(f, "alignment fault"),
+            Self::TlbConflict => write!(f, "TLB conflict abort"),
+            Self::Other(code) => write!(f, "fault status {:#x}", code),
+        }
+    }
+}
@@ -0,0 +1,618 @@
+// The Generic Interrupt Controller of QEMU's virt machine, either a GICv2 with a memory mapped CPU
+// interface or a GICv3 with per-CPU redistributors and a system register CPU interface. Every
+// interrupt is configured as Group 1 on a GICv3 and Group 0 on a GICv2, so that it is signaled as
+// an IRQ at EL1.
+//
+// This expects the GIC's security extensions to be disabled, which is QEMU's default. When QEMU
+// starts at EL3 they are enabled, and secure firmware would have to move interrupts to the
+// non-secure group before EL1 could use them.
+
+use core::{arch::asm, ptr};
+
+use crate::{aarch64::registers::mpidr_affinity, fdt::Fdt};
+
+pub type Error = &'static str;
+
+const GICV2_COMPATIBLES: &[&str] = &[
+    "arm,gic-400",
+    "arm,cortex-a15-gic",
+    "arm,cortex-a9-gic",
+    "arm,cortex-a7-gic",
+];
+const GICV3_COMPATIBLES: &[&str] = &["arm,gic-v3"];
+
+const GICD_CTLR: u64 = 0x000;
+const GICD_TYPER: u64 = 0x004;
+const GICD_IGROUPR: u64 = 0x080;
+const GICD_ISENABLER: u64 = 0x100;
+const GICD_ICENABLER: u64 = 0x180;
+const GICD_ICPENDR: u64 = 0x280;
+const GICD_IPRIORITYR: u64 = 0x400;
+const GICD_ITARGETSR: u64 = 0x800;
+const GICD_ICFGR: u64 = 0xc00;
+const GICD_IROUTER: u64 = 0x6000;
+
+const GICD_CTLR_ENABLE_GRP0: u32 = 1 << 0;
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+const GICD_CTLR_ARE: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+
+const GICC_CTLR: u64 = 0x00;
+const GICC_PMR: u64 = 0x04;
+const GICC_BPR: u64 = 0x08;
+const GICC_IAR: u64 = 0x0c;
+const GICC_EOIR: u64 = 0x10;
+
+const GICR_TYPER: u64 = 0x08;
+const GICR_WAKER: u64 = 0x14;
+/// The SGI and PPI registers follow the redistributor's control registers, at the same offsets
+/// as in the distributor.
+const GICR_SGI_BASE: u64 = 0x1_0000;
+const GICR_FRAME_SIZE: u64 = 0x2_0000;
+const GICR_VLPI_FRAME_SIZE: u64 = 0x4_0000;
+
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+const ICC_SRE_SRE: u64 = 1 << 0;
+
+/// The first shared peripheral interrupt. Lower IDs are SGIs and PPIs, which are private to
+/// each CPU.
+pub const SPI_START: u32 = 32;
+/// Acknowledging an interrupt returns an ID from here to 1023 when there is none pending.
+pub const SPURIOUS_START: u32 = 1020;
+/// The priority given to every interrupt by `init`. Lower values are higher priorities.
+pub const DEFAULT_PRIORITY: u8 = 0xa0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Level,
+    Edge,
+}
+
+/// An interrupt returned by `acknowledge`, which must be passed to `end_of_interrupt` once it has
+/// been handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Acknowledged {
+    pub intid: u32,
+    raw: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GicV2 {
+    distributor: u64,
+    cpu_interface: u64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GicV3 {
+    distributor: u64,
+    redistributors: u64,
+    redistributor_stride: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Gic {
+    V2(GicV2),
+    V3(GicV3),
+}
+
+/// The per-interrupt registers of the distributor, or of a GICv3 redistributor's SGI frame.
+#[derive(Copy, Clone)]
+struct InterruptRegisters(u64);
+
+fn read32(address: u64) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+unsafe fn write32(address: u64, value: u32) {
+    ptr::write_volatile(address as *mut u32, value);
+}
+
+impl InterruptRegisters {
+    fn bit(offset: u64, intid: u32) -> (u64, u32) {
+        (offset + (intid / 32) as u64 * 4, 1 << (intid % 32))
+    }
+
+    unsafe fn set_enabled(&self, intid: u32, enabled: bool) {
+        let offset = if enabled {
+            GICD_ISENABLER
+        } else {
+            GICD_ICENABLER
+        };
+        let (offset, bit) = Self::bit(offset, intid);
+        write32(self.0 + offset, bit);
+    }
+
+    unsafe fn clear_pending(&self, intid: u32) {
+        let (offset, bit) = Self::bit(GICD_ICPENDR, intid);
+        write32(self.0 + offset, bit);
+    }
+
+    unsafe fn set_group1(&self, intid: u32) {
+        let (offset, bit) = Self::bit(GICD_IGROUPR, intid);
+        write32(self.0 + offset, read32(self.0 + offset) | bit);
+    }
+
+    unsafe fn set_priority(&self, intid: u32, priority: u8) {
+        ptr::write_volatile(
+            (self.0 + GICD_IPRIORITYR + intid as u64) as *mut u8,
+            priority,
+        );
+    }
+
+    unsafe fn set_trigger(&self, intid: u32, trigger: Trigger) {
+        // Two bits per interrupt, where the upper one selects edge triggering
+        let offset = self.0 + GICD_ICFGR + (intid / 16) as u64 * 4;
+        let bit = 1 << ((intid % 16) * 2 + 1);
+        let value = match trigger {
+            Trigger::Level => read32(offset) & !bit,
+            Trigger::Edge => read32(offset) | bit,
+        };
+        write32(offset, value);
+    }
+}
+
+/// Returns the number of interrupt IDs that the distributor implements, including the SGIs and
+/// PPIs.
+fn interrupt_count(distributor: u64) -> u32 {
+    let lines = ((read32(distributor + GICD_TYPER) & 0x1f) + 1) * 32;
+    lines.min(SPURIOUS_START)
+}
+
+impl GicV2 {
+    pub fn new(distributor: u64, cpu_interface: u64) -> Self {
+        Self {
+            distributor,
+            cpu_interface,
+        }
+    }
+
+    pub fn distributor(&self) -> u64 {
+        self.distributor
+    }
+
+    pub fn cpu_interface(&self) -> u64 {
+        self.cpu_interface
+    }
+
+    fn registers(&self) -> InterruptRegisters {
+        InterruptRegisters(self.distributor)
+    }
+
+    pub fn interrupt_count(&self) -> u32 {
+        interrupt_count(self.distributor)
+    }
+
+    /// Disables every shared interrupt, gives them [`DEFAULT_PRIORITY`], and enables the
+    /// distributor. Only the boot CPU calls this; every CPU then calls [`Self::init_cpu`].
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the GIC.
+    pub unsafe fn init(&self) {
+        write32(self.distributor + GICD_CTLR, 0);
+
+        for intid in SPI_START..self.interrupt_count() {
+            self.registers().set_enabled(intid, false);
+            self.registers().clear_pending(intid);
+            self.registers().set_priority(intid, DEFAULT_PRIORITY);
+        }
+
+        write32(self.distributor + GICD_CTLR, GICD_CTLR_ENABLE_GRP0);
+    }
+
+    /// Disables the current CPU's PPIs, gives its SGIs and PPIs [`DEFAULT_PRIORITY`], and
+    /// enables its CPU interface with no priority masking.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the current CPU's interface.
+    pub unsafe fn init_cpu(&self) {
+        // SGIs may be permanently enabled, so only the PPIs are disabled
+        write32(self.distributor + GICD_ICENABLER, 0xffff_0000);
+        for intid in 0..SPI_START {
+            self.registers().set_priority(intid, DEFAULT_PRIORITY);
+        }
+
+        write32(self.cpu_interface + GICC_PMR, 0xff);
+        write32(self.cpu_interface + GICC_BPR, 0);
+        write32(self.cpu_interface + GICC_CTLR, 1);
+    }
+
+    /// # Safety
+    ///
+    /// Something must handle `intid` once IRQs are unmasked.
+    pub unsafe fn enable(&self, intid: u32) {
+        self.registers().set_enabled(intid, true);
+    }
+
+    pub fn disable(&self, intid: u32) {
+        unsafe { self.registers().set_enabled(intid, false) };
+    }
+
+    pub fn set_priority(&self, intid: u32, priority: u8) {
+        unsafe { self.registers().set_priority(intid, priority) };
+    }
+
+    /// Sets the trigger mode of a PPI or SPI. SGIs are always edge triggered.
+    pub fn set_trigger(&self, intid: u32, trigger: Trigger) {
+        unsafe { self.registers().set_trigger(intid, trigger) };
+    }
+
+    /// Sends the shared interrupt `intid` to every CPU interface in `cpu_mask`.
+    pub fn set_targets(&self, intid: u32, cpu_mask: u8) -> Result<(), Error> {
+        if intid < SPI_START {
+            return Err("Only shared interrupts have GICv2 targets");
+        }
+
+        unsafe {
+            ptr::write_volatile(
+                (self.distributor + GICD_ITARGETSR + intid as u64) as *mut u8,
+                cpu_mask,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the CPU interface mask of the current CPU.
+    pub fn current_cpu_mask(&self) -> u8 {
+        // The first target registers are banked and read as the current CPU's mask, except on
+        // uniprocessor GICs where they read as zero
+        match read32(self.distributor + GICD_ITARGETSR) as u8 {
+            0 => 1,
+            mask => mask,
+        }
+    }
+
+    /// Returns the highest priority pending interrupt and marks it active.
+    pub fn acknowledge(&self) -> Option<Acknowledged> {
+        let raw = read32(self.cpu_interface + GICC_IAR);
+        let intid = raw & 0x3ff;
+
+        (intid < SPURIOUS_START).then_some(Acknowledged { intid, raw })
+    }
+
+    pub fn end_of_interrupt(&self, interrupt: Acknowledged) {
+        unsafe { write32(self.cpu_interface + GICC_EOIR, interrupt.raw) };
+    }
+}
+
+impl GicV3 {
+    /// `redistributors` is the first redistributor region. `redistributor_stride` is only needed
+    /// when the frames are not packed, as given by the device tree's `redistributor-stride`.
+    pub fn new(distributor: u64, redistributors: u64, redistributor_stride: Option<u64>) -> Self {
+        Self {
+            distributor,
+            redistributors,
+            redistributor_stride,
+        }
+    }
+
+    pub fn distributor(&self) -> u64 {
+        self.distributor
+    }
+
+    pub fn interrupt_count(&self) -> u32 {
+        interrupt_count(self.distributor)
+    }
+
+    /// Returns the redistributor of the current CPU, by matching its affinity in GICR_TYPER.
+    pub fn redistributor(&self) -> Result<u64, Error> {
+        let mpidr = mpidr_affinity();
+        let affinity = ((mpidr >> 8) & 0xff00_0000) | (mpidr & 0xff_ffff);
+        let mut frame = self.redistributors;
+
+        loop {
+            let typer = unsafe { ptr::read_volatile((frame + GICR_TYPER) as *const u64) };
+            if typer >> 32 == affinity {
+                return Ok(frame);
+            }
+            if typer & GICR_TYPER_LAST != 0 {
+                return Err("No GICv3 redistributor for the current CPU");
+            }
+
+            frame += match self.redistributor_stride {
+                Some(stride) => stride,
+                None if typer & GICR_TYPER_VLPIS != 0 => GICR_VLPI_FRAME_SIZE,
+                None => GICR_FRAME_SIZE,
+            };
+        }
+    }
+
+    /// Returns the registers that configure `intid`, which are in the current CPU's
+    /// redistributor for SGIs and PPIs.
+    fn registers(&self, intid: u32) -> Result<InterruptRegisters, Error> {
+        if intid < SPI_START {
+            Ok(InterruptRegisters(self.redistributor()? + GICR_SGI_BASE))
+        } else {
+            Ok(InterruptRegisters(self.distributor))
+        }
+    }
+
+    fn wait_for_distributor(&self) {
+        while read32(self.distributor + GICD_CTLR) & GICD_CTLR_RWP != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Disables every shared interrupt, moves them to Group 1 with [`DEFAULT_PRIORITY`] routed
+    /// to the current CPU, and enables the distributor with affinity routing. Only the boot CPU
+    /// calls this; every CPU then calls [`Self::init_cpu`].
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the GIC.
+    pub unsafe fn init(&self) {
+        write32(self.distributor + GICD_CTLR, 0);
+        self.wait_for_distributor();
+
+        let registers = InterruptRegisters(self.distributor);
+        for intid in SPI_START..self.interrupt_count() {
+            registers.set_enabled(intid, false);
+            registers.clear_pending(intid);
+            registers.set_group1(intid);
+            registers.set_priority(intid, DEFAULT_PRIORITY);
+            self.write_router(intid, mpidr_affinity());
+        }
+
+        // Affinity routing must be enabled before the groups
+        write32(self.distributor + GICD_CTLR, GICD_CTLR_ARE);
+        self.wait_for_distributor();
+        write32(
+            self.distributor + GICD_CTLR,
+            GICD_CTLR_ARE | GICD_CTLR_ENABLE_GRP1 | GICD_CTLR_ENABLE_GRP0,
+        );
+        self.wait_for_distributor();
+    }
+
+    /// Wakes the current CPU's redistributor, moves its SGIs and PPIs to Group 1 with
+    /// [`DEFAULT_PRIORITY`], and enables the system register CPU interface with no priority
+    /// masking.
+    ///
+    /// # Safety
+    ///
+    /// Must run at EL1. Nothing else may be using the current CPU's redistributor.
+    pub unsafe fn init_cpu(&self) -> Result<(), Error> {
+        let redistributor = self.redistributor()?;
+
+        let waker = redistributor + GICR_WAKER;
+        write32(waker, read32(waker) & !GICR_WAKER_PROCESSOR_SLEEP);
+        while read32(waker) & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+            core::hint::spin_loop();
+        }
+
+        let registers = InterruptRegisters(redistributor + GICR_SGI_BASE);
+        write32(registers.0 + GICD_IGROUPR, u32::MAX);
+        write32(registers.0 + GICD_ICENABLER, 0xffff_0000);
+        for intid in 0..SPI_START {
+            registers.set_priority(intid, DEFAULT_PRIORITY);
+        }
+
+        let mut sre: u64;
+        asm!("mrs {}, icc_sre_el1", out(reg) sre, options(nomem, nostack, preserves_flags));
+        asm!("msr icc_sre_el1, {}", "isb", in(reg) sre | ICC_SRE_SRE, options(nostack));
+        asm!("mrs {}, icc_sre_el1", out(reg) sre, options(nomem, nostack, preserves_flags));
+        if sre & ICC_SRE_SRE == 0 {
+            return Err("GICv3 system register interface is disabled by a higher exception level");
+        }
+
+        asm!(
+            "msr icc_pmr_el1, {pmr}",
+            "msr icc_bpr1_el1, xzr",
+            "msr icc_ctlr_el1, xzr",
+            "msr icc_igrpen1_el1, {enable}",
+            "isb",
+            pmr = in(reg) 0xffu64,
+            enable = in(reg) 1u64,
+            options(nostack),
+        );
+
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// Something must handle `intid` once IRQs are unmasked.
+    pub unsafe fn enable(&self, intid: u32) -> Result<(), Error> {
+        self.registers(intid)?.set_enabled(intid, true);
+        Ok(())
+    }
+
+    pub fn disable(&self, intid: u32) -> Result<(), Error> {
+        unsafe { self.registers(intid)?.set_enabled(intid, false) };
+        Ok(())
+    }
+
+    pub fn set_priority(&self, intid: u32, priority: u8) -> Result<(), Error> {
+        unsafe { self.registers(intid)?.set_priority(intid, priority) };
+        Ok(())
+    }
+
+    /// Sets the trigger mode of a PPI or SPI. SGIs are always edge triggered.
+    pub fn set_trigger(&self, intid: u32, trigger: Trigger) -> Result<(), Error> {
+        unsafe { self.registers(intid)?.set_trigger(intid, trigger) };
+        Ok(())
+    }
+
+    unsafe fn write_router(&self, intid: u32, affinity: u64) {
+        let router = self.distributor + GICD_IROUTER + intid as u64 * 8;
+        ptr::write_volatile(router as *mut u64, affinity);
+    }
+
+    /// Sends the shared interrupt `intid` to the CPU with MPIDR affinity `affinity`.
+    pub fn set_affinity(&self, intid: u32, affinity: u64) -> Result<(), Error> {
+        if intid < SPI_START {
+            return Err("Only shared interrupts have GICv3 affinity routing");
+        }
+
+        unsafe { self.write_router(intid, affinity) };
+        Ok(())
+    }
+
+    /// Returns the highest priority pending Group 1 interrupt and marks it active.
+    pub fn acknowledge(&self) -> Option<Acknowledged> {
+        let raw: u64;
+        unsafe {
+            asm!("mrs {}, icc_iar1_el1", out(reg) raw, options(nomem, nostack, preserves_flags));
+        }
+        let intid = raw as u32 & 0xff_ffff;
+
+        // IDs from 1020 to 1023 are special, but LPIs start above them
+        match intid {
+            SPURIOUS_START..=1023 => None,
+            intid => Some(Acknowledged { intid, raw: intid }),
+        }
+    }
+
+    pub fn end_of_interrupt(&self, interrupt: Acknowledged) {
+        unsafe {
+            asm!("msr icc_eoir1_el1, {}", "isb", in(reg) interrupt.raw as u64, options(nostack));
+        }
+    }
+}
+
+impl Gic {
+    /// Finds the GIC in the device tree. Its first `reg` range is the distributor, and the second
+    /// is the GICv2 CPU interface or the first GICv3 redistributor region.
+    pub fn from_device_tree(fdt: &Fdt) -> Option<Self> {
+        if let Some(node) = fdt
+            .find_any_compatible(GICV3_COMPATIBLES)
+            .filter(|node| node.is_enabled())
+        {
+            let mut reg = node.reg()?;
+            let distributor = reg.next()?.address;
+            let redistributors = reg.next()?.address;
+            let stride = node
+                .property("redistributor-stride")
+                .and_then(|property| property.as_u64());
+
+            return Some(Self::V3(GicV3::new(distributor, redistributors, stride)));
+        }
+
+        let node = fdt
+            .find_any_compatible(GICV2_COMPATIBLES)
+            .filter(|node| node.is_enabled())?;
+        let mut reg = node.reg()?;
+        let distributor = reg.next()?.address;
+        let cpu_interface = reg.next()?.address;
+
+        Some(Self::V2(GicV2::new(distributor, cpu_interface)))
+    }
+
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V2(_) => 2,
+            Self::V3(_) => 3,
+        }
+    }
+
+    pub fn distributor(&self) -> u64 {
+        match self {
+            Self::V2(gic) => gic.distributor(),
+            Self::V3(gic) => gic.distributor(),
+        }
+    }
+
+    pub fn interrupt_count(&self) -> u32 {
+        match self {
+            Self::V2(gic) => gic.interrupt_count(),
+            Self::V3(gic) => gic.interrupt_count(),
+        }
+    }
+
+    /// Initializes the distributor. Only the boot CPU calls this.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the GIC.
+    pub unsafe fn init(&self) {
+        match self {
+            Self::V2(gic) => gic.init(),
+            Self::V3(gic) => gic.init(),
+        }
+    }
+
+    /// Initializes the current CPU's interface. Every CPU calls this after [`Self::init`].
+    ///
+    /// # Safety
+    ///
+    /// Must run at EL1. Nothing else may be using the current CPU's interface.
+    pub unsafe fn init_cpu(&self) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => {
+                gic.init_cpu();
+                Ok(())
+            }
+            Self::V3(gic) => gic.init_cpu(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Something must handle `intid` once IRQs are unmasked.
+    pub unsafe fn enable(&self, intid: u32) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => {
+                gic.enable(intid);
+                Ok(())
+            }
+            Self::V3(gic) => gic.enable(intid),
+        }
+    }
+
+    pub fn disable(&self, intid: u32) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => {
+                gic.disable(intid);
+                Ok(())
+            }
+            Self::V3(gic) => gic.disable(intid),
+        }
+    }
+
+    pub fn set_priority(&self, intid: u32, priority: u8) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => {
+                gic.set_priority(intid, priority);
+                Ok(())
+            }
+            Self::V3(gic) => gic.set_priority(intid, priority),
+        }
+    }
+
+    pub fn set_trigger(&self, intid: u32, trigger: Trigger) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => {
+                gic.set_trigger(intid, trigger);
+                Ok(())
+            }
+            Self::V3(gic) => gic.set_trigger(intid, trigger),
+        }
+    }
+
+    /// Sends the shared interrupt `intid` to the current CPU.
+    pub fn set_target_current_cpu(&self, intid: u32) -> Result<(), Error> {
+        match self {
+            Self::V2(gic) => gic.set_targets(intid, gic.current_cpu_mask()),
+            Self::V3(gic) => gic.set_affinity(intid, mpidr_affinity()),
+        }
+    }
+
+    pub fn acknowledge(&self) -> Option<Acknowledged> {
+        match self {
+            Self::V2(gic) => gic.acknowledge(),
+            Self::V3(gic) => gic.acknowledge(),
+        }
+    }
+
+    pub fn end_of_interrupt(&self, interrupt: Acknowledged) {
+        match self {
+            Self::V2(gic) => gic.end_of_interrupt(interrupt),
+            Self::V3(gic) => gic.end_of_interrupt(interrupt),
+        }
+    }
+}
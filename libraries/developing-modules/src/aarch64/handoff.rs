@@ -0,0 +1,32 @@
+use core::arch::asm;
+
+use crate::boot_info::BootInfo;
+
+/// Switches TTBR1 to the kernel's translation table and the stack to `stack_top`, then jumps to
+/// the entry point with `boot_info` in x0. Interrupts are masked before anything is switched.
+///
+/// # Safety
+///
+/// The MMU must already be on, with TTBR0 identity mapping this function. `ttbr1` must be the
+/// physical address of a level 0 table that maps the kernel entry point, the whole stack, and the
+/// boot info along with everything it points to.
+pub unsafe fn handoff(entry: u64, stack_top: u64, ttbr1: u64, boot_info: *const BootInfo) -> ! {
+    asm!(
+        "msr daifset, #0xf",
+        "dsb ishst",
+        "msr ttbr1_el1, x11",
+        "isb",
+        "tlbi vmalle1",
+        "dsb ish",
+        "isb",
+        "mov sp, x10",
+        "mov x29, xzr",
+        "mov x30, xzr",
+        "br x9",
+        in("x9") entry,
+        in("x10") stack_top & !0xf,
+        in("x11") ttbr1,
+        in("x0") boot_info,
+        options(noreturn),
+    );
+}
@@ -0,0 +1,391 @@
+use core::{
+    arch::asm,
+    fmt,
+    ops::{Index, IndexMut},
+};
+
+use crate::{
+    aarch64::registers::{IdAa64Mmfr0, Mair, Sctlr, Tcr, Ttbr0, Ttbr1},
+    memory::{Error, FrameAllocator, PageMap, PageSize},
+};
+
+pub const ENTRY_COUNT: usize = 512;
+
+/// Number of virtual address bits translated by each of TTBR0 and TTBR1.
+pub const VIRTUAL_ADDRESS_BITS: u64 = 48;
+
+/// Start of the address range translated by TTBR1.
+pub const HIGHER_HALF_START: u64 = 0xffff_0000_0000_0000;
+
+/// Memory attributes programmed into MAIR_EL1 by [`configure`]. The value of each variant is its
+/// index in MAIR_EL1.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryAttribute {
+    /// Device-nGnRnE memory for MMIO.
+    Device = 0,
+    /// Normal inner and outer write-back cacheable memory.
+    Normal = 1,
+    /// Normal inner and outer non-cacheable memory.
+    NormalNonCacheable = 2,
+}
+
+const MAIR_DEVICE_NGNRNE: u64 = 0x00;
+const MAIR_NORMAL_WRITE_BACK: u64 = 0xff;
+const MAIR_NORMAL_NON_CACHEABLE: u64 = 0x44;
+
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Descriptor(u64);
+
+#[repr(C, align(4096))]
+pub struct TranslationTable {
+    entries: [Descriptor; ENTRY_COUNT],
+}
+
+/// Builds and walks translation tables for a 4 KiB granule and 48-bit virtual addresses.
+///
+/// Tables are accessed through `phys_offset + physical address`, so an identity mapped
+/// bootloader uses an offset of 0.
+#[derive(Debug)]
+pub struct TranslationTableMapper {
+    root: u64,
+    phys_offset: u64,
+}
+
+impl Descriptor {
+    pub const VALID: u64 = 1 << 0;
+    /// Marks a table descriptor at levels 0-2 or a page descriptor at level 3.
+    pub const TABLE_OR_PAGE: u64 = 1 << 1;
+    pub const ATTR_INDEX_SHIFT: u64 = 2;
+    pub const NON_SECURE: u64 = 1 << 5;
+    pub const EL0_ACCESS: u64 = 1 << 6;
+    pub const READ_ONLY: u64 = 1 << 7;
+    pub const OUTER_SHAREABLE: u64 = 0b10 << 8;
+    pub const INNER_SHAREABLE: u64 = 0b11 << 8;
+    pub const ACCESS_FLAG: u64 = 1 << 10;
+    pub const NOT_GLOBAL: u64 = 1 << 11;
+    pub const PRIVILEGED_EXECUTE_NEVER: u64 = 1 << 53;
+    pub const UNPRIVILEGED_EXECUTE_NEVER: u64 = 1 << 54;
+
+    const ADDRESS_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn new(address: u64, flags: u64) -> Self {
+        Self((address & Self::ADDRESS_MASK) | (flags & !Self::ADDRESS_MASK))
+    }
+
+    pub fn address(&self) -> u64 {
+        self.0 & Self::ADDRESS_MASK
+    }
+
+    pub fn flags(&self) -> u64 {
+        self.0 & !Self::ADDRESS_MASK
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.contains(Self::VALID)
+    }
+
+    /// Returns true if this descriptor maps memory directly at `level` rather than pointing to
+    /// another table.
+    fn is_leaf(&self, level: usize) -> bool {
+        level == 1 || !self.contains(Self::TABLE_OR_PAGE)
+    }
+
+    pub fn set(&mut self, address: u64, flags: u64) {
+        *self = Self::new(address, flags);
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl TranslationTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: [Descriptor::empty(); ENTRY_COUNT],
+        }
+    }
+
+    pub fn zero(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.clear();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Descriptor> {
+        self.entries.iter()
+    }
+}
+
+impl Default for TranslationTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for TranslationTable {
+    type Output = Descriptor;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.entries[index]
+    }
+}
+
+impl IndexMut<usize> for TranslationTable {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.entries[index]
+    }
+}
+
+/// Returns the index into the table at `level` (1 to 4) that `virt` uses.
+fn table_index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * (level - 1))) & 0x1ff) as usize
+}
+
+fn is_valid_address(virt: u64) -> bool {
+    let upper = virt >> VIRTUAL_ADDRESS_BITS;
+    upper == 0 || upper == 0xffff
+}
+
+impl TranslationTableMapper {
+    /// Allocates and zeroes a new level 0 translation table.
+    pub fn new(allocator: &mut impl FrameAllocator, phys_offset: u64) -> Result<Self, Error> {
+        let root = allocator
+            .allocate_frame()
+            .ok_or("Failed to allocate a frame for the level 0 table")?;
+
+        let mapper = unsafe { Self::from_root(root, phys_offset) };
+        unsafe { mapper.table_at(root).zero() };
+
+        Ok(mapper)
+    }
+
+    /// # Safety
+    ///
+    /// `root` must be the physical address of a valid level 0 table, and every table it
+    /// references must be accessible at `phys_offset + physical address`.
+    pub unsafe fn from_root(root: u64, phys_offset: u64) -> Self {
+        Self { root, phys_offset }
+    }
+
+    /// Returns the physical address of the level 0 table, which is written to TTBR0 or TTBR1.
+    pub fn root_address(&self) -> u64 {
+        self.root
+    }
+
+    unsafe fn table_at<'a>(&self, address: u64) -> &'a mut TranslationTable {
+        &mut *((address + self.phys_offset) as *mut TranslationTable)
+    }
+}
+
+impl PageMap for TranslationTableMapper {
+    type Flags = (MemoryAttribute, u64);
+
+    fn higher_half_start(&self) -> u64 {
+        HIGHER_HALF_START
+    }
+
+    /// `flags` are the memory attribute, and the access permission and execute never bits; the
+    /// valid, access, and shareability bits are filled in from the attribute.
+    unsafe fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: PageSize,
+        (attribute, flags): Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        if !is_valid_address(virt) {
+            return Err("Virtual address is outside of the 48-bit address space");
+        }
+        if !virt.is_multiple_of(size.bytes()) || !phys.is_multiple_of(size.bytes()) {
+            return Err("Address is not aligned to the page size");
+        }
+
+        let mut table = self.table_at(self.root);
+        for level in (size.level() + 1..=4).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                let frame = allocator
+                    .allocate_frame()
+                    .ok_or("Failed to allocate a frame for a translation table")?;
+                self.table_at(frame).zero();
+                entry.set(frame, Descriptor::VALID | Descriptor::TABLE_OR_PAGE);
+            } else if entry.is_leaf(level) {
+                return Err("Virtual address is already mapped by a larger block");
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        let entry = &mut table[table_index(virt, size.level())];
+        if entry.is_valid() {
+            return Err("Virtual address is already mapped");
+        }
+
+        let mut descriptor_flags = flags
+            | Descriptor::VALID
+            | Descriptor::ACCESS_FLAG
+            | ((attribute as u64) << Descriptor::ATTR_INDEX_SHIFT);
+        if size == PageSize::Size4KiB {
+            descriptor_flags |= Descriptor::TABLE_OR_PAGE;
+        }
+        descriptor_flags |= match attribute {
+            MemoryAttribute::Device => {
+                Descriptor::PRIVILEGED_EXECUTE_NEVER | Descriptor::UNPRIVILEGED_EXECUTE_NEVER
+            }
+            MemoryAttribute::Normal | MemoryAttribute::NormalNonCacheable => {
+                Descriptor::INNER_SHAREABLE
+            }
+        };
+        entry.set(phys, descriptor_flags);
+
+        Ok(())
+    }
+
+    unsafe fn unmap(&mut self, virt: u64) -> Result<(u64, PageSize), Error> {
+        let mut table = self.table_at(self.root);
+        for level in (1..=4).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                return Err("Virtual address is not mapped");
+            }
+
+            if entry.is_leaf(level) {
+                let phys = entry.address();
+                entry.clear();
+                flush(virt);
+                return Ok((phys, PageSize::from_level(level)));
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        unreachable!()
+    }
+
+    fn translate(&self, virt: u64) -> Option<u64> {
+        let mut table = unsafe { self.table_at(self.root) };
+        for level in (1..=4).rev() {
+            let entry = &table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                return None;
+            }
+
+            if entry.is_leaf(level) {
+                let size = PageSize::from_level(level).bytes();
+                return Some(entry.address() + (virt & (size - 1)));
+            }
+
+            table = unsafe { self.table_at(entry.address()) };
+        }
+
+        None
+    }
+}
+
+/// Invalidates the EL1 TLB entries for a single page.
+pub fn flush(virt: u64) {
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vaae1is, {}",
+            "dsb ish",
+            "isb",
+            in(reg) (virt >> 12) & 0xfff_ffff_ffff,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Invalidates all EL1 TLB entries.
+pub fn flush_all() {
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vmalle1is",
+            "dsb ish",
+            "isb",
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Programs MAIR_EL1, TCR_EL1, TTBR0_EL1 and TTBR1_EL1 for 4 KiB granules and 48-bit virtual
+/// addresses in both halves. `ttbr0` and `ttbr1` are physical addresses of level 0 tables.
+///
+/// # Safety
+///
+/// If the MMU is already on, the new tables must map the running code and its stack.
+pub unsafe fn configure(ttbr0: u64, ttbr1: u64) -> Result<(), Error> {
+    let mmfr0 = IdAa64Mmfr0::read();
+    if !mmfr0.supports_4kib_granule() {
+        return Err("CPU does not support the 4 KiB translation granule");
+    }
+
+    Mair(
+        (MAIR_DEVICE_NGNRNE << (MemoryAttribute::Device as u64 * 8))
+            | (MAIR_NORMAL_WRITE_BACK << (MemoryAttribute::Normal as u64 * 8))
+            | (MAIR_NORMAL_NON_CACHEABLE << (MemoryAttribute::NormalNonCacheable as u64 * 8)),
+    )
+    .write();
+
+    let region_size_offset = 64 - VIRTUAL_ADDRESS_BITS;
+    Tcr((region_size_offset << Tcr::T0SZ_SHIFT)
+        | Tcr::IRGN0_WRITE_BACK
+        | Tcr::ORGN0_WRITE_BACK
+        | Tcr::SH0_INNER
+        | Tcr::TG0_4KIB
+        | (region_size_offset << Tcr::T1SZ_SHIFT)
+        | Tcr::IRGN1_WRITE_BACK
+        | Tcr::ORGN1_WRITE_BACK
+        | Tcr::SH1_INNER
+        | Tcr::TG1_4KIB
+        | (mmfr0.physical_address_range() << Tcr::IPS_SHIFT))
+    .write();
+
+    Ttbr0(ttbr0).write();
+    Ttbr1(ttbr1).write();
+    asm!("isb", options(nostack, preserves_flags));
+
+    Ok(())
+}
+
+/// Turns on the MMU along with the data and instruction caches.
+///
+/// # Safety
+///
+/// [`configure`] must have been called with tables that identity map the running code, its
+/// stack, and any memory used afterwards.
+pub unsafe fn enable_mmu() {
+    flush_all();
+
+    let sctlr = Sctlr::read();
+    Sctlr(sctlr.0 | Sctlr::MMU_ENABLE | Sctlr::DATA_CACHE | Sctlr::INSTRUCTION_CACHE).write();
+}
+
+pub fn is_mmu_enabled() -> bool {
+    Sctlr::read().contains(Sctlr::MMU_ENABLE)
+}
+
+impl fmt::Debug for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Descriptor")
+            .field("address", &format_args!("{:#x}", self.address()))
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .finish()
+    }
+}
@@ -1 +1,8 @@
-
+pub mod exceptions;
+pub mod gic;
+pub mod handoff;
+pub mod mmu;
+pub mod psci;
+pub mod registers;
+pub mod semihosting;
+pub mod smp;
@@ -0,0 +1,233 @@
+// Calls into the Power State Coordination Interface, which firmware or a hypervisor implements
+// at a higher exception level. The device tree's `/psci` node tells whether it is reached with
+// `hvc` or `smc`; on QEMU's virt machine without EL2 or EL3 it is QEMU itself, through `hvc`.
+
+use core::{arch::asm, fmt};
+
+use crate::fdt::Fdt;
+
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const CPU_ON: u32 = 0xc400_0003;
+pub const AFFINITY_INFO: u32 = 0xc400_0004;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+pub const PSCI_FEATURES: u32 = 0x8400_000a;
+
+const COMPATIBLES: &[&str] = &["arm,psci-1.0", "arm,psci-0.2", "arm,psci"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PsciConduit {
+    Hvc,
+    Smc,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PsciError {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    Unknown(i32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AffinityState {
+    On,
+    Off,
+    OnPending,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Psci {
+    conduit: PsciConduit,
+}
+
+impl PsciError {
+    fn from_code(code: i32) -> Self {
+        match code {
+            -1 => Self::NotSupported,
+            -2 => Self::InvalidParameters,
+            -3 => Self::Denied,
+            -4 => Self::AlreadyOn,
+            -5 => Self::OnPending,
+            -6 => Self::InternalFailure,
+            -7 => Self::NotPresent,
+            -8 => Self::Disabled,
+            -9 => Self::InvalidAddress,
+            code => Self::Unknown(code),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotSupported => "PSCI function is not supported",
+            Self::InvalidParameters => "PSCI function has invalid parameters",
+            Self::Denied => "PSCI function was denied",
+            Self::AlreadyOn => "PSCI CPU is already on",
+            Self::OnPending => "PSCI CPU is already being turned on",
+            Self::InternalFailure => "PSCI internal failure",
+            Self::NotPresent => "PSCI CPU is not present",
+            Self::Disabled => "PSCI CPU is disabled",
+            Self::InvalidAddress => "PSCI function has an invalid address",
+            Self::Unknown(_) => "PSCI function returned an unknown error",
+        }
+    }
+}
+
+impl fmt::Display for PsciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(code) => write!(f, "{} ({})", self.as_str(), code),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl From<PsciError> for &'static str {
+    fn from(error: PsciError) -> Self {
+        error.as_str()
+    }
+}
+
+impl Psci {
+    pub fn new(conduit: PsciConduit) -> Self {
+        Self { conduit }
+    }
+
+    /// Reads the conduit from the `method` property of the PSCI node.
+    pub fn from_device_tree(fdt: &Fdt) -> Option<Self> {
+        let node = fdt.find_any_compatible(COMPATIBLES)?;
+
+        match node.property("method")?.as_str()? {
+            "hvc" => Some(Self::new(PsciConduit::Hvc)),
+            "smc" => Some(Self::new(PsciConduit::Smc)),
+            _ => None,
+        }
+    }
+
+    pub fn conduit(&self) -> PsciConduit {
+        self.conduit
+    }
+
+    /// Calls a PSCI function using the SMC calling convention.
+    ///
+    /// # Safety
+    ///
+    /// The call must be valid for the current state of the system; for example, turning a CPU on
+    /// at an invalid entry point is undefined behavior.
+    pub unsafe fn call(&self, function: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+        let result: i64;
+
+        match self.conduit {
+            PsciConduit::Hvc => asm!(
+                "hvc #0",
+                inlateout("x0") function as u64 => result,
+                inlateout("x1") arg0 => _,
+                inlateout("x2") arg1 => _,
+                inlateout("x3") arg2 => _,
+                // SMCCC 1.0 lets firmware corrupt x4-x17, which are all caller saved in the C ABI
+                clobber_abi("C"),
+                options(nostack),
+            ),
+            PsciConduit::Smc => asm!(
+                "smc #0",
+                inlateout("x0") function as u64 => result,
+                inlateout("x1") arg0 => _,
+                inlateout("x2") arg1 => _,
+                inlateout("x3") arg2 => _,
+                clobber_abi("C"),
+                options(nostack),
+            ),
+        }
+
+        result
+    }
+
+    fn call_result(
+        &self,
+        function: u32,
+        arg0: u64,
+        arg1: u64,
+        arg2: u64,
+    ) -> Result<u64, PsciError> {
+        // Every function used here returns a 32 bit value, even the SMC64 ones
+        match unsafe { self.call(function, arg0, arg1, arg2) } as i32 {
+            result if result >= 0 => Ok(result as u64),
+            result => Err(PsciError::from_code(result)),
+        }
+    }
+
+    /// Returns the implemented PSCI version as (major, minor).
+    pub fn version(&self) -> (u16, u16) {
+        let version = unsafe { self.call(PSCI_VERSION, 0, 0, 0) } as u32;
+        ((version >> 16) as u16, version as u16)
+    }
+
+    /// Returns true if the firmware implements `function`. Needs PSCI 1.0.
+    pub fn has_function(&self, function: u32) -> bool {
+        self.call_result(PSCI_FEATURES, function as u64, 0, 0)
+            .is_ok()
+    }
+
+    /// Powers on the CPU with the affinity value `mpidr` at the physical address `entry_point`,
+    /// with `context_id` in x0 and the MMU and caches off.
+    ///
+    /// # Safety
+    ///
+    /// `entry_point` must be the physical address of code that can run with the MMU disabled and
+    /// sets up its own stack.
+    pub unsafe fn cpu_on(
+        &self,
+        mpidr: u64,
+        entry_point: u64,
+        context_id: u64,
+    ) -> Result<(), PsciError> {
+        self.call_result(CPU_ON, mpidr, entry_point, context_id)
+            .map(|_| ())
+    }
+
+    /// Powers off the calling CPU, returning only if it could not be powered off.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may depend on the calling CPU making progress after this call.
+    pub unsafe fn cpu_off(&self) -> PsciError {
+        match self.call_result(CPU_OFF, 0, 0, 0) {
+            Ok(_) => PsciError::InternalFailure,
+            Err(err) => err,
+        }
+    }
+
+    pub fn affinity_info(&self, mpidr: u64) -> Result<AffinityState, PsciError> {
+        match self.call_result(AFFINITY_INFO, mpidr, 0, 0)? {
+            0 => Ok(AffinityState::On),
+            1 => Ok(AffinityState::Off),
+            2 => Ok(AffinityState::OnPending),
+            state => Err(PsciError::Unknown(state as i32)),
+        }
+    }
+
+    /// Powers off the system.
+    pub fn system_off(&self) -> ! {
+        unsafe { self.call(SYSTEM_OFF, 0, 0, 0) };
+
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Resets the system.
+    pub fn system_reset(&self) -> ! {
+        unsafe { self.call(SYSTEM_RESET, 0, 0, 0) };
+
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
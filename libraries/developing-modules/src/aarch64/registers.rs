@@ -0,0 +1,258 @@
+use core::arch::asm;
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mair(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tcr(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ttbr0(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ttbr1(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sctlr(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IdAa64Mmfr0(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vbar(pub u64);
+
+impl Mair {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, mair_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// Changing memory attributes that are used by active translation tables changes how that
+    /// memory is cached.
+    pub unsafe fn write(self) {
+        asm!("msr mair_el1, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+
+    /// Returns the attribute encoding stored at `index` (0 to 7).
+    pub fn attribute(&self, index: usize) -> u8 {
+        (self.0 >> (index * 8)) as u8
+    }
+}
+
+impl Tcr {
+    pub const T0SZ_SHIFT: u64 = 0;
+    pub const EPD0: u64 = 1 << 7;
+    pub const IRGN0_WRITE_BACK: u64 = 0b01 << 8;
+    pub const ORGN0_WRITE_BACK: u64 = 0b01 << 10;
+    pub const SH0_INNER: u64 = 0b11 << 12;
+    pub const TG0_4KIB: u64 = 0b00 << 14;
+    pub const T1SZ_SHIFT: u64 = 16;
+    pub const EPD1: u64 = 1 << 23;
+    pub const IRGN1_WRITE_BACK: u64 = 0b01 << 24;
+    pub const ORGN1_WRITE_BACK: u64 = 0b01 << 26;
+    pub const SH1_INNER: u64 = 0b11 << 28;
+    pub const TG1_4KIB: u64 = 0b10 << 30;
+    pub const IPS_SHIFT: u64 = 32;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, tcr_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The new translation control settings must be compatible with the active translation
+    /// tables, or the MMU must be off.
+    pub unsafe fn write(self) {
+        asm!("msr tcr_el1, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+}
+
+impl Ttbr0 {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, ttbr0_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The new translation table must map everything in the lower half that is accessed after
+    /// the switch.
+    pub unsafe fn write(self) {
+        asm!("msr ttbr0_el1, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+}
+
+impl Ttbr1 {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, ttbr1_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The new translation table must map everything in the higher half that is accessed after
+    /// the switch.
+    pub unsafe fn write(self) {
+        asm!("msr ttbr1_el1, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+}
+
+impl Sctlr {
+    pub const MMU_ENABLE: u64 = 1 << 0;
+    pub const ALIGNMENT_CHECK: u64 = 1 << 1;
+    pub const DATA_CACHE: u64 = 1 << 2;
+    pub const STACK_ALIGNMENT_CHECK: u64 = 1 << 3;
+    pub const INSTRUCTION_CACHE: u64 = 1 << 12;
+    pub const WRITE_EXECUTE_NEVER: u64 = 1 << 19;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, sctlr_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// Turning the MMU or caches on or off changes how every following memory access behaves.
+    pub unsafe fn write(self) {
+        asm!(
+            "msr sctlr_el1, {}",
+            "isb",
+            in(reg) self.0,
+            options(nostack, preserves_flags),
+        );
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl IdAa64Mmfr0 {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!(
+                "mrs {}, id_aa64mmfr0_el1",
+                out(reg) value,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+
+        Self(value)
+    }
+
+    /// Returns the supported physical address range, encoded the same way as TCR_EL1.IPS.
+    pub fn physical_address_range(&self) -> u64 {
+        self.0 & 0xf
+    }
+
+    pub fn supports_4kib_granule(&self) -> bool {
+        ((self.0 >> 28) & 0xf) != 0xf
+    }
+}
+
+impl Vbar {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mrs {}, vbar_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The address must point to a 2 KiB aligned exception vector table that stays mapped for as
+    /// long as it is installed.
+    pub unsafe fn write(self) {
+        asm!(
+            "msr vbar_el1, {}",
+            "isb",
+            in(reg) self.0,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Reads the syndrome register, which describes the last exception taken to EL1.
+pub fn esr_el1() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {}, esr_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the faulting virtual address of the last abort taken to EL1.
+pub fn far_el1() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {}, far_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Returns the exception level that the CPU is running in, from 0 to 3.
+pub fn current_el() -> u8 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {}, CurrentEL", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    ((value >> 2) & 0b11) as u8
+}
+
+/// Returns the affinity fields of MPIDR_EL1, which is how PSCI and the device tree identify
+/// CPUs.
+pub fn mpidr_affinity() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {}, mpidr_el1", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value & 0xff_00ff_ffff
+}
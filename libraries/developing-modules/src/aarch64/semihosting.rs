@@ -0,0 +1,94 @@
+// ARM semihosting, which lets code running under a debugger or QEMU ask the host to do I/O.
+// QEMU only handles these calls when it is started with `-semihosting`; otherwise the `hlt`
+// instruction raises an exception.
+
+use core::{arch::asm, ffi::CStr, fmt};
+
+pub const SYS_WRITEC: u64 = 0x03;
+pub const SYS_WRITE0: u64 = 0x04;
+pub const SYS_EXIT: u64 = 0x18;
+pub const SYS_EXIT_EXTENDED: u64 = 0x20;
+
+/// The reason given to `SYS_EXIT` when the application finished normally.
+pub const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x2_0026;
+
+/// Performs a semihosting call.
+///
+/// # Safety
+///
+/// The host must handle semihosting calls, and `parameter` must be valid for `operation`.
+pub unsafe fn call(operation: u64, parameter: u64) -> u64 {
+    let result: u64;
+
+    asm!(
+        "hlt #0xf000",
+        inlateout("x0") operation => result,
+        in("x1") parameter,
+        options(nostack),
+    );
+
+    result
+}
+
+/// Writes a null terminated string to the host's debug console.
+///
+/// # Safety
+///
+/// The host must handle semihosting calls.
+pub unsafe fn write0(s: &CStr) {
+    call(SYS_WRITE0, s.as_ptr() as u64);
+}
+
+/// Writes `s` to the host's debug console. It is copied into null terminated chunks for
+/// `SYS_WRITE0`, so it is cut off at the first null byte.
+///
+/// # Safety
+///
+/// The host must handle semihosting calls.
+pub unsafe fn write_str(s: &str) {
+    let mut buffer = [0u8; 64];
+
+    for chunk in s.as_bytes().chunks(buffer.len() - 1) {
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        buffer[chunk.len()] = 0;
+
+        match CStr::from_bytes_until_nul(&buffer) {
+            Ok(s) if s.to_bytes().len() == chunk.len() => write0(s),
+            Ok(s) => return write0(s),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Exits QEMU with `status` as its exit status.
+///
+/// # Safety
+///
+/// The host must handle semihosting calls.
+pub unsafe fn exit(status: u32) -> ! {
+    let parameters = [ADP_STOPPED_APPLICATION_EXIT, status as u64];
+    call(SYS_EXIT_EXTENDED, parameters.as_ptr() as u64);
+
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Writes to the host's debug console.
+pub struct SemihostingConsole(());
+
+impl SemihostingConsole {
+    /// # Safety
+    ///
+    /// The host must handle semihosting calls.
+    pub unsafe fn new() -> Self {
+        Self(())
+    }
+}
+
+impl fmt::Write for SemihostingConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe { write_str(s) };
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+// Starts secondary CPUs with PSCI CPU_ON. A started CPU enters `__secondary_entry` in the same
+// EL as the caller with the address of its `StartInfo` in x0, and the MMU and caches off. The
+// trampoline switches to the CPU's stack from `sections` and jumps to the Rust entry point.
+
+use core::arch::global_asm;
+
+use crate::{
+    aarch64::psci::{Psci, PsciError},
+    smp::StartInfo,
+};
+
+/// The Rust entry point of a secondary CPU, which is called with its MPIDR affinity and the
+/// argument that was given to [`start_secondary`].
+pub type SecondaryEntry = extern "C" fn(mpidr: u64, arg: u64) -> !;
+
+global_asm!(
+    r#"
+.pushsection .text.smp, "ax"
+.balign 4
+.globl __secondary_entry
+__secondary_entry:
+    ldr x1, [x0]
+    mov sp, x1
+    ldp x2, x1, [x0, #8]
+    mrs x0, mpidr_el1
+    and x3, x0, #0xffffff
+    ubfx x0, x0, #32, #8
+    orr x0, x3, x0, lsl #32
+    br x2
+
+.popsection
+"#
+);
+
+extern "C" {
+    static __secondary_entry: u8;
+}
+
+/// Starts the CPU `mpidr` at `entry` with `arg`, on the stack that
+/// [`crate::sections::cpu_stack_range`] gives for `cpu`.
+///
+/// # Safety
+///
+/// The CPU must be off, and nothing else may use the stack of `cpu`. `entry` runs with the MMU
+/// off, so it must be identity mapped along with everything that it uses.
+pub unsafe fn start_secondary(
+    psci: &Psci,
+    mpidr: u64,
+    cpu: usize,
+    entry: SecondaryEntry,
+    arg: u64,
+) -> Result<(), PsciError> {
+    let info =
+        StartInfo::prepare(cpu, entry as usize as u64, arg).ok_or(PsciError::InvalidParameters)?;
+
+    let start_address = &__secondary_entry as *const u8 as u64;
+    psci.cpu_on(mpidr, start_address, info)
+}
@@ -0,0 +1,151 @@
+// The FADT grew with every ACPI revision, so fields are read by their offset from the start of the
+// table and are only used when the table is long enough to have them.
+
+use crate::acpi::tables::{read, Error, GenericAddress, Sdt};
+
+pub const FADT_SIGNATURE: [u8; 4] = *b"FACP";
+
+/// The fixed ACPI description table, which describes the fixed hardware registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Fadt {
+    pub revision: u8,
+    pub preferred_pm_profile: u8,
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm_timer_length: u8,
+    pub century: u8,
+    pub iapc_boot_arch: u16,
+    pub arm_boot_arch: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+    dsdt: u32,
+    x_dsdt: u64,
+    firmware_ctrl: u32,
+    x_firmware_ctrl: u64,
+    pm1a_control_block: u32,
+    x_pm1a_control_block: Option<GenericAddress>,
+    pm_timer_block: u32,
+    x_pm_timer_block: Option<GenericAddress>,
+}
+
+impl Fadt {
+    /// `iapc_boot_arch`: legacy devices such as a PS/2 controller may be present.
+    pub const BOOT_LEGACY_DEVICES: u16 = 1 << 0;
+    /// `iapc_boot_arch`: a 8042 compatible keyboard controller is present.
+    pub const BOOT_8042: u16 = 1 << 1;
+    /// `iapc_boot_arch`: VGA must not be probed.
+    pub const BOOT_VGA_NOT_PRESENT: u16 = 1 << 2;
+    /// `iapc_boot_arch`: the CMOS real time clock is not present.
+    pub const BOOT_CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+    /// `arm_boot_arch`: PSCI is implemented.
+    pub const ARM_PSCI_COMPLIANT: u16 = 1 << 0;
+    /// `arm_boot_arch`: PSCI calls use HVC instead of SMC.
+    pub const ARM_PSCI_USE_HVC: u16 = 1 << 1;
+
+    /// `flags`: the PM timer counter is 32 bits instead of 24 bits.
+    pub const TMR_VAL_EXT: u32 = 1 << 8;
+    /// `flags`: the reset register is supported.
+    pub const RESET_REG_SUP: u32 = 1 << 10;
+    /// `flags`: the system is hardware reduced and has no fixed hardware registers.
+    pub const HW_REDUCED_ACPI: u32 = 1 << 20;
+
+    pub fn parse(table: &Sdt) -> Result<Self, Error> {
+        if table.signature() != FADT_SIGNATURE {
+            return Err("Table is not a FADT");
+        }
+
+        let bytes = table.bytes();
+        let too_short = "FADT is too short";
+
+        // The reset register was added in ACPI 2.0, and is only valid if the flag is set
+        let flags = read(bytes, 112).unwrap_or(0);
+        let reset_register = if (flags & Self::RESET_REG_SUP) != 0 {
+            read(bytes, 116)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            revision: table.header.revision,
+            preferred_pm_profile: read(bytes, 45).ok_or(too_short)?,
+            sci_interrupt: read(bytes, 46).ok_or(too_short)?,
+            smi_command_port: read(bytes, 48).ok_or(too_short)?,
+            acpi_enable: read(bytes, 52).ok_or(too_short)?,
+            acpi_disable: read(bytes, 53).ok_or(too_short)?,
+            pm_timer_length: read(bytes, 91).ok_or(too_short)?,
+            century: read(bytes, 108).ok_or(too_short)?,
+            iapc_boot_arch: read(bytes, 109).unwrap_or(0),
+            arm_boot_arch: read(bytes, 129).unwrap_or(0),
+            flags,
+            reset_register,
+            reset_value: read(bytes, 128).unwrap_or(0),
+            dsdt: read(bytes, 40).ok_or(too_short)?,
+            x_dsdt: read(bytes, 140).unwrap_or(0),
+            firmware_ctrl: read(bytes, 36).ok_or(too_short)?,
+            x_firmware_ctrl: read(bytes, 132).unwrap_or(0),
+            pm1a_control_block: read(bytes, 64).ok_or(too_short)?,
+            x_pm1a_control_block: read(bytes, 172),
+            pm_timer_block: read(bytes, 76).ok_or(too_short)?,
+            x_pm_timer_block: read(bytes, 208),
+        })
+    }
+
+    /// Returns the physical address of the DSDT, preferring the 64 bit field.
+    pub fn dsdt_address(&self) -> u64 {
+        if self.x_dsdt != 0 {
+            self.x_dsdt
+        } else {
+            self.dsdt as u64
+        }
+    }
+
+    /// Returns the physical address of the FACS, preferring the 64 bit field.
+    pub fn facs_address(&self) -> Option<u64> {
+        match (self.x_firmware_ctrl, self.firmware_ctrl) {
+            (0, 0) => None,
+            (0, address) => Some(address as u64),
+            (address, _) => Some(address),
+        }
+    }
+
+    /// Returns the PM1a control register, which is used to enter sleep states.
+    pub fn pm1a_control_block(&self) -> Option<GenericAddress> {
+        extended_or_port(self.x_pm1a_control_block, self.pm1a_control_block, 16)
+    }
+
+    /// Returns the PM timer register, which counts at 3.579545 MHz.
+    pub fn pm_timer_block(&self) -> Option<GenericAddress> {
+        if self.pm_timer_length == 0 {
+            return None;
+        }
+
+        extended_or_port(self.x_pm_timer_block, self.pm_timer_block, 32)
+    }
+
+    pub fn is_hardware_reduced(&self) -> bool {
+        (self.flags & Self::HW_REDUCED_ACPI) != 0
+    }
+}
+
+/// Prefers a 64 bit register address, falling back to a 32 bit I/O port address.
+fn extended_or_port(
+    extended: Option<GenericAddress>,
+    port: u32,
+    bit_width: u8,
+) -> Option<GenericAddress> {
+    match extended {
+        Some(address) if address.address != 0 => Some(address),
+        _ if port != 0 => Some(GenericAddress {
+            address_space: GenericAddress::SYSTEM_IO,
+            bit_width,
+            bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }),
+        _ => None,
+    }
+}
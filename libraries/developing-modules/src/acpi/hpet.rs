@@ -0,0 +1,51 @@
+use crate::acpi::tables::{read, Error, GenericAddress, Sdt};
+
+pub const HPET_SIGNATURE: [u8; 4] = *b"HPET";
+
+/// The HPET description table, which locates the high precision event timer registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Hpet {
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    /// Smallest periodic tick, in main counter ticks, that does not lose interrupts.
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl Hpet {
+    pub fn parse(table: &Sdt) -> Result<Self, Error> {
+        if table.signature() != HPET_SIGNATURE {
+            return Err("Table is not a HPET table");
+        }
+
+        let data = table.data();
+        let too_short = "HPET table is too short";
+
+        Ok(Self {
+            event_timer_block_id: read(data, 0).ok_or(too_short)?,
+            base_address: read(data, 4).ok_or(too_short)?,
+            hpet_number: read(data, 16).ok_or(too_short)?,
+            minimum_tick: read(data, 17).ok_or(too_short)?,
+            page_protection: read(data, 19).ok_or(too_short)?,
+        })
+    }
+
+    /// Returns the physical address of the timer registers.
+    pub fn address(&self) -> u64 {
+        self.base_address.address
+    }
+
+    /// Returns the number of comparators, which is one more than the last comparator index.
+    pub fn comparator_count(&self) -> u8 {
+        (((self.event_timer_block_id >> 8) & 0x1f) + 1) as u8
+    }
+
+    pub fn counter_is_64_bit(&self) -> bool {
+        (self.event_timer_block_id & (1 << 13)) != 0
+    }
+
+    pub fn pci_vendor_id(&self) -> u16 {
+        (self.event_timer_block_id >> 16) as u16
+    }
+}
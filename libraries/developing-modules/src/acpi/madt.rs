@@ -0,0 +1,248 @@
+use crate::acpi::tables::{read, Error, Sdt};
+
+pub const MADT_SIGNATURE: [u8; 4] = *b"APIC";
+
+/// The MADT, which lists the interrupt controllers and processors of the system.
+#[derive(Copy, Clone, Debug)]
+pub struct Madt<'a> {
+    local_apic_address: u32,
+    flags: u32,
+    entries: &'a [u8],
+}
+
+/// Polarity and trigger mode of an interrupt, as used by interrupt source overrides and NMIs.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MpsIntiFlags(pub u16);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ConformsToBus,
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+    ConformsToBus,
+    Edge,
+    Level,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MadtEntry<'a> {
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        gsi_base: u32,
+    },
+    /// An ISA interrupt that is not identity mapped to a global system interrupt.
+    InterruptSourceOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: MpsIntiFlags,
+    },
+    NmiSource {
+        flags: MpsIntiFlags,
+        gsi: u32,
+    },
+    /// `processor_id` is 0xff for every processor.
+    LocalApicNmi {
+        processor_id: u8,
+        flags: MpsIntiFlags,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        processor_uid: u32,
+    },
+    /// `processor_uid` is 0xffffffff for every processor.
+    LocalX2ApicNmi {
+        flags: MpsIntiFlags,
+        processor_uid: u32,
+        lint: u8,
+    },
+    /// An entry type that is not decoded. `data` does not include the type and length bytes.
+    Unknown {
+        entry_type: u8,
+        data: &'a [u8],
+    },
+}
+
+pub struct MadtEntryIter<'a> {
+    entries: &'a [u8],
+}
+
+impl MpsIntiFlags {
+    pub fn polarity(&self) -> Polarity {
+        match self.0 & 0x3 {
+            1 => Polarity::ActiveHigh,
+            3 => Polarity::ActiveLow,
+            _ => Polarity::ConformsToBus,
+        }
+    }
+
+    pub fn trigger_mode(&self) -> TriggerMode {
+        match (self.0 >> 2) & 0x3 {
+            1 => TriggerMode::Edge,
+            3 => TriggerMode::Level,
+            _ => TriggerMode::ConformsToBus,
+        }
+    }
+}
+
+impl<'a> Madt<'a> {
+    /// Set when the system also has dual 8259 PICs, which must be masked before using the APICs.
+    pub const PCAT_COMPAT: u32 = 1 << 0;
+
+    /// Set in local APIC and x2APIC entry flags when the processor can be used.
+    pub const PROCESSOR_ENABLED: u32 = 1 << 0;
+    /// Set in local APIC and x2APIC entry flags when a disabled processor can be brought online.
+    pub const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+    pub fn parse(table: &Sdt<'a>) -> Result<Self, Error> {
+        if table.signature() != MADT_SIGNATURE {
+            return Err("Table is not a MADT");
+        }
+
+        let data = table.data();
+        let local_apic_address = read(data, 0).ok_or("MADT is too short")?;
+        let flags = read(data, 4).ok_or("MADT is too short")?;
+
+        Ok(Self {
+            local_apic_address,
+            flags,
+            entries: &data[8..],
+        })
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn has_8259_pics(&self) -> bool {
+        (self.flags & Self::PCAT_COMPAT) != 0
+    }
+
+    /// Returns the physical address of the local APIC, taking a 64 bit override into account.
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddressOverride { address } => Some(address),
+                _ => None,
+            })
+            .unwrap_or(self.local_apic_address as u64)
+    }
+
+    pub fn entries(&self) -> MadtEntryIter<'a> {
+        MadtEntryIter {
+            entries: self.entries,
+        }
+    }
+
+    /// Returns the number of processors that are enabled or can be brought online.
+    pub fn processor_count(&self) -> usize {
+        let usable = Self::PROCESSOR_ENABLED | Self::PROCESSOR_ONLINE_CAPABLE;
+
+        self.entries()
+            .filter(|entry| match entry {
+                MadtEntry::LocalApic { flags, .. } | MadtEntry::LocalX2Apic { flags, .. } => {
+                    (flags & usable) != 0
+                }
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Returns the global system interrupt and flags that ISA interrupt `irq` is routed to.
+    pub fn isa_irq_to_gsi(&self, irq: u8) -> (u32, MpsIntiFlags) {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::InterruptSourceOverride {
+                    bus: 0,
+                    source,
+                    gsi,
+                    flags,
+                } if source == irq => Some((gsi, flags)),
+                _ => None,
+            })
+            .unwrap_or((irq as u32, MpsIntiFlags(0)))
+    }
+}
+
+impl<'a> Iterator for MadtEntryIter<'a> {
+    type Item = MadtEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry_type = *self.entries.first()?;
+        let length = *self.entries.get(1)? as usize;
+
+        // A malformed length would otherwise loop forever or read past the table
+        if length < 2 || length > self.entries.len() {
+            self.entries = &[];
+            return None;
+        }
+
+        let entry = &self.entries[..length];
+        self.entries = &self.entries[length..];
+
+        // A known entry that is too short for its type is returned as unknown, instead of ending
+        // the iteration early
+        let decode = || match entry_type {
+            0 => Some(MadtEntry::LocalApic {
+                processor_id: *entry.get(2)?,
+                apic_id: *entry.get(3)?,
+                flags: read(entry, 4)?,
+            }),
+            1 => Some(MadtEntry::IoApic {
+                id: *entry.get(2)?,
+                address: read(entry, 4)?,
+                gsi_base: read(entry, 8)?,
+            }),
+            2 => Some(MadtEntry::InterruptSourceOverride {
+                bus: *entry.get(2)?,
+                source: *entry.get(3)?,
+                gsi: read(entry, 4)?,
+                flags: MpsIntiFlags(read(entry, 8)?),
+            }),
+            3 => Some(MadtEntry::NmiSource {
+                flags: MpsIntiFlags(read(entry, 2)?),
+                gsi: read(entry, 4)?,
+            }),
+            4 => Some(MadtEntry::LocalApicNmi {
+                processor_id: *entry.get(2)?,
+                flags: MpsIntiFlags(read(entry, 3)?),
+                lint: *entry.get(5)?,
+            }),
+            5 => Some(MadtEntry::LocalApicAddressOverride {
+                address: read(entry, 4)?,
+            }),
+            9 => Some(MadtEntry::LocalX2Apic {
+                x2apic_id: read(entry, 4)?,
+                flags: read(entry, 8)?,
+                processor_uid: read(entry, 12)?,
+            }),
+            10 => Some(MadtEntry::LocalX2ApicNmi {
+                flags: MpsIntiFlags(read(entry, 2)?),
+                processor_uid: read(entry, 4)?,
+                lint: *entry.get(8)?,
+            }),
+            _ => None,
+        };
+
+        Some(decode().unwrap_or(MadtEntry::Unknown {
+            entry_type,
+            data: &entry[2..],
+        }))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::acpi::tables::{read, Error, Sdt};
+
+pub const MCFG_SIGNATURE: [u8; 4] = *b"MCFG";
+
+/// The MCFG, which locates the memory mapped PCI Express configuration space.
+#[derive(Copy, Clone, Debug)]
+pub struct Mcfg<'a> {
+    entries: &'a [u8],
+}
+
+/// The configuration space of the buses `start_bus..=end_bus` in one PCI segment group.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+pub struct McfgEntryIter<'a> {
+    entries: &'a [u8],
+}
+
+const ENTRY_SIZE: usize = 16;
+
+impl<'a> Mcfg<'a> {
+    pub fn parse(table: &Sdt<'a>) -> Result<Self, Error> {
+        if table.signature() != MCFG_SIGNATURE {
+            return Err("Table is not a MCFG");
+        }
+
+        // The entries follow 8 reserved bytes
+        let entries = table.data().get(8..).ok_or("MCFG is too short")?;
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> McfgEntryIter<'a> {
+        McfgEntryIter {
+            entries: self.entries,
+        }
+    }
+
+    /// Returns the physical address of the configuration space of a PCI function. The base address
+    /// of an entry is where bus 0 would be, even if the entry starts at a later bus.
+    pub fn config_address(&self, segment: u16, bus: u8, device: u8, function: u8) -> Option<u64> {
+        self.entries()
+            .find(|entry| {
+                entry.segment == segment && (entry.start_bus..=entry.end_bus).contains(&bus)
+            })
+            .map(|entry| {
+                entry.base_address
+                    + ((bus as u64) << 20)
+                    + (((device & 0x1f) as u64) << 15)
+                    + (((function & 0x7) as u64) << 12)
+            })
+    }
+}
+
+impl Iterator for McfgEntryIter<'_> {
+    type Item = McfgEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.len() < ENTRY_SIZE {
+            return None;
+        }
+
+        let entry = &self.entries[..ENTRY_SIZE];
+        self.entries = &self.entries[ENTRY_SIZE..];
+
+        Some(McfgEntry {
+            base_address: read(entry, 0)?,
+            segment: read(entry, 8)?,
+            start_bus: entry[10],
+            end_bus: entry[11],
+        })
+    }
+}
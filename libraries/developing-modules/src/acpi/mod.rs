@@ -0,0 +1,9 @@
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod spcr;
+pub mod tables;
+
+#[cfg(test)]
+mod tests;
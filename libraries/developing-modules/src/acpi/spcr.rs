@@ -0,0 +1,105 @@
+use crate::acpi::tables::{read, Error, GenericAddress, Sdt};
+
+pub const SPCR_SIGNATURE: [u8; 4] = *b"SPCR";
+
+/// The serial port console redirection table, which describes the console UART.
+#[derive(Copy, Clone, Debug)]
+pub struct Spcr {
+    pub revision: u8,
+    pub interface_type: SpcrInterface,
+    pub base_address: GenericAddress,
+    pub interrupt_type: u8,
+    pub irq: u8,
+    pub gsi: u32,
+    /// The baud rate, or `None` if the firmware settings should be kept.
+    pub baud: Option<u32>,
+    /// Frequency of the UART input clock, which is only given from revision 3.
+    pub clock_frequency: Option<u32>,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpcrInterface(pub u8);
+
+impl SpcrInterface {
+    pub const UART_16550: Self = Self(0x00);
+    pub const UART_16450: Self = Self(0x01);
+    pub const ARM_PL011: Self = Self(0x03);
+    pub const ARM_SBSA_GENERIC_32BIT: Self = Self(0x0d);
+    pub const ARM_SBSA_GENERIC: Self = Self(0x0e);
+    pub const BCM2835: Self = Self(0x10);
+    /// A 16550 compatible UART whose register width comes from the base address.
+    pub const UART_16550_GAS: Self = Self(0x12);
+
+    /// Returns true for the interfaces that are programmed like a 16550.
+    pub fn is_16550(&self) -> bool {
+        matches!(
+            *self,
+            Self::UART_16550 | Self::UART_16450 | Self::UART_16550_GAS
+        )
+    }
+
+    /// Returns true for the interfaces that are programmed like a PL011.
+    pub fn is_pl011(&self) -> bool {
+        matches!(
+            *self,
+            Self::ARM_PL011 | Self::ARM_SBSA_GENERIC | Self::ARM_SBSA_GENERIC_32BIT
+        )
+    }
+}
+
+impl Spcr {
+    pub fn parse(table: &Sdt) -> Result<Self, Error> {
+        if table.signature() != SPCR_SIGNATURE {
+            return Err("Table is not a SPCR");
+        }
+
+        let bytes = table.bytes();
+        let too_short = "SPCR is too short";
+
+        let baud = match read::<u8>(bytes, 58).ok_or(too_short)? {
+            3 => Some(9600),
+            4 => Some(19200),
+            6 => Some(57600),
+            7 => Some(115200),
+            _ => None,
+        };
+
+        // Revision 3 added the UART clock frequency, and revision 4 added a precise baud rate that
+        // overrides the encoded one when it is not 0. Older tables have other fields there.
+        let revision = table.header.revision;
+        let clock_frequency = match read::<u32>(bytes, 76) {
+            Some(0) | None => None,
+            _ if revision < 3 => None,
+            clock_frequency => clock_frequency,
+        };
+        let precise_baud = match read::<u32>(bytes, 80) {
+            Some(0) | None => None,
+            _ if revision < 4 => None,
+            precise_baud => precise_baud,
+        };
+
+        Ok(Self {
+            revision,
+            interface_type: SpcrInterface(read(bytes, 36).ok_or(too_short)?),
+            base_address: read(bytes, 40).ok_or(too_short)?,
+            interrupt_type: read(bytes, 52).ok_or(too_short)?,
+            irq: read(bytes, 53).ok_or(too_short)?,
+            gsi: read(bytes, 54).ok_or(too_short)?,
+            baud: precise_baud.or(baud),
+            clock_frequency,
+        })
+    }
+
+    /// Returns the distance between registers as a shift, based on the access size of the base
+    /// address.
+    pub fn register_shift(&self) -> u32 {
+        match self.base_address.access_size {
+            // Undefined, which legacy tables use for byte registers
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            _ => 3,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// Finds ACPI tables by walking the RSDP and the XSDT, or the RSDT on ACPI 1.0 systems. Every table
+// is checksummed before it is handed out.
+
+use core::{mem, ptr, slice, str};
+
+use crate::memory::PhysicalMapper;
+
+pub type Error = &'static str;
+
+pub const RSDP_SIGNATURE: [u8; 8] = *b"RSD PTR ";
+pub const RSDT_SIGNATURE: [u8; 4] = *b"RSDT";
+pub const XSDT_SIGNATURE: [u8; 4] = *b"XSDT";
+
+/// Size of the RSDP before ACPI 2.0 added the XSDT address.
+const RSDP_V1_LENGTH: usize = 20;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    // The rest is only valid when `revision` is 2 or above
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    _reserved: [u8; 3],
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// A register location, used by tables such as the FADT and HPET.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+/// A table that passed its checksum. The bytes include the header.
+#[derive(Copy, Clone, Debug)]
+pub struct Sdt<'a> {
+    pub address: u64,
+    pub header: SdtHeader,
+    bytes: &'a [u8],
+}
+
+pub struct AcpiTables<'a, M: PhysicalMapper> {
+    mapper: &'a M,
+    revision: u8,
+    /// The XSDT, or the RSDT if there is no XSDT.
+    root: Sdt<'a>,
+    entry_size: usize,
+}
+
+pub struct SdtIter<'t, 'a, M: PhysicalMapper> {
+    tables: &'t AcpiTables<'a, M>,
+    index: usize,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+    pub const PCI_CONFIG: u8 = 2;
+}
+
+/// Returns true if the bytes add up to 0, which is how every ACPI checksum works.
+pub fn checksum_is_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Reads a `T` at `offset` in `bytes`, or returns `None` if it does not fit.
+pub(crate) fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    if offset.checked_add(mem::size_of::<T>())? > bytes.len() {
+        return None;
+    }
+
+    Some(unsafe { ptr::read_unaligned(bytes.as_ptr().add(offset) as *const T) })
+}
+
+impl SdtHeader {
+    pub fn signature_str(&self) -> &str {
+        str::from_utf8(&self.signature).unwrap_or("????")
+    }
+}
+
+impl<'a> Sdt<'a> {
+    /// Checks the header and checksum of the table at `address`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the physical address of an ACPI table.
+    pub unsafe fn from_address<M: PhysicalMapper>(
+        mapper: &'a M,
+        address: u64,
+    ) -> Result<Self, Error> {
+        let header_size = mem::size_of::<SdtHeader>();
+        let header: SdtHeader =
+            ptr::read_unaligned(mapper.map_physical(address, header_size) as *const SdtHeader);
+
+        let length = header.length as usize;
+        if length < header_size {
+            return Err("ACPI table is shorter than its header");
+        }
+
+        let bytes = slice::from_raw_parts(mapper.map_physical(address, length), length);
+        Self::from_bytes(address, bytes)
+    }
+
+    /// Checks the header and checksum of a table that has already been read into memory.
+    pub fn from_bytes(address: u64, bytes: &'a [u8]) -> Result<Self, Error> {
+        let header: SdtHeader = read(bytes, 0).ok_or("ACPI table is shorter than its header")?;
+
+        let length = header.length as usize;
+        if length < mem::size_of::<SdtHeader>() || length > bytes.len() {
+            return Err("ACPI table has an invalid length");
+        }
+
+        let bytes = &bytes[..length];
+        if !checksum_is_valid(bytes) {
+            return Err("ACPI table has an invalid checksum");
+        }
+
+        Ok(Self {
+            address,
+            header,
+            bytes,
+        })
+    }
+
+    pub fn signature(&self) -> [u8; 4] {
+        self.header.signature
+    }
+
+    /// Returns the whole table, including the header.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the table after the header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<SdtHeader>()..]
+    }
+}
+
+impl<'a, M: PhysicalMapper> AcpiTables<'a, M> {
+    /// Validates the RSDP at `rsdp_address` and the XSDT or RSDT that it points to.
+    ///
+    /// # Safety
+    ///
+    /// `rsdp_address` must be the physical address of the RSDP, such as the one found through the
+    /// UEFI configuration tables.
+    pub unsafe fn from_rsdp(mapper: &'a M, rsdp_address: u64) -> Result<Self, Error> {
+        let v1 = slice::from_raw_parts(
+            mapper.map_physical(rsdp_address, RSDP_V1_LENGTH),
+            RSDP_V1_LENGTH,
+        );
+        if v1[..8] != RSDP_SIGNATURE {
+            return Err("RSDP has an invalid signature");
+        }
+        if !checksum_is_valid(v1) {
+            return Err("RSDP has an invalid checksum");
+        }
+
+        let revision = v1[15];
+        if revision >= 2 {
+            let length = mem::size_of::<Rsdp>();
+            let bytes = slice::from_raw_parts(mapper.map_physical(rsdp_address, length), length);
+            if !checksum_is_valid(bytes) {
+                return Err("RSDP has an invalid extended checksum");
+            }
+
+            let rsdp: Rsdp = read(bytes, 0).ok_or("RSDP is too short")?;
+            if rsdp.xsdt_address != 0 {
+                return Self::from_root(mapper, revision, rsdp.xsdt_address, XSDT_SIGNATURE, 8);
+            }
+        }
+
+        let rsdt_address: u32 = read(v1, 16).ok_or("RSDP is too short")?;
+        Self::from_root(mapper, revision, rsdt_address as u64, RSDT_SIGNATURE, 4)
+    }
+
+    unsafe fn from_root(
+        mapper: &'a M,
+        revision: u8,
+        address: u64,
+        signature: [u8; 4],
+        entry_size: usize,
+    ) -> Result<Self, Error> {
+        let root = Sdt::from_address(mapper, address)?;
+        if root.signature() != signature {
+            return Err("ACPI root table has an invalid signature");
+        }
+
+        Ok(Self {
+            mapper,
+            revision,
+            root,
+            entry_size,
+        })
+    }
+
+    /// Returns the RSDP revision: 0 for ACPI 1.0 and 2 for ACPI 2.0 and later.
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.root.data().len() / self.entry_size
+    }
+
+    /// Returns the physical address of the table at `index` in the XSDT or RSDT.
+    pub fn table_address(&self, index: usize) -> Option<u64> {
+        let offset = index.checked_mul(self.entry_size)?;
+
+        if self.entry_size == 8 {
+            read::<u64>(self.root.data(), offset)
+        } else {
+            read::<u32>(self.root.data(), offset).map(|address| address as u64)
+        }
+    }
+
+    /// Iterates over every table listed in the XSDT or RSDT. Tables that fail their checksum are
+    /// returned as errors so that one bad table does not hide the rest.
+    pub fn tables(&self) -> SdtIter<'_, 'a, M> {
+        SdtIter {
+            tables: self,
+            index: 0,
+        }
+    }
+
+    /// Returns the first valid table with `signature`, such as `b"APIC"` for the MADT.
+    pub fn find(&self, signature: &[u8; 4]) -> Option<Sdt<'a>> {
+        self.tables()
+            .filter_map(Result::ok)
+            .find(|table| table.signature() == *signature)
+    }
+
+    /// Reads a table that is not listed in the root table, such as the DSDT.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the physical address of an ACPI table.
+    pub unsafe fn table_at(&self, address: u64) -> Result<Sdt<'a>, Error> {
+        Sdt::from_address(self.mapper, address)
+    }
+}
+
+impl<'a, M: PhysicalMapper> Iterator for SdtIter<'_, 'a, M> {
+    type Item = Result<Sdt<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.tables.table_address(self.index)?;
+        self.index += 1;
+
+        // The root table was validated, so its entries are trusted to be table addresses
+        Some(unsafe { Sdt::from_address(self.tables.mapper, address) })
+    }
+}
@@ -0,0 +1,272 @@
+// Feeds the ACPI tables of a Firecracker microVM through the parsers. APIC, FACP, MCFG and DSDT
+// were dumped from /sys/firmware/acpi/tables in the guest. Linux does not export the RSDP and the
+// XSDT there, so those two are rebuilt from the addresses and headers that it logged at boot:
+//
+//   ACPI: RSDP 0x00000000000E0000 000024 (v02 FIRECK)
+//   ACPI: XSDT 0x00000000000A0E13 00003C (v01 FIRECK FCMVXSDT 00000000 FCAT 20240119)
+//   ACPI: FACP 0x00000000000A0C83 000114 (v06 FIRECK FCVMFADT 00000000 FCAT 20240119)
+//   ACPI: DSDT 0x000000000009FD30 000F53 (v02 FIRECK FCVMDSDT 00000000 FCAT 20240119)
+//   ACPI: APIC 0x00000000000A0D97 000040 (v06 FIRECK FCVMMADT 00000000 FCAT 20240119)
+//   ACPI: MCFG 0x00000000000A0DD7 00003C (v01 FIRECK FCMVMCFG 00000000 FCAT 20240119)
+//
+// Every table is placed at its logged physical address in a buffer, which an `OffsetMapper` then
+// treats as physical memory.
+
+use super::{
+    fadt::Fadt,
+    madt::{Madt, MadtEntry, MpsIntiFlags},
+    mcfg::{Mcfg, McfgEntry},
+    tables::{checksum_is_valid, AcpiTables, Sdt},
+};
+use crate::memory::OffsetMapper;
+
+const RSDP_ADDRESS: u64 = 0xe_0000;
+const XSDT_ADDRESS: u64 = 0xa_0e13;
+const FADT_ADDRESS: u64 = 0xa_0c83;
+const DSDT_ADDRESS: u64 = 0x9_fd30;
+const MADT_ADDRESS: u64 = 0xa_0d97;
+const MCFG_ADDRESS: u64 = 0xa_0dd7;
+
+const RSDP: [u8; 36] = [
+    0x52, 0x53, 0x44, 0x20, 0x50, 0x54, 0x52, 0x20, 0x2b, 0x46, 0x49, 0x52, //
+    0x45, 0x43, 0x4b, 0x02, 0x00, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, //
+    0x13, 0x0e, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0xb1, 0x00, 0x00, 0x00, //
+];
+const XSDT: [u8; 60] = [
+    0x58, 0x53, 0x44, 0x54, 0x3c, 0x00, 0x00, 0x00, 0x01, 0xac, 0x46, 0x49, //
+    0x52, 0x45, 0x43, 0x4b, 0x46, 0x43, 0x4d, 0x56, 0x58, 0x53, 0x44, 0x54, //
+    0x00, 0x00, 0x00, 0x00, 0x46, 0x43, 0x41, 0x54, 0x19, 0x01, 0x24, 0x20, //
+    0x83, 0x0c, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x97, 0x0d, 0x0a, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0xd7, 0x0d, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, //
+];
+const FADT: &[u8] = include_bytes!("testdata/firecracker/FACP.dat");
+const DSDT: &[u8] = include_bytes!("testdata/firecracker/DSDT.dat");
+const MADT: &[u8] = include_bytes!("testdata/firecracker/APIC.dat");
+const MCFG: &[u8] = include_bytes!("testdata/firecracker/MCFG.dat");
+
+/// Returns a buffer that holds every table at its physical address.
+fn memory() -> Vec<u8> {
+    let mut memory = vec![0; RSDP_ADDRESS as usize + RSDP.len()];
+    for (address, table) in [
+        (RSDP_ADDRESS, &RSDP[..]),
+        (XSDT_ADDRESS, &XSDT),
+        (FADT_ADDRESS, FADT),
+        (DSDT_ADDRESS, DSDT),
+        (MADT_ADDRESS, MADT),
+        (MCFG_ADDRESS, MCFG),
+    ] {
+        let address = address as usize;
+        memory[address..address + table.len()].copy_from_slice(table);
+    }
+    memory
+}
+
+/// Fixes the length and checksum of a table after it was deliberately corrupted, so that only
+/// the corruption under test is wrong.
+fn finish(mut table: Vec<u8>) -> Vec<u8> {
+    let length = table.len() as u32;
+    table[4..8].copy_from_slice(&length.to_le_bytes());
+    table[9] = 0;
+    table[9] = table.iter().fold(0u8, |sum, byte| sum.wrapping_sub(*byte));
+    table
+}
+
+fn sdt(bytes: &[u8]) -> Sdt<'_> {
+    Sdt::from_bytes(0, bytes).unwrap()
+}
+
+#[test]
+fn finds_tables_through_the_rsdp_and_xsdt() {
+    let memory = memory();
+    let mapper = OffsetMapper::new(memory.as_ptr() as u64);
+    let tables = unsafe { AcpiTables::from_rsdp(&mapper, RSDP_ADDRESS) }.unwrap();
+
+    assert_eq!(tables.revision(), 2);
+    assert_eq!(tables.table_count(), 3);
+    assert_eq!(tables.table_address(1), Some(MADT_ADDRESS));
+    assert_eq!(tables.table_address(3), None);
+
+    let signatures: Vec<[u8; 4]> = tables
+        .tables()
+        .map(|table| table.unwrap().signature())
+        .collect();
+    assert_eq!(signatures, [*b"FACP", *b"APIC", *b"MCFG"]);
+
+    let madt = tables.find(b"APIC").unwrap();
+    assert_eq!(madt.address, MADT_ADDRESS);
+    assert_eq!(madt.bytes(), MADT);
+    assert!(tables.find(b"HPET").is_none());
+
+    // The DSDT is only reachable through the FADT
+    let fadt = Fadt::parse(&tables.find(b"FACP").unwrap()).unwrap();
+    let dsdt = unsafe { tables.table_at(fadt.dsdt_address()) }.unwrap();
+    assert_eq!(dsdt.signature(), *b"DSDT");
+    assert_eq!(dsdt.bytes(), DSDT);
+}
+
+#[test]
+fn rejects_a_bad_rsdp() {
+    let mut memory = memory();
+    memory[RSDP_ADDRESS as usize + 20] ^= 1;
+    let mapper = OffsetMapper::new(memory.as_ptr() as u64);
+    let result = unsafe { AcpiTables::from_rsdp(&mapper, RSDP_ADDRESS) };
+    assert_eq!(result.err(), Some("RSDP has an invalid extended checksum"));
+
+    let mut memory = self::memory();
+    memory[RSDP_ADDRESS as usize] = b'X';
+    let mapper = OffsetMapper::new(memory.as_ptr() as u64);
+    let result = unsafe { AcpiTables::from_rsdp(&mapper, RSDP_ADDRESS) };
+    assert_eq!(result.err(), Some("RSDP has an invalid signature"));
+}
+
+#[test]
+fn skips_tables_that_fail_their_checksum() {
+    let mut memory = memory();
+    memory[MADT_ADDRESS as usize + 40] ^= 1;
+    let mapper = OffsetMapper::new(memory.as_ptr() as u64);
+    let tables = unsafe { AcpiTables::from_rsdp(&mapper, RSDP_ADDRESS) }.unwrap();
+
+    assert!(tables.tables().nth(1).unwrap().is_err());
+    assert!(tables.find(b"APIC").is_none());
+    assert!(tables.find(b"MCFG").is_some());
+}
+
+#[test]
+fn checks_the_length_and_checksum_of_tables() {
+    for table in [FADT, DSDT, MADT, MCFG] {
+        assert!(checksum_is_valid(table));
+    }
+    assert!(Sdt::from_bytes(0, &MADT[..35]).is_err());
+    assert!(Sdt::from_bytes(0, &MADT[..MADT.len() - 1]).is_err());
+
+    let mut madt = MADT.to_vec();
+    madt[50] ^= 1;
+    assert!(Sdt::from_bytes(0, &madt).is_err());
+
+    let table = sdt(MADT);
+    assert_eq!(table.signature(), *b"APIC");
+    assert_eq!(table.header.revision, 6);
+    assert_eq!(table.data(), &MADT[36..]);
+}
+
+#[test]
+fn parses_the_madt() {
+    let table = sdt(MADT);
+    let madt = Madt::parse(&table).unwrap();
+
+    assert!(!madt.has_8259_pics());
+    assert_eq!(madt.local_apic_address(), 0xfee0_0000);
+    assert_eq!(madt.processor_count(), 1);
+
+    let entries: Vec<MadtEntry> = madt.entries().collect();
+    assert_eq!(
+        entries,
+        [
+            MadtEntry::IoApic {
+                id: 0,
+                address: 0xfec0_0000,
+                gsi_base: 0,
+            },
+            MadtEntry::LocalApic {
+                processor_id: 0,
+                apic_id: 0,
+                flags: Madt::PROCESSOR_ENABLED,
+            },
+        ]
+    );
+
+    // Without interrupt source overrides, ISA interrupts are identity mapped
+    assert_eq!(madt.isa_irq_to_gsi(4), (4, MpsIntiFlags(0)));
+
+    assert!(Madt::parse(&sdt(MCFG)).is_err());
+}
+
+#[test]
+fn returns_truncated_madt_entries_as_unknown() {
+    let mut madt = MADT[..44].to_vec();
+    // A local APIC entry with only the type and length
+    madt.extend_from_slice(&[0, 2]);
+    // An I/O APIC entry that ends before its GSI base
+    madt.extend_from_slice(&[1, 8, 3, 0, 0x00, 0x00, 0xc0, 0xfe]);
+    // A local APIC NMI entry with only its flags
+    madt.extend_from_slice(&[4, 5, 0xff, 0, 0]);
+    // The local APIC entry of the captured table
+    madt.extend_from_slice(&MADT[56..64]);
+    let madt = finish(madt);
+
+    let table = sdt(&madt);
+    let madt = Madt::parse(&table).unwrap();
+    let entries: Vec<MadtEntry> = madt.entries().collect();
+    assert_eq!(
+        entries,
+        [
+            MadtEntry::Unknown {
+                entry_type: 0,
+                data: &[],
+            },
+            MadtEntry::Unknown {
+                entry_type: 1,
+                data: &[3, 0, 0x00, 0x00, 0xc0, 0xfe],
+            },
+            MadtEntry::Unknown {
+                entry_type: 4,
+                data: &[0xff, 0, 0],
+            },
+            MadtEntry::LocalApic {
+                processor_id: 0,
+                apic_id: 0,
+                flags: Madt::PROCESSOR_ENABLED,
+            },
+        ]
+    );
+    assert_eq!(madt.processor_count(), 1);
+}
+
+#[test]
+fn stops_at_madt_entries_with_a_bad_length() {
+    for length in [0, 1, 9] {
+        let mut madt = MADT.to_vec();
+        madt.extend_from_slice(&[0, length, 1, 1, 1, 0, 0, 0]);
+        let madt = finish(madt);
+
+        let table = sdt(&madt);
+        let madt = Madt::parse(&table).unwrap();
+        assert_eq!(madt.entries().count(), 2);
+    }
+}
+
+#[test]
+fn parses_the_mcfg() {
+    let table = sdt(MCFG);
+    let mcfg = Mcfg::parse(&table).unwrap();
+
+    let entries: Vec<McfgEntry> = mcfg.entries().collect();
+    assert_eq!(
+        entries,
+        [McfgEntry {
+            base_address: 0xeec0_0000,
+            segment: 0,
+            start_bus: 0,
+            end_bus: 0,
+        }]
+    );
+    assert_eq!(mcfg.config_address(0, 0, 31, 7), Some(0xeecf_f000));
+    assert_eq!(mcfg.config_address(0, 1, 0, 0), None);
+    assert_eq!(mcfg.config_address(1, 0, 0, 0), None);
+}
+
+#[test]
+fn parses_the_fadt() {
+    let fadt = Fadt::parse(&sdt(FADT)).unwrap();
+
+    assert_eq!(fadt.revision, 6);
+    assert!(fadt.is_hardware_reduced());
+    assert_eq!(fadt.iapc_boot_arch, Fadt::BOOT_VGA_NOT_PRESENT);
+    assert_eq!(fadt.dsdt_address(), DSDT_ADDRESS);
+    assert_eq!(fadt.facs_address(), None);
+    assert!(fadt.reset_register.is_none());
+    assert!(fadt.pm1a_control_block().is_none());
+    assert!(fadt.pm_timer_block().is_none());
+
+    assert!(Fadt::parse(&sdt(MADT)).is_err());
+}
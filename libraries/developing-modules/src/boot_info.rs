@@ -0,0 +1,442 @@
+// The boot info is built by the bootloader and read by the kernel, which may be compiled
+// separately, so everything here is `#[repr(C)]` and uses fixed size integers for addresses.
+
+use core::{mem, ptr, slice, str};
+
+pub type Error = &'static str;
+
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"OSDLBOOT");
+pub const BOOT_INFO_VERSION: u32 = 1;
+
+pub const MODULE_NAME_LENGTH: usize = 48;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BootInfoHeader {
+    pub magic: u64,
+    pub version: u32,
+    /// Size of the whole `BootInfo` in bytes.
+    pub size: u32,
+    /// Wrapping sum of every byte of the `BootInfo`, not counting this field.
+    pub checksum: u32,
+    _reserved: u32,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegionKind(pub u32);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub size: u64,
+    pub kind: MemoryRegionKind,
+    _reserved: u32,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelFormat(pub u32);
+
+/// Bits of a pixel that hold each color. Only used when the format is `PixelFormat::BITMASK`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelMask {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
+}
+
+/// A linear framebuffer that stays valid after the firmware is no longer running.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Framebuffer {
+    pub base: u64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Number of pixels between the start of one scanline and the next.
+    pub stride: u32,
+    pub bytes_per_pixel: u32,
+    pub format: PixelFormat,
+    _reserved: u32,
+    pub mask: PixelMask,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FirmwareTableKind(pub u32);
+
+/// The root table that describes the platform: the ACPI RSDP on x86 or the device tree blob on
+/// aarch64 and riscv64.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FirmwareTable {
+    pub kind: FirmwareTableKind,
+    _reserved: u32,
+    pub address: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KernelRange {
+    pub phys_start: u64,
+    pub virt_start: u64,
+    pub size: u64,
+}
+
+/// A file that the bootloader loaded into memory for the kernel, such as an initial ramdisk.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BootModule {
+    pub start: u64,
+    pub size: u64,
+    name: [u8; MODULE_NAME_LENGTH],
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SerialKind(pub u32);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SerialConfig {
+    pub kind: SerialKind,
+    pub baud_rate: u32,
+    /// I/O port for `SerialKind::PORT_IO_16550`, otherwise a physical MMIO address.
+    pub base: u64,
+    /// Registers are `1 << register_shift` bytes apart.
+    pub register_shift: u32,
+    _reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BootInfo {
+    pub header: BootInfoHeader,
+    flags: u64,
+    memory_map: u64,
+    memory_map_length: u64,
+    framebuffer: Framebuffer,
+    firmware_table: FirmwareTable,
+    kernel: KernelRange,
+    command_line: u64,
+    command_line_length: u64,
+    modules: u64,
+    module_count: u64,
+    serial: SerialConfig,
+}
+
+impl MemoryRegionKind {
+    pub const USABLE: Self = Self(1);
+    pub const RESERVED: Self = Self(2);
+    pub const ACPI_RECLAIMABLE: Self = Self(3);
+    pub const ACPI_NVS: Self = Self(4);
+    pub const BAD_MEMORY: Self = Self(5);
+    /// Memory used by the bootloader that the kernel can reclaim once it is done with the boot
+    /// info.
+    pub const BOOTLOADER: Self = Self(6);
+    pub const KERNEL: Self = Self(7);
+    pub const KERNEL_STACK: Self = Self(8);
+    pub const PAGE_TABLES: Self = Self(9);
+    pub const MODULE: Self = Self(10);
+    pub const FRAMEBUFFER: Self = Self(11);
+    pub const FIRMWARE_RUNTIME: Self = Self(12);
+}
+
+impl PixelFormat {
+    /// 32 bits per pixel with red in the lowest byte.
+    pub const RGB: Self = Self(0);
+    /// 32 bits per pixel with blue in the lowest byte.
+    pub const BGR: Self = Self(1);
+    /// Colors are described by the framebuffer's `PixelMask`.
+    pub const BITMASK: Self = Self(2);
+}
+
+impl FirmwareTableKind {
+    pub const NONE: Self = Self(0);
+    pub const ACPI_RSDP: Self = Self(1);
+    pub const DEVICE_TREE: Self = Self(2);
+}
+
+impl SerialKind {
+    pub const NONE: Self = Self(0);
+    pub const PORT_IO_16550: Self = Self(1);
+    pub const MMIO_16550: Self = Self(2);
+    pub const PL011: Self = Self(3);
+}
+
+impl MemoryRegion {
+    pub fn new(start: u64, size: u64, kind: MemoryRegionKind) -> Self {
+        Self {
+            start,
+            size,
+            kind,
+            _reserved: 0,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.start + self.size
+    }
+}
+
+impl Framebuffer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        base: u64,
+        size: u64,
+        width: u32,
+        height: u32,
+        stride: u32,
+        bytes_per_pixel: u32,
+        format: PixelFormat,
+        mask: PixelMask,
+    ) -> Self {
+        Self {
+            base,
+            size,
+            width,
+            height,
+            stride,
+            bytes_per_pixel,
+            format,
+            _reserved: 0,
+            mask,
+        }
+    }
+}
+
+impl FirmwareTable {
+    pub fn new(kind: FirmwareTableKind, address: u64) -> Self {
+        Self {
+            kind,
+            _reserved: 0,
+            address,
+        }
+    }
+}
+
+impl BootModule {
+    /// Creates a module description; names longer than `MODULE_NAME_LENGTH - 1` bytes are cut off.
+    pub fn new(start: u64, size: u64, name: &str) -> Self {
+        let mut name_bytes = [0u8; MODULE_NAME_LENGTH];
+        let mut length = name.len().min(MODULE_NAME_LENGTH - 1);
+        while !name.is_char_boundary(length) {
+            length -= 1;
+        }
+        name_bytes[..length].copy_from_slice(&name.as_bytes()[..length]);
+
+        Self {
+            start,
+            size,
+            name: name_bytes,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        let length = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.name.len());
+        str::from_utf8(&self.name[..length]).unwrap_or("")
+    }
+}
+
+impl SerialConfig {
+    pub fn new(kind: SerialKind, base: u64, register_shift: u32, baud_rate: u32) -> Self {
+        Self {
+            kind,
+            baud_rate,
+            base,
+            register_shift,
+            _reserved: 0,
+        }
+    }
+}
+
+impl BootInfo {
+    const HAS_FRAMEBUFFER: u64 = 1 << 0;
+
+    /// Creates an empty boot info; fill it in with the `set_*` methods and call
+    /// [`BootInfo::seal`] before handing it to the kernel.
+    pub fn new() -> Self {
+        // Every field is an integer, so all zeroes is a valid value
+        let mut info: Self = unsafe { mem::zeroed() };
+        info.header.magic = BOOT_INFO_MAGIC;
+        info.header.version = BOOT_INFO_VERSION;
+        info.header.size = mem::size_of::<Self>() as u32;
+
+        info
+    }
+
+    /// Validates the header and checksum of a boot info passed by the bootloader.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to readable memory that is at least `size_of::<BootInfo>()` bytes long,
+    /// and every address stored in it must be accessible for as long as `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const BootInfo) -> Result<&'a BootInfo, Error> {
+        if ptr.is_null() {
+            return Err("Boot info pointer is null");
+        }
+        if !ptr.is_aligned() {
+            return Err("Boot info pointer is not aligned");
+        }
+
+        let info = &*ptr;
+        info.validate()?;
+
+        Ok(info)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.header.magic != BOOT_INFO_MAGIC {
+            return Err("Boot info has an invalid magic number");
+        }
+        if self.header.version != BOOT_INFO_VERSION {
+            return Err("Boot info version is not supported");
+        }
+        if self.header.size as usize != mem::size_of::<Self>() {
+            return Err("Boot info has an unexpected size");
+        }
+        if self.header.checksum != self.compute_checksum() {
+            return Err("Boot info checksum does not match");
+        }
+
+        Ok(())
+    }
+
+    /// Fills in the checksum. Must be called after the last change to the boot info.
+    pub fn seal(&mut self) {
+        self.header.checksum = self.compute_checksum();
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let bytes = unsafe {
+            slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>())
+        };
+        let checksum_start = mem::offset_of!(BootInfoHeader, checksum);
+        let checksum_end = checksum_start + mem::size_of::<u32>();
+
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i < checksum_start || *i >= checksum_end)
+            .fold(0u32, |sum, (_, &byte)| sum.wrapping_add(byte as u32))
+    }
+
+    pub fn set_memory_map(&mut self, regions: &'static [MemoryRegion]) {
+        self.memory_map = regions.as_ptr() as u64;
+        self.memory_map_length = regions.len() as u64;
+    }
+
+    pub fn set_framebuffer(&mut self, framebuffer: Framebuffer) {
+        self.framebuffer = framebuffer;
+        self.flags |= Self::HAS_FRAMEBUFFER;
+    }
+
+    pub fn set_firmware_table(&mut self, table: FirmwareTable) {
+        self.firmware_table = table;
+    }
+
+    pub fn set_kernel(&mut self, kernel: KernelRange) {
+        self.kernel = kernel;
+    }
+
+    pub fn set_command_line(&mut self, command_line: &'static str) {
+        self.command_line = command_line.as_ptr() as u64;
+        self.command_line_length = command_line.len() as u64;
+    }
+
+    pub fn set_modules(&mut self, modules: &'static [BootModule]) {
+        self.modules = modules.as_ptr() as u64;
+        self.module_count = modules.len() as u64;
+    }
+
+    pub fn set_serial(&mut self, serial: SerialConfig) {
+        self.serial = serial;
+    }
+
+    pub fn memory_map(&self) -> &[MemoryRegion] {
+        if self.memory_map == 0 {
+            return &[];
+        }
+
+        unsafe {
+            slice::from_raw_parts(
+                self.memory_map as *const MemoryRegion,
+                self.memory_map_length as usize,
+            )
+        }
+    }
+
+    pub fn framebuffer(&self) -> Option<&Framebuffer> {
+        if (self.flags & Self::HAS_FRAMEBUFFER) == 0 {
+            return None;
+        }
+
+        Some(&self.framebuffer)
+    }
+
+    pub fn firmware_table(&self) -> Option<FirmwareTable> {
+        if self.firmware_table.kind == FirmwareTableKind::NONE {
+            return None;
+        }
+
+        Some(self.firmware_table)
+    }
+
+    pub fn kernel(&self) -> &KernelRange {
+        &self.kernel
+    }
+
+    pub fn command_line(&self) -> Option<&str> {
+        if self.command_line == 0 {
+            return None;
+        }
+
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                self.command_line as *const u8,
+                self.command_line_length as usize,
+            )
+        };
+        str::from_utf8(bytes).ok()
+    }
+
+    pub fn modules(&self) -> &[BootModule] {
+        if self.modules == 0 {
+            return &[];
+        }
+
+        unsafe {
+            slice::from_raw_parts(
+                self.modules as *const BootModule,
+                self.module_count as usize,
+            )
+        }
+    }
+
+    pub fn serial(&self) -> Option<&SerialConfig> {
+        if self.serial.kind == SerialKind::NONE {
+            return None;
+        }
+
+        Some(&self.serial)
+    }
+
+    /// Returns a raw pointer to pass to the kernel entry point.
+    pub fn as_ptr(&self) -> *const BootInfo {
+        ptr::from_ref(self)
+    }
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
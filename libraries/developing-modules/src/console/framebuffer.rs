@@ -0,0 +1,570 @@
+// A text console drawn onto a linear framebuffer. Drawing only touches a byte slice, so the
+// console works the same on a real framebuffer and on a buffer in memory.
+
+use core::{fmt, slice};
+
+use crate::{
+    boot_info::{Framebuffer, PixelFormat, PixelMask},
+    console::font::{glyph, FONT_HEIGHT, FONT_WIDTH},
+};
+
+pub type Error = &'static str;
+
+const TAB_WIDTH: usize = 8;
+const MAX_ESCAPE_PARAMETERS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+/// The eight ANSI colors followed by their bright variants, as used by SGR escape sequences.
+pub const ANSI_PALETTE: [Color; 16] = [
+    Color::new(0x00, 0x00, 0x00),
+    Color::new(0xaa, 0x00, 0x00),
+    Color::new(0x00, 0xaa, 0x00),
+    Color::new(0xaa, 0x55, 0x00),
+    Color::new(0x00, 0x00, 0xaa),
+    Color::new(0xaa, 0x00, 0xaa),
+    Color::new(0x00, 0xaa, 0xaa),
+    Color::new(0xaa, 0xaa, 0xaa),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0xff, 0x55, 0x55),
+    Color::new(0x55, 0xff, 0x55),
+    Color::new(0xff, 0xff, 0x55),
+    Color::new(0x55, 0x55, 0xff),
+    Color::new(0xff, 0x55, 0xff),
+    Color::new(0x55, 0xff, 0xff),
+    Color::new(0xff, 0xff, 0xff),
+];
+
+pub const DEFAULT_FOREGROUND: Color = ANSI_PALETTE[7];
+pub const DEFAULT_BACKGROUND: Color = ANSI_PALETTE[0];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EscapeState {
+    None,
+    /// Received `ESC`.
+    Escape,
+    /// Received `ESC [` and possibly some parameters.
+    Csi,
+}
+
+pub struct FramebufferConsole<'a> {
+    buffer: &'a mut [u8],
+    info: Framebuffer,
+    columns: usize,
+    rows: usize,
+    cursor_column: usize,
+    cursor_row: usize,
+    foreground: Color,
+    background: Color,
+    bold: bool,
+    foreground_pixel: u32,
+    background_pixel: u32,
+    escape: EscapeState,
+    parameters: [u16; MAX_ESCAPE_PARAMETERS],
+    parameter_count: usize,
+}
+
+/// Scales an 8 bit color channel to the bits set in `mask`.
+fn encode_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+
+    ((value as u32 * max + 127) / 255) << shift
+}
+
+fn encode_color(color: Color, format: PixelFormat, mask: PixelMask) -> u32 {
+    match format {
+        PixelFormat::RGB => {
+            (color.red as u32) | ((color.green as u32) << 8) | ((color.blue as u32) << 16)
+        }
+        PixelFormat::BGR => {
+            (color.blue as u32) | ((color.green as u32) << 8) | ((color.red as u32) << 16)
+        }
+        _ => {
+            encode_channel(color.red, mask.red)
+                | encode_channel(color.green, mask.green)
+                | encode_channel(color.blue, mask.blue)
+        }
+    }
+}
+
+impl<'a> FramebufferConsole<'a> {
+    /// Creates a console that draws into `buffer`, which is laid out as described by `info`. The
+    /// `base` of `info` is not used. The screen is cleared.
+    pub fn new(buffer: &'a mut [u8], info: Framebuffer) -> Result<Self, Error> {
+        let bytes_per_pixel = info.bytes_per_pixel as usize;
+        if !(1..=4).contains(&bytes_per_pixel) {
+            return Err("Unsupported number of bytes per pixel");
+        }
+        if info.stride < info.width {
+            return Err("Framebuffer stride is smaller than its width");
+        }
+        if buffer.len() < info.stride as usize * info.height as usize * bytes_per_pixel {
+            return Err("Buffer is too small for the framebuffer");
+        }
+
+        let columns = info.width as usize / FONT_WIDTH;
+        let rows = info.height as usize / FONT_HEIGHT;
+        if columns == 0 || rows == 0 {
+            return Err("Framebuffer is too small for a single character");
+        }
+
+        let mut console = Self {
+            buffer,
+            info,
+            columns,
+            rows,
+            cursor_column: 0,
+            cursor_row: 0,
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bold: false,
+            foreground_pixel: 0,
+            background_pixel: 0,
+            escape: EscapeState::None,
+            parameters: [0; MAX_ESCAPE_PARAMETERS],
+            parameter_count: 0,
+        };
+        console.update_pixels();
+        console.clear();
+
+        Ok(console)
+    }
+
+    /// Creates a console that draws directly into the framebuffer at `info.base`.
+    ///
+    /// # Safety
+    ///
+    /// `info` must describe a framebuffer that is mapped at `info.base` and not used by anything
+    /// else for as long as the console exists.
+    pub unsafe fn from_framebuffer(info: Framebuffer) -> Result<Self, Error> {
+        let size = info.stride as usize * info.height as usize * info.bytes_per_pixel as usize;
+        let buffer = slice::from_raw_parts_mut(info.base as *mut u8, size);
+
+        Self::new(buffer, info)
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the column and row that the next character will be drawn at.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_column, self.cursor_row)
+    }
+
+    /// Moves the cursor, clamping it to the screen.
+    pub fn set_cursor(&mut self, column: usize, row: usize) {
+        self.cursor_column = column.min(self.columns - 1);
+        self.cursor_row = row.min(self.rows - 1);
+    }
+
+    pub fn set_colors(&mut self, foreground: Color, background: Color) {
+        self.foreground = foreground;
+        self.background = background;
+        self.update_pixels();
+    }
+
+    /// Fills the screen with the background color and moves the cursor to the top left.
+    pub fn clear(&mut self) {
+        let height = self.info.height as usize;
+        self.fill_rows(0, height, self.background_pixel);
+        self.cursor_column = 0;
+        self.cursor_row = 0;
+    }
+
+    fn update_pixels(&mut self) {
+        self.foreground_pixel = encode_color(self.foreground, self.info.format, self.info.mask);
+        self.background_pixel = encode_color(self.background, self.info.format, self.info.mask);
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.info.bytes_per_pixel as usize
+    }
+
+    fn pitch(&self) -> usize {
+        self.info.stride as usize * self.bytes_per_pixel()
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, pixel: u32) {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let offset = y * self.pitch() + x * bytes_per_pixel;
+
+        self.buffer[offset..offset + bytes_per_pixel]
+            .copy_from_slice(&pixel.to_le_bytes()[..bytes_per_pixel]);
+    }
+
+    /// Fills pixel rows `start..end` across the visible width.
+    fn fill_rows(&mut self, start: usize, end: usize, pixel: u32) {
+        for y in start..end {
+            for x in 0..self.info.width as usize {
+                self.put_pixel(x, y, pixel);
+            }
+        }
+    }
+
+    fn draw_cell(&mut self, c: char, column: usize, row: usize) {
+        let glyph = glyph(c);
+        let x = column * FONT_WIDTH;
+        let y = row * FONT_HEIGHT;
+
+        for (glyph_y, line) in glyph.iter().enumerate() {
+            for glyph_x in 0..FONT_WIDTH {
+                let pixel = if line & (0x80 >> glyph_x) != 0 {
+                    self.foreground_pixel
+                } else {
+                    self.background_pixel
+                };
+                self.put_pixel(x + glyph_x, y + glyph_y, pixel);
+            }
+        }
+    }
+
+    /// Clears from `column` to the end of `row`.
+    fn clear_line_from(&mut self, column: usize, row: usize) {
+        for column in column..self.columns {
+            self.draw_cell(' ', column, row);
+        }
+    }
+
+    /// Moves every text row up by one and clears the bottom row.
+    fn scroll(&mut self) {
+        let line_bytes = self.pitch() * FONT_HEIGHT;
+        let text_bytes = line_bytes * self.rows;
+
+        self.buffer.copy_within(line_bytes..text_bytes, 0);
+        self.fill_rows(
+            (self.rows - 1) * FONT_HEIGHT,
+            self.rows * FONT_HEIGHT,
+            self.background_pixel,
+        );
+    }
+
+    fn new_line(&mut self) {
+        self.cursor_column = 0;
+
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn write_printable(&mut self, c: char) {
+        if self.cursor_column >= self.columns {
+            self.new_line();
+        }
+
+        self.draw_cell(c, self.cursor_column, self.cursor_row);
+        self.cursor_column += 1;
+    }
+
+    /// Writes a single character, interpreting control characters and ANSI escape sequences.
+    pub fn write_char(&mut self, c: char) {
+        match self.escape {
+            EscapeState::Escape => {
+                if c == '[' {
+                    self.escape = EscapeState::Csi;
+                    self.parameters = [0; MAX_ESCAPE_PARAMETERS];
+                    self.parameter_count = 0;
+                } else {
+                    self.escape = EscapeState::None;
+                }
+                return;
+            }
+            EscapeState::Csi => {
+                self.handle_csi(c);
+                return;
+            }
+            EscapeState::None => {}
+        }
+
+        match c {
+            '\x1b' => self.escape = EscapeState::Escape,
+            '\n' => self.new_line(),
+            '\r' => self.cursor_column = 0,
+            '\t' => {
+                let next = (self.cursor_column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.cursor_column < next.min(self.columns) {
+                    self.write_printable(' ');
+                }
+            }
+            '\x08' => self.cursor_column = self.cursor_column.saturating_sub(1),
+            c if c.is_control() => {}
+            c => self.write_printable(c),
+        }
+    }
+
+    fn handle_csi(&mut self, c: char) {
+        if let Some(digit) = c.to_digit(10) {
+            if self.parameter_count == 0 {
+                self.parameter_count = 1;
+            }
+            if let Some(parameter) = self.parameters.get_mut(self.parameter_count - 1) {
+                *parameter = parameter.saturating_mul(10).saturating_add(digit as u16);
+            }
+            return;
+        }
+
+        if c == ';' {
+            // An empty parameter before the separator still counts as a 0
+            self.parameter_count = (self.parameter_count.max(1) + 1).min(MAX_ESCAPE_PARAMETERS + 1);
+            return;
+        }
+
+        self.escape = EscapeState::None;
+        let count = self.parameter_count.min(MAX_ESCAPE_PARAMETERS);
+        let parameters = self.parameters;
+
+        match c {
+            'm' => {
+                if count == 0 {
+                    self.select_graphic_rendition(0);
+                }
+                for &parameter in &parameters[..count] {
+                    self.select_graphic_rendition(parameter);
+                }
+                self.update_pixels();
+            }
+            'H' | 'f' => {
+                // Positions are 1 based, and a missing or 0 parameter means 1
+                let row = parameters[0].max(1) as usize - 1;
+                let column = parameters[1].max(1) as usize - 1;
+                self.set_cursor(column, row);
+            }
+            'J' if parameters[0] == 2 => self.clear(),
+            'K' => self.clear_line_from(self.cursor_column, self.cursor_row),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, parameter: u16) {
+        let bright = if self.bold { 8 } else { 0 };
+
+        match parameter {
+            0 => {
+                self.foreground = DEFAULT_FOREGROUND;
+                self.background = DEFAULT_BACKGROUND;
+                self.bold = false;
+            }
+            1 => self.bold = true,
+            22 => self.bold = false,
+            30..=37 => self.foreground = ANSI_PALETTE[(parameter - 30) as usize + bright],
+            39 => self.foreground = DEFAULT_FOREGROUND,
+            40..=47 => self.background = ANSI_PALETTE[(parameter - 40) as usize],
+            49 => self.background = DEFAULT_BACKGROUND,
+            90..=97 => self.foreground = ANSI_PALETTE[(parameter - 90) as usize + 8],
+            100..=107 => self.background = ANSI_PALETTE[(parameter - 100) as usize + 8],
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Write for FramebufferConsole<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            FramebufferConsole::write_char(self, c);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    const COLUMNS: usize = 4;
+    const ROWS: usize = 3;
+    const WIDTH: usize = COLUMNS * FONT_WIDTH;
+    const HEIGHT: usize = ROWS * FONT_HEIGHT;
+    // Wider than the visible width, so that drawing can be checked to leave the padding alone
+    const STRIDE: usize = WIDTH + 3;
+    const SIZE: usize = STRIDE * HEIGHT * 4;
+    const PADDING: u8 = 0xee;
+
+    const WHITE: u32 = 0x00aa_aaaa;
+    const BLACK: u32 = 0;
+
+    fn info() -> Framebuffer {
+        Framebuffer::new(
+            0,
+            SIZE as u64,
+            WIDTH as u32,
+            HEIGHT as u32,
+            STRIDE as u32,
+            4,
+            PixelFormat::BGR,
+            PixelMask {
+                red: 0xff0000,
+                green: 0x00ff00,
+                blue: 0x0000ff,
+                reserved: 0xff000000,
+            },
+        )
+    }
+
+    /// Writes `text` to a new console, returning the drawn buffer and the final cursor.
+    fn render(text: &str) -> (Vec<u8>, (usize, usize)) {
+        let mut buffer = vec![PADDING; SIZE];
+        let mut console = FramebufferConsole::new(&mut buffer, info()).unwrap();
+        console.write_str(text).unwrap();
+        let cursor = console.cursor();
+
+        (buffer, cursor)
+    }
+
+    fn pixel(buffer: &[u8], x: usize, y: usize) -> u32 {
+        let offset = (y * STRIDE + x) * 4;
+        u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn assert_cell(buffer: &[u8], c: char, column: usize, row: usize, colors: (u32, u32)) {
+        for (y, line) in glyph(c).iter().enumerate() {
+            for x in 0..FONT_WIDTH {
+                let expected = if line & (0x80 >> x) != 0 {
+                    colors.0
+                } else {
+                    colors.1
+                };
+                let actual = pixel(buffer, column * FONT_WIDTH + x, row * FONT_HEIGHT + y);
+                assert_eq!(
+                    actual, expected,
+                    "{:?} at column {}, row {}",
+                    c, column, row
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_framebuffers() {
+        let mut buffer = vec![0; SIZE - 1];
+        assert!(FramebufferConsole::new(&mut buffer, info()).is_err());
+
+        let mut buffer = vec![0; SIZE];
+        let mut wide_pixels = info();
+        wide_pixels.bytes_per_pixel = 5;
+        assert!(FramebufferConsole::new(&mut buffer, wide_pixels).is_err());
+
+        let mut narrow_stride = info();
+        narrow_stride.stride = WIDTH as u32 - 1;
+        assert!(FramebufferConsole::new(&mut buffer, narrow_stride).is_err());
+    }
+
+    #[test]
+    fn draws_glyphs_inside_the_visible_width() {
+        let (buffer, cursor) = render("Hi");
+
+        assert_cell(&buffer, 'H', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, 'i', 1, 0, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 2, 0, (WHITE, BLACK));
+        assert_eq!(cursor, (2, 0));
+
+        for y in 0..HEIGHT {
+            let padding = (y * STRIDE + WIDTH) * 4..(y + 1) * STRIDE * 4;
+            assert!(buffer[padding].iter().all(|&byte| byte == PADDING));
+        }
+    }
+
+    #[test]
+    fn wraps_at_the_end_of_a_line() {
+        let (buffer, cursor) = render("ABCDE");
+
+        assert_cell(&buffer, 'D', 3, 0, (WHITE, BLACK));
+        assert_cell(&buffer, 'E', 0, 1, (WHITE, BLACK));
+        assert_eq!(cursor, (1, 1));
+
+        // A full line only wraps once the next character is written
+        assert_eq!(render("ABCD").1, (4, 0));
+    }
+
+    #[test]
+    fn scrolls_when_writing_past_the_last_row() {
+        let (buffer, cursor) = render("A\nB\nC\nD");
+
+        assert_cell(&buffer, 'B', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, 'C', 0, 1, (WHITE, BLACK));
+        assert_cell(&buffer, 'D', 0, 2, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 1, 2, (WHITE, BLACK));
+        assert_eq!(cursor, (1, 2));
+    }
+
+    #[test]
+    fn tracks_the_cursor_through_control_characters() {
+        assert_eq!(render("AB\n").1, (0, 1));
+        assert_eq!(render("AB\x08").1, (1, 0));
+        assert_eq!(render("\x08").1, (0, 0));
+        assert_eq!(render("A\t").1, (4, 0));
+
+        let (buffer, cursor) = render("AB\rC");
+        assert_cell(&buffer, 'C', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, 'B', 1, 0, (WHITE, BLACK));
+        assert_eq!(cursor, (1, 0));
+    }
+
+    #[test]
+    fn sets_colors_with_sgr_sequences() {
+        let (buffer, _) = render("\x1b[31mA\x1b[1;32mB\x1b[44mC\x1b[0mD");
+
+        assert_cell(&buffer, 'A', 0, 0, (0xaa0000, BLACK));
+        assert_cell(&buffer, 'B', 1, 0, (0x55ff55, BLACK));
+        assert_cell(&buffer, 'C', 2, 0, (0x55ff55, 0x0000aa));
+        assert_cell(&buffer, 'D', 3, 0, (WHITE, BLACK));
+
+        let (buffer, _) = render("\x1b[95;100mA\x1b[mB");
+        assert_cell(&buffer, 'A', 0, 0, (0xff55ff, 0x555555));
+        assert_cell(&buffer, 'B', 1, 0, (WHITE, BLACK));
+    }
+
+    #[test]
+    fn moves_the_cursor_and_erases_with_csi_sequences() {
+        let (buffer, cursor) = render("\x1b[2;3HA");
+        assert_cell(&buffer, 'A', 2, 1, (WHITE, BLACK));
+        assert_eq!(cursor, (3, 1));
+
+        // Positions are clamped to the screen
+        assert_eq!(render("\x1b[99;99H").1, (COLUMNS - 1, ROWS - 1));
+
+        let (buffer, cursor) = render("ABC\x1b[1;2H\x1b[K");
+        assert_cell(&buffer, 'A', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 1, 0, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 2, 0, (WHITE, BLACK));
+        assert_eq!(cursor, (1, 0));
+
+        let (buffer, cursor) = render("AB\nC\x1b[2J");
+        assert_cell(&buffer, ' ', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 0, 1, (WHITE, BLACK));
+        assert_eq!(cursor, (0, 0));
+    }
+
+    #[test]
+    fn ignores_unsupported_escape_sequences() {
+        let (buffer, cursor) = render("\x1b[5XA\x1b7");
+
+        assert_cell(&buffer, 'A', 0, 0, (WHITE, BLACK));
+        assert_cell(&buffer, ' ', 1, 0, (WHITE, BLACK));
+        assert_eq!(cursor, (1, 0));
+    }
+}
@@ -0,0 +1,678 @@
+use core::{mem, ptr, slice};
+
+use crate::memory::{align_down, Error, FrameAllocator, PAGE_SIZE};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_VERSION_CURRENT: u8 = 1;
+
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+pub const EM_RISCV: u16 = 243;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_TLS: u32 = 7;
+
+pub const PF_X: u32 = 1 << 0;
+pub const PF_W: u32 = 1 << 1;
+pub const PF_R: u32 = 1 << 2;
+
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+
+const R_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+const R_AARCH64_RELATIVE: u32 = 1027;
+const R_RISCV_RELATIVE: u32 = 3;
+
+/// The machine that kernels are loaded for, which is the machine this crate is compiled for.
+pub const CURRENT_MACHINE: u16 = if cfg!(target_arch = "x86_64") {
+    EM_X86_64
+} else if cfg!(target_arch = "aarch64") {
+    EM_AARCH64
+} else if cfg!(target_arch = "riscv64") {
+    EM_RISCV
+} else {
+    0
+};
+
+pub const MAX_LOADED_SEGMENTS: usize = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ElfHeader {
+    pub ident: [u8; 16],
+    pub elf_type: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub program_header_offset: u64,
+    pub section_header_offset: u64,
+    pub flags: u32,
+    pub header_size: u16,
+    pub program_header_size: u16,
+    pub program_header_count: u16,
+    pub section_header_size: u16,
+    pub section_header_count: u16,
+    pub section_name_index: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramHeader {
+    pub segment_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub virtual_address: u64,
+    pub physical_address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub alignment: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DynamicEntry {
+    tag: i64,
+    value: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SegmentPermissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+/// A `PT_LOAD` segment after it has been copied into physical memory. Both ranges are page
+/// aligned.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoadedSegment {
+    pub virt_start: u64,
+    pub phys_start: u64,
+    pub size: u64,
+    pub permissions: SegmentPermissions,
+}
+
+/// The initialization image for thread local storage, described by the `PT_TLS` segment.
+#[derive(Copy, Clone, Debug)]
+pub struct TlsTemplate {
+    pub start_address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub alignment: u64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LoadedElf {
+    pub entry_point: u64,
+    pub tls: Option<TlsTemplate>,
+    segments: [LoadedSegment; MAX_LOADED_SEGMENTS],
+    segment_count: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    header: ElfHeader,
+}
+
+pub struct ProgramHeaders<'a> {
+    data: &'a [u8],
+    offset: usize,
+    remaining: usize,
+}
+
+/// Reads a `T` from `data` at `offset`, or returns `None` if it does not fit.
+fn read<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(mem::size_of::<T>())?;
+    if end > data.len() {
+        return None;
+    }
+
+    Some(unsafe { ptr::read_unaligned(data.as_ptr().add(offset) as *const T) })
+}
+
+impl ProgramHeader {
+    pub fn permissions(&self) -> SegmentPermissions {
+        SegmentPermissions {
+            read: (self.flags & PF_R) != 0,
+            write: (self.flags & PF_W) != 0,
+            execute: (self.flags & PF_X) != 0,
+        }
+    }
+}
+
+impl<'a> Iterator for ProgramHeaders<'a> {
+    type Item = ProgramHeader;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let header = read::<ProgramHeader>(self.data, self.offset)?;
+        self.offset += mem::size_of::<ProgramHeader>();
+        self.remaining -= 1;
+
+        Some(header)
+    }
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses an ELF file that was built for [`CURRENT_MACHINE`].
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        Self::parse_for_machine(data, CURRENT_MACHINE)
+    }
+
+    pub fn parse_for_machine(data: &'a [u8], machine: u16) -> Result<Self, Error> {
+        let header = read::<ElfHeader>(data, 0).ok_or("ELF file is too small for its header")?;
+
+        if header.ident[0..4] != ELF_MAGIC {
+            return Err("ELF file has an invalid magic number");
+        }
+        if header.ident[4] != ELF_CLASS_64 {
+            return Err("ELF file is not 64-bit");
+        }
+        if header.ident[5] != ELF_DATA_LITTLE_ENDIAN {
+            return Err("ELF file is not little endian");
+        }
+        if header.ident[6] != ELF_VERSION_CURRENT {
+            return Err("ELF file has an unknown version");
+        }
+        if header.elf_type != ET_EXEC && header.elf_type != ET_DYN {
+            return Err("ELF file is not an executable");
+        }
+        if header.machine != machine {
+            return Err("ELF file was built for a different machine");
+        }
+        if header.program_header_size as usize != mem::size_of::<ProgramHeader>() {
+            return Err("ELF file has an unexpected program header size");
+        }
+
+        let table_size =
+            header.program_header_count as u64 * mem::size_of::<ProgramHeader>() as u64;
+        let table_end = header
+            .program_header_offset
+            .checked_add(table_size)
+            .ok_or("ELF program header table is out of bounds")?;
+        if table_end > data.len() as u64 {
+            return Err("ELF program header table is out of bounds");
+        }
+
+        Ok(Self { data, header })
+    }
+
+    pub fn header(&self) -> &ElfHeader {
+        &self.header
+    }
+
+    /// Returns the entry point before any load bias is applied.
+    pub fn entry_point(&self) -> u64 {
+        self.header.entry
+    }
+
+    /// Returns true for PIE kernels, which can be loaded at any address.
+    pub fn is_position_independent(&self) -> bool {
+        self.header.elf_type == ET_DYN
+    }
+
+    pub fn program_headers(&self) -> ProgramHeaders<'a> {
+        ProgramHeaders {
+            data: self.data,
+            offset: self.header.program_header_offset as usize,
+            remaining: self.header.program_header_count as usize,
+        }
+    }
+
+    /// Returns the TLS template with `load_bias` applied to its address.
+    pub fn tls_template(&self, load_bias: u64) -> Option<TlsTemplate> {
+        self.program_headers()
+            .find(|header| header.segment_type == PT_TLS)
+            .map(|header| TlsTemplate {
+                start_address: header.virtual_address.wrapping_add(load_bias),
+                file_size: header.file_size,
+                memory_size: header.memory_size,
+                alignment: header.alignment,
+            })
+    }
+
+    /// Copies every `PT_LOAD` segment into newly allocated frames, zeroes the part of each
+    /// segment that is not backed by the file (`.bss`), and applies relative relocations.
+    ///
+    /// `load_bias` is added to every virtual address and must be 0 for kernels that are not
+    /// position independent. Frames are written through `phys_offset + physical address`.
+    pub fn load(
+        &self,
+        load_bias: u64,
+        allocator: &mut impl FrameAllocator,
+        phys_offset: u64,
+    ) -> Result<LoadedElf, Error> {
+        if load_bias != 0 && !self.is_position_independent() {
+            return Err("Only position independent ELF files can be loaded with a bias");
+        }
+        if !load_bias.is_multiple_of(PAGE_SIZE) {
+            return Err("ELF load bias is not page aligned");
+        }
+
+        let mut loaded = LoadedElf {
+            entry_point: self.header.entry.wrapping_add(load_bias),
+            tls: self.tls_template(load_bias),
+            segments: [LoadedSegment::default(); MAX_LOADED_SEGMENTS],
+            segment_count: 0,
+        };
+
+        // Every segment is checked before any frames are allocated, so that a rejected file does
+        // not leak the frames of the segments before it
+        for header in self.loadable_headers() {
+            let segment = self.segment_pages(&header, load_bias)?;
+            if loaded.segments().iter().any(|other| {
+                segment.virt_start < other.virt_start + other.size
+                    && other.virt_start < segment.virt_start + segment.size
+            }) {
+                return Err("ELF loadable segments share a page");
+            }
+            if loaded.segment_count == MAX_LOADED_SEGMENTS {
+                return Err("ELF file has too many loadable segments");
+            }
+
+            loaded.segments[loaded.segment_count] = segment;
+            loaded.segment_count += 1;
+        }
+
+        if loaded.segment_count == 0 {
+            return Err("ELF file has no loadable segments");
+        }
+
+        for (header, segment) in self.loadable_headers().zip(loaded.segments.iter_mut()) {
+            self.load_segment(&header, segment, allocator, phys_offset)?;
+        }
+
+        if self.is_position_independent() {
+            self.apply_relocations(&loaded, load_bias, phys_offset)?;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Returns the `PT_LOAD` headers that take up memory.
+    fn loadable_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        self.program_headers()
+            .filter(|header| header.segment_type == PT_LOAD && header.memory_size != 0)
+    }
+
+    /// Checks that a `PT_LOAD` segment is in bounds and returns the pages that it covers, without
+    /// a physical address yet.
+    fn segment_pages(
+        &self,
+        header: &ProgramHeader,
+        load_bias: u64,
+    ) -> Result<LoadedSegment, Error> {
+        if header.file_size > header.memory_size {
+            return Err("ELF segment is larger in the file than in memory");
+        }
+        let file_end = header
+            .offset
+            .checked_add(header.file_size)
+            .ok_or("ELF segment is out of bounds")?;
+        if file_end > self.data.len() as u64 {
+            return Err("ELF segment is out of bounds");
+        }
+
+        let virt = header.virtual_address.wrapping_add(load_bias);
+        let virt_end = virt
+            .checked_add(header.memory_size)
+            .and_then(|end| end.checked_add(PAGE_SIZE - 1))
+            .ok_or("ELF segment wraps around the address space")?;
+        let virt_start = align_down(virt, PAGE_SIZE);
+        let virt_end = align_down(virt_end, PAGE_SIZE);
+
+        Ok(LoadedSegment {
+            virt_start,
+            phys_start: 0,
+            size: virt_end - virt_start,
+            permissions: header.permissions(),
+        })
+    }
+
+    /// Allocates frames for a segment that was checked by `segment_pages` and copies it into them.
+    fn load_segment(
+        &self,
+        header: &ProgramHeader,
+        segment: &mut LoadedSegment,
+        allocator: &mut impl FrameAllocator,
+        phys_offset: u64,
+    ) -> Result<(), Error> {
+        segment.phys_start = allocator
+            .allocate_frames((segment.size / PAGE_SIZE) as usize)
+            .ok_or("Failed to allocate frames for an ELF segment")?;
+
+        unsafe {
+            let memory = slice::from_raw_parts_mut(
+                (segment.phys_start + phys_offset) as *mut u8,
+                segment.size as usize,
+            );
+            let file_start = (header.virtual_address % PAGE_SIZE) as usize;
+            let file_bytes =
+                &self.data[header.offset as usize..(header.offset + header.file_size) as usize];
+
+            memory[..file_start].fill(0);
+            memory[file_start..file_start + file_bytes.len()].copy_from_slice(file_bytes);
+            memory[file_start + file_bytes.len()..].fill(0);
+        }
+
+        Ok(())
+    }
+
+    fn apply_relocations(
+        &self,
+        loaded: &LoadedElf,
+        load_bias: u64,
+        phys_offset: u64,
+    ) -> Result<(), Error> {
+        let dynamic = match self
+            .program_headers()
+            .find(|header| header.segment_type == PT_DYNAMIC)
+        {
+            Some(dynamic) => dynamic,
+            None => return Ok(()),
+        };
+
+        let mut rela_address = None;
+        let mut rela_size = 0;
+        let mut rela_entry_size = mem::size_of::<Rela>() as u64;
+
+        let entry_count = dynamic.file_size as usize / mem::size_of::<DynamicEntry>();
+        for i in 0..entry_count {
+            let offset = dynamic.offset as usize + i * mem::size_of::<DynamicEntry>();
+            let entry = read::<DynamicEntry>(self.data, offset)
+                .ok_or("ELF dynamic section is out of bounds")?;
+
+            match entry.tag {
+                DT_NULL => break,
+                DT_RELA => rela_address = Some(entry.value.wrapping_add(load_bias)),
+                DT_RELASZ => rela_size = entry.value,
+                DT_RELAENT => rela_entry_size = entry.value,
+                _ => {}
+            }
+        }
+
+        let rela_address = match rela_address {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        if rela_entry_size != mem::size_of::<Rela>() as u64 {
+            return Err("ELF file has an unexpected relocation entry size");
+        }
+
+        let relative_type = match self.header.machine {
+            EM_X86_64 => R_X86_64_RELATIVE,
+            EM_AARCH64 => R_AARCH64_RELATIVE,
+            EM_RISCV => R_RISCV_RELATIVE,
+            _ => return Err("Relocations are not supported for this machine"),
+        };
+
+        for i in 0..rela_size / rela_entry_size {
+            let rela_phys = i
+                .checked_mul(rela_entry_size)
+                .and_then(|offset| rela_address.checked_add(offset))
+                .and_then(|address| loaded.range_to_phys(address, rela_entry_size))
+                .ok_or("ELF relocation table is not in a loaded segment")?;
+            let rela = unsafe { ptr::read_unaligned((rela_phys + phys_offset) as *const Rela) };
+
+            let relocation_type = rela.info as u32;
+            if relocation_type == R_NONE {
+                continue;
+            }
+            if relocation_type != relative_type {
+                return Err("ELF file has an unsupported relocation type");
+            }
+
+            let target_phys = loaded
+                .range_to_phys(rela.offset.wrapping_add(load_bias), 8)
+                .ok_or("ELF relocation target is not in a loaded segment")?;
+            let value = load_bias.wrapping_add(rela.addend as u64);
+            unsafe { ptr::write_unaligned((target_phys + phys_offset) as *mut u64, value) };
+        }
+
+        Ok(())
+    }
+}
+
+impl LoadedElf {
+    pub fn segments(&self) -> &[LoadedSegment] {
+        &self.segments[..self.segment_count]
+    }
+
+    /// Returns the physical address that a loaded virtual address was copied to.
+    pub fn virt_to_phys(&self, virt: u64) -> Option<u64> {
+        self.segments()
+            .iter()
+            .find(|segment| virt >= segment.virt_start && virt < segment.virt_start + segment.size)
+            .map(|segment| segment.phys_start + (virt - segment.virt_start))
+    }
+
+    /// Returns the physical address that `size` bytes at a loaded virtual address were copied to,
+    /// or `None` if they are not all in the same segment.
+    pub fn range_to_phys(&self, virt: u64, size: u64) -> Option<u64> {
+        let end = virt.checked_add(size)?;
+
+        self.segments()
+            .iter()
+            .find(|segment| virt >= segment.virt_start && end <= segment.virt_start + segment.size)
+            .map(|segment| segment.phys_start + (virt - segment.virt_start))
+    }
+
+    /// Returns the page aligned start and end of the virtual range covered by all segments.
+    pub fn virtual_range(&self) -> (u64, u64) {
+        let start = self
+            .segments()
+            .iter()
+            .map(|s| s.virt_start)
+            .min()
+            .unwrap_or(0);
+        let end = self
+            .segments()
+            .iter()
+            .map(|s| s.virt_start + s.size)
+            .max()
+            .unwrap_or(0);
+
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::BumpFrameAllocator;
+
+    const DYNAMIC_OFFSET: u64 = 0x200;
+    const RELA_OFFSET: u64 = 0x300;
+    const LOAD_BIAS: u64 = 0x20_0000;
+    const ADDEND: i64 = 0x1234;
+
+    fn put<T: Copy>(data: &mut [u8], offset: u64, value: T) {
+        let offset = offset as usize;
+        assert!(offset + mem::size_of::<T>() <= data.len());
+        unsafe { ptr::write_unaligned(data.as_mut_ptr().add(offset) as *mut T, value) };
+    }
+
+    /// Builds a position independent x86_64 ELF file with one page sized, page aligned `PT_LOAD`
+    /// segment, and a `PT_DYNAMIC` segment inside it that points to one relative relocation.
+    fn pie(rela_address: u64, target: u64) -> Vec<u8> {
+        let mut data = vec![0; PAGE_SIZE as usize];
+
+        let mut ident = [0; 16];
+        ident[..4].copy_from_slice(&ELF_MAGIC);
+        ident[4] = ELF_CLASS_64;
+        ident[5] = ELF_DATA_LITTLE_ENDIAN;
+        ident[6] = ELF_VERSION_CURRENT;
+        put(
+            &mut data,
+            0,
+            ElfHeader {
+                ident,
+                elf_type: ET_DYN,
+                machine: EM_X86_64,
+                version: 1,
+                entry: 0x100,
+                program_header_offset: mem::size_of::<ElfHeader>() as u64,
+                section_header_offset: 0,
+                flags: 0,
+                header_size: mem::size_of::<ElfHeader>() as u16,
+                program_header_size: mem::size_of::<ProgramHeader>() as u16,
+                program_header_count: 2,
+                section_header_size: 0,
+                section_header_count: 0,
+                section_name_index: 0,
+            },
+        );
+
+        let headers = [
+            ProgramHeader {
+                segment_type: PT_LOAD,
+                flags: PF_R | PF_W,
+                offset: 0,
+                virtual_address: 0,
+                physical_address: 0,
+                file_size: PAGE_SIZE,
+                memory_size: PAGE_SIZE,
+                alignment: PAGE_SIZE,
+            },
+            ProgramHeader {
+                segment_type: PT_DYNAMIC,
+                flags: PF_R | PF_W,
+                offset: DYNAMIC_OFFSET,
+                virtual_address: DYNAMIC_OFFSET,
+                physical_address: DYNAMIC_OFFSET,
+                file_size: 3 * mem::size_of::<DynamicEntry>() as u64,
+                memory_size: 3 * mem::size_of::<DynamicEntry>() as u64,
+                alignment: 8,
+            },
+        ];
+        for (i, header) in headers.into_iter().enumerate() {
+            let offset = mem::size_of::<ElfHeader>() + i * mem::size_of::<ProgramHeader>();
+            put(&mut data, offset as u64, header);
+        }
+
+        let dynamic = [
+            DynamicEntry {
+                tag: DT_RELA,
+                value: rela_address,
+            },
+            DynamicEntry {
+                tag: DT_RELASZ,
+                value: mem::size_of::<Rela>() as u64,
+            },
+            DynamicEntry {
+                tag: DT_NULL,
+                value: 0,
+            },
+        ];
+        for (i, entry) in dynamic.into_iter().enumerate() {
+            let offset = DYNAMIC_OFFSET + (i * mem::size_of::<DynamicEntry>()) as u64;
+            put(&mut data, offset, entry);
+        }
+
+        let rela = Rela {
+            offset: target,
+            info: R_X86_64_RELATIVE as u64,
+            addend: ADDEND,
+        };
+        if let Some(end) = (rela_address as usize).checked_add(mem::size_of::<Rela>()) {
+            if end <= data.len() {
+                put(&mut data, rela_address, rela);
+            }
+        }
+
+        data
+    }
+
+    /// Loads `data` into frames that are backed by `memory`, which stands in for physical memory.
+    fn load(data: &[u8], memory: &mut [u8]) -> Result<LoadedElf, Error> {
+        let elf = ElfFile::parse_for_machine(data, EM_X86_64)?;
+        let mut allocator = BumpFrameAllocator::new(0, memory.len() as u64);
+
+        elf.load(LOAD_BIAS, &mut allocator, memory.as_mut_ptr() as u64)
+    }
+
+    #[test]
+    fn applies_a_relocation_at_the_end_of_a_segment() {
+        let target = PAGE_SIZE - 8;
+        let data = pie(RELA_OFFSET, target);
+        let mut memory = vec![0xaa; 2 * PAGE_SIZE as usize];
+
+        let loaded = load(&data, &mut memory).unwrap();
+        assert_eq!(loaded.segments().len(), 1);
+        assert_eq!(loaded.entry_point, LOAD_BIAS + 0x100);
+
+        let phys = loaded.range_to_phys(LOAD_BIAS + target, 8).unwrap() as usize;
+        let value = u64::from_le_bytes(memory[phys..phys + 8].try_into().unwrap());
+        assert_eq!(value, LOAD_BIAS + ADDEND as u64);
+        // The byte after the segment is not written
+        assert_eq!(memory[phys + 8], 0xaa);
+    }
+
+    #[test]
+    fn rejects_a_relocation_target_that_crosses_the_end_of_a_segment() {
+        for target in [
+            PAGE_SIZE - 7,
+            PAGE_SIZE,
+            (u64::MAX - 3).wrapping_sub(LOAD_BIAS),
+        ] {
+            let data = pie(RELA_OFFSET, target);
+            let mut memory = vec![0xaa; 2 * PAGE_SIZE as usize];
+
+            let result = load(&data, &mut memory);
+            assert_eq!(
+                result.err(),
+                Some("ELF relocation target is not in a loaded segment")
+            );
+            assert!(memory[PAGE_SIZE as usize..]
+                .iter()
+                .all(|byte| *byte == 0xaa));
+        }
+    }
+
+    #[test]
+    fn rejects_a_relocation_table_that_crosses_the_end_of_a_segment() {
+        for rela_address in [PAGE_SIZE - 16, (u64::MAX - 8).wrapping_sub(LOAD_BIAS)] {
+            let data = pie(rela_address, 0x800);
+            let mut memory = vec![0; 2 * PAGE_SIZE as usize];
+
+            let result = load(&data, &mut memory);
+            assert_eq!(
+                result.err(),
+                Some("ELF relocation table is not in a loaded segment")
+            );
+        }
+    }
+
+    #[test]
+    fn finds_ranges_only_inside_one_segment() {
+        let data = pie(RELA_OFFSET, 0x800);
+        let mut memory = vec![0; 2 * PAGE_SIZE as usize];
+        let loaded = load(&data, &mut memory).unwrap();
+
+        assert_eq!(loaded.range_to_phys(LOAD_BIAS, PAGE_SIZE), Some(0));
+        assert_eq!(loaded.range_to_phys(LOAD_BIAS + 1, PAGE_SIZE), None);
+        assert_eq!(loaded.range_to_phys(LOAD_BIAS - 1, 2), None);
+        assert_eq!(loaded.range_to_phys(u64::MAX, 2), None);
+    }
+}
@@ -0,0 +1,928 @@
+// A reader for flattened device trees, as passed by QEMU and firmware on aarch64 and riscv64.
+// Everything in the blob is big endian, and nothing is copied out of it.
+
+use core::{mem, slice, str};
+
+pub type Error = &'static str;
+
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// Oldest structure block version that this parser understands.
+const FDT_MIN_VERSION: u32 = 16;
+
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+#[derive(Copy, Clone, Debug)]
+pub struct FdtHeader {
+    pub magic: u32,
+    pub total_size: u32,
+    pub structure_offset: u32,
+    pub strings_offset: u32,
+    pub memory_reservation_offset: u32,
+    pub version: u32,
+    pub last_compatible_version: u32,
+    pub boot_cpu_id: u32,
+    pub strings_size: u32,
+    pub structure_size: u32,
+}
+
+#[derive(Copy, Clone)]
+pub struct Fdt<'a> {
+    header: FdtHeader,
+    structure: &'a [u8],
+    strings: &'a [u8],
+    memory_reservations: &'a [u8],
+}
+
+#[derive(Copy, Clone)]
+pub struct FdtNode<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset into the structure block of the first token after the node name.
+    offset: usize,
+    /// Cells that the parent uses for the `reg` of this node.
+    address_cells: u32,
+    size_cells: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FdtProperty<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// An address range, such as an entry of a `reg` property or a memory reservation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FdtRegion {
+    pub address: u64,
+    pub size: u64,
+}
+
+pub struct FdtPropertyIter<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+pub struct FdtChildIter<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    address_cells: u32,
+    size_cells: u32,
+}
+
+pub struct FdtRegIter<'a> {
+    value: &'a [u8],
+    address_cells: u32,
+    size_cells: u32,
+}
+
+pub struct FdtMemoryReservationIter<'a> {
+    entries: &'a [u8],
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a null terminated string starting at `offset`.
+fn c_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let length = bytes.iter().position(|&byte| byte == 0)?;
+
+    str::from_utf8(&bytes[..length]).ok()
+}
+
+fn align_token(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Reads a number made of `cells` 32 bit cells. Only up to 2 cells fit in a `u64`.
+fn read_cells(bytes: &[u8], cells: u32) -> Option<u64> {
+    match cells {
+        0 => Some(0),
+        1 => be_u32(bytes, 0).map(|value| value as u64),
+        2 => be_u64(bytes, 0),
+        _ => None,
+    }
+}
+
+impl<'a> Fdt<'a> {
+    /// Validates the header of the device tree blob in `data`.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let too_short = "Device tree is too short";
+        let field = |index: usize| be_u32(data, index * mem::size_of::<u32>()).ok_or(too_short);
+
+        let header = FdtHeader {
+            magic: field(0)?,
+            total_size: field(1)?,
+            structure_offset: field(2)?,
+            strings_offset: field(3)?,
+            memory_reservation_offset: field(4)?,
+            version: field(5)?,
+            last_compatible_version: field(6)?,
+            boot_cpu_id: field(7)?,
+            strings_size: field(8)?,
+            structure_size: field(9)?,
+        };
+
+        if header.magic != FDT_MAGIC {
+            return Err("Device tree has an invalid magic number");
+        }
+        if header.last_compatible_version > FDT_MIN_VERSION || header.version < FDT_MIN_VERSION {
+            return Err("Unsupported device tree version");
+        }
+        if header.total_size as usize > data.len() {
+            return Err(too_short);
+        }
+
+        let data = &data[..header.total_size as usize];
+        let block = |offset: u32, size: u32| {
+            let start = offset as usize;
+            data.get(start..start + size as usize)
+                .ok_or("Device tree block is out of bounds")
+        };
+
+        let structure = block(header.structure_offset, header.structure_size)?;
+        let strings = block(header.strings_offset, header.strings_size)?;
+        let memory_reservations = data
+            .get(header.memory_reservation_offset as usize..)
+            .ok_or("Device tree block is out of bounds")?;
+
+        Ok(Self {
+            header,
+            structure,
+            strings,
+            memory_reservations,
+        })
+    }
+
+    /// Validates the header of the device tree blob at `address`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to a readable device tree blob that stays valid for `'a`, such as the
+    /// one passed to the bootloader by firmware.
+    pub unsafe fn from_ptr(address: *const u8) -> Result<Self, Error> {
+        if address.is_null() {
+            return Err("Device tree pointer is null");
+        }
+
+        // Read just the total size first, so that nothing past the end of the blob is touched
+        let header = slice::from_raw_parts(address, 2 * mem::size_of::<u32>());
+        if be_u32(header, 0) != Some(FDT_MAGIC) {
+            return Err("Device tree has an invalid magic number");
+        }
+        let total_size = be_u32(header, 4).ok_or("Device tree is too short")?;
+
+        Self::new(slice::from_raw_parts(address, total_size as usize))
+    }
+
+    pub fn header(&self) -> &FdtHeader {
+        &self.header
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.header.total_size as usize
+    }
+
+    pub fn root(&self) -> Result<FdtNode<'a>, Error> {
+        let mut offset = 0;
+
+        // Skip any NOPs before the root node
+        while be_u32(self.structure, offset) == Some(FDT_NOP) {
+            offset += 4;
+        }
+        if be_u32(self.structure, offset) != Some(FDT_BEGIN_NODE) {
+            return Err("Device tree does not start with a node");
+        }
+
+        self.node_at(offset + 4, DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS)
+            .ok_or("Device tree root node is malformed")
+    }
+
+    /// Builds the node whose name starts at `offset`.
+    fn node_at(&self, offset: usize, address_cells: u32, size_cells: u32) -> Option<FdtNode<'a>> {
+        let name = c_str(self.structure, offset)?;
+
+        Some(FdtNode {
+            fdt: *self,
+            name,
+            offset: align_token(offset + name.len() + 1),
+            address_cells,
+            size_cells,
+        })
+    }
+
+    /// Returns the offset of the token after the one at `offset`, or `None` at the end.
+    fn next_token(&self, offset: usize) -> Option<usize> {
+        match be_u32(self.structure, offset)? {
+            FDT_BEGIN_NODE => {
+                let name = c_str(self.structure, offset + 4)?;
+                Some(align_token(offset + 4 + name.len() + 1))
+            }
+            FDT_PROP => {
+                let length = be_u32(self.structure, offset + 4)? as usize;
+                Some(align_token(offset + 12 + length))
+            }
+            FDT_END_NODE | FDT_NOP => Some(offset + 4),
+            _ => None,
+        }
+    }
+
+    /// Returns the offset just past the `FDT_END_NODE` of the node whose contents start at
+    /// `offset`.
+    fn skip_node(&self, mut offset: usize) -> Option<usize> {
+        let mut depth = 0usize;
+
+        loop {
+            match be_u32(self.structure, offset)? {
+                FDT_BEGIN_NODE => depth += 1,
+                FDT_END_NODE if depth == 0 => return Some(offset + 4),
+                FDT_END_NODE => depth -= 1,
+                FDT_END => return None,
+                _ => {}
+            }
+            offset = self.next_token(offset)?;
+        }
+    }
+
+    fn string(&self, offset: u32) -> Option<&'a str> {
+        c_str(self.strings, offset as usize)
+    }
+
+    /// Finds a node by its full path, such as `/chosen` or `/soc/uart@10000000`. A path component
+    /// without a unit address also matches nodes that have one, so `/memory` finds
+    /// `/memory@80000000`.
+    pub fn find_node(&self, path: &str) -> Option<FdtNode<'a>> {
+        let mut node = self.root().ok()?;
+
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            node = node.child(component)?;
+        }
+
+        Some(node)
+    }
+
+    /// Finds the first node, in depth first order, that is compatible with `compatible`.
+    pub fn find_compatible(&self, compatible: &str) -> Option<FdtNode<'a>> {
+        fn search<'a>(node: FdtNode<'a>, compatible: &str) -> Option<FdtNode<'a>> {
+            if node.is_compatible(compatible) {
+                return Some(node);
+            }
+
+            node.children().find_map(|child| search(child, compatible))
+        }
+
+        search(self.root().ok()?, compatible)
+    }
+
+    /// Finds the first node that is compatible with any of `compatibles`, preferring earlier
+    /// entries.
+    pub fn find_any_compatible(&self, compatibles: &[&str]) -> Option<FdtNode<'a>> {
+        compatibles
+            .iter()
+            .find_map(|compatible| self.find_compatible(compatible))
+    }
+
+    /// Returns the `reg` ranges of every `/memory` node.
+    pub fn memory_regions(&self) -> impl Iterator<Item = FdtRegion> + 'a {
+        self.root()
+            .ok()
+            .into_iter()
+            .flat_map(|root| root.children())
+            .filter(|node| {
+                node.property("device_type")
+                    .and_then(|property| property.as_str())
+                    == Some("memory")
+                    || node.base_name() == "memory"
+            })
+            .flat_map(|node| node.reg().into_iter().flatten())
+    }
+
+    /// Returns the nodes under `/cpus` whose `device_type` is `cpu`. Their first `reg` address is
+    /// the hart ID on RISC-V and the MPIDR affinity on ARM.
+    pub fn cpus(&self) -> impl Iterator<Item = FdtNode<'a>> + 'a {
+        self.find_node("/cpus")
+            .into_iter()
+            .flat_map(|cpus| cpus.children())
+            .filter(|node| {
+                node.property("device_type")
+                    .and_then(|property| property.as_str())
+                    == Some("cpu")
+            })
+    }
+
+    /// Returns the memory reservation block, which lists memory that must not be used.
+    pub fn memory_reservations(&self) -> FdtMemoryReservationIter<'a> {
+        FdtMemoryReservationIter {
+            entries: self.memory_reservations,
+        }
+    }
+
+    /// Returns `/chosen/stdout-path` without any options after a `:`, such as `115200n8`.
+    pub fn stdout_path(&self) -> Option<&'a str> {
+        let chosen = self.find_node("/chosen")?;
+        let path = chosen
+            .property("stdout-path")
+            .or_else(|| chosen.property("linux,stdout-path"))?
+            .as_str()?;
+
+        path.split(':').next()
+    }
+
+    /// Returns the node that `/chosen/stdout-path` refers to, resolving it through `/aliases` if
+    /// it is not a full path.
+    pub fn stdout_node(&self) -> Option<FdtNode<'a>> {
+        let path = self.stdout_path()?;
+
+        if path.starts_with('/') {
+            return self.find_node(path);
+        }
+
+        let alias = self.find_node("/aliases")?.property(path)?.as_str()?;
+        self.find_node(alias)
+    }
+
+    /// Returns the `bootargs` that were given to the bootloader, such as QEMU's `-append`.
+    pub fn bootargs(&self) -> Option<&'a str> {
+        self.find_node("/chosen")?.property("bootargs")?.as_str()
+    }
+}
+
+impl<'a> FdtNode<'a> {
+    /// Returns the full name, including any unit address, such as `uart@10000000`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the name without the unit address, such as `uart`.
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    /// Returns the unit address, such as `10000000`.
+    pub fn unit_address(&self) -> Option<&'a str> {
+        self.name.split_once('@').map(|(_, address)| address)
+    }
+
+    pub fn properties(&self) -> FdtPropertyIter<'a> {
+        FdtPropertyIter {
+            fdt: self.fdt,
+            offset: self.offset,
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<FdtProperty<'a>> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    pub fn children(&self) -> FdtChildIter<'a> {
+        FdtChildIter {
+            fdt: self.fdt,
+            offset: self.offset,
+            address_cells: self.child_address_cells(),
+            size_cells: self.child_size_cells(),
+        }
+    }
+
+    /// Finds a direct child by name. `name` matches a child with a unit address if it does not
+    /// have one itself.
+    pub fn child(&self, name: &str) -> Option<FdtNode<'a>> {
+        self.children()
+            .find(|child| child.name == name || (!name.contains('@') && child.base_name() == name))
+    }
+
+    /// Returns the `#address-cells` that children of this node use.
+    pub fn child_address_cells(&self) -> u32 {
+        self.property("#address-cells")
+            .and_then(|property| property.as_u32())
+            .unwrap_or(DEFAULT_ADDRESS_CELLS)
+    }
+
+    /// Returns the `#size-cells` that children of this node use.
+    pub fn child_size_cells(&self) -> u32 {
+        self.property("#size-cells")
+            .and_then(|property| property.as_u32())
+            .unwrap_or(DEFAULT_SIZE_CELLS)
+    }
+
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible")
+            .into_iter()
+            .flat_map(|property| property.as_str_list())
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|entry| entry == compatible)
+    }
+
+    /// Returns the address ranges in `reg`, interpreted with the parent's cell sizes. Addresses
+    /// are in the parent's address space and are not translated through `ranges`.
+    pub fn reg(&self) -> Option<FdtRegIter<'a>> {
+        Some(FdtRegIter {
+            value: self.property("reg")?.value,
+            address_cells: self.address_cells,
+            size_cells: self.size_cells,
+        })
+    }
+
+    /// Returns the first `reg` range.
+    pub fn first_reg(&self) -> Option<FdtRegion> {
+        self.reg()?.next()
+    }
+
+    /// Returns the handle that other nodes use to refer to this one, such as in
+    /// `interrupts-extended`.
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle")
+            .or_else(|| self.property("linux,phandle"))?
+            .as_u32()
+    }
+
+    /// Returns `status`, where a missing property means the device is enabled.
+    pub fn is_enabled(&self) -> bool {
+        match self
+            .property("status")
+            .and_then(|property| property.as_str())
+        {
+            Some(status) => status == "okay" || status == "ok",
+            None => true,
+        }
+    }
+}
+
+impl<'a> FdtProperty<'a> {
+    pub fn as_u32(&self) -> Option<u32> {
+        be_u32(self.value, 0)
+    }
+
+    /// Reads a single 64 bit value, or a 32 bit value if the property is only one cell.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => self.as_u32().map(|value| value as u64),
+            _ => be_u64(self.value, 0),
+        }
+    }
+
+    /// Returns the value as a string if it is a single null terminated string.
+    pub fn as_str(&self) -> Option<&'a str> {
+        let (last, string) = self.value.split_last()?;
+        if *last != 0 {
+            return None;
+        }
+
+        str::from_utf8(string).ok()
+    }
+
+    /// Returns each string of a list of null terminated strings, such as `compatible`.
+    pub fn as_str_list(&self) -> impl Iterator<Item = &'a str> {
+        let value = self.value.strip_suffix(&[0]).unwrap_or(&[]);
+
+        value
+            .split(|&byte| byte == 0)
+            .filter_map(|string| str::from_utf8(string).ok())
+    }
+
+    /// Returns each 32 bit cell of the value.
+    pub fn as_cells(&self) -> impl Iterator<Item = u32> + 'a {
+        self.value
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|cell| u32::from_be_bytes(*cell))
+    }
+}
+
+impl<'a> Iterator for FdtPropertyIter<'a> {
+    type Item = FdtProperty<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match be_u32(self.fdt.structure, self.offset)? {
+                FDT_NOP => self.offset += 4,
+                FDT_PROP => {
+                    let length = be_u32(self.fdt.structure, self.offset + 4)? as usize;
+                    let name_offset = be_u32(self.fdt.structure, self.offset + 8)?;
+                    let start = self.offset + 12;
+                    let value = self.fdt.structure.get(start..start.checked_add(length)?)?;
+
+                    self.offset = align_token(start + length);
+
+                    return Some(FdtProperty {
+                        name: self.fdt.string(name_offset)?,
+                        value,
+                    });
+                }
+                // Properties always come before child nodes
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for FdtChildIter<'a> {
+    type Item = FdtNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match be_u32(self.fdt.structure, self.offset)? {
+                FDT_BEGIN_NODE => {
+                    let node =
+                        self.fdt
+                            .node_at(self.offset + 4, self.address_cells, self.size_cells)?;
+                    self.offset = self.fdt.skip_node(node.offset)?;
+
+                    return Some(node);
+                }
+                FDT_PROP | FDT_NOP => self.offset = self.fdt.next_token(self.offset)?,
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl Iterator for FdtRegIter<'_> {
+    type Item = FdtRegion;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address_size = self.address_cells as usize * 4;
+        let entry_size = address_size + self.size_cells as usize * 4;
+        if entry_size == 0 || self.value.len() < entry_size {
+            return None;
+        }
+
+        let entry = &self.value[..entry_size];
+        self.value = &self.value[entry_size..];
+
+        Some(FdtRegion {
+            address: read_cells(entry, self.address_cells)?,
+            size: read_cells(&entry[address_size..], self.size_cells)?,
+        })
+    }
+}
+
+impl Iterator for FdtMemoryReservationIter<'_> {
+    type Item = FdtRegion;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = be_u64(self.entries, 0)?;
+        let size = be_u64(self.entries, 8)?;
+
+        // The list ends with an entry that is all zeros
+        if address == 0 && size == 0 {
+            self.entries = &[];
+            return None;
+        }
+        self.entries = &self.entries[16..];
+
+        Some(FdtRegion { address, size })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIAL: &str = "/soc/serial@10000000";
+
+    /// Writes a device tree blob, one token at a time.
+    #[derive(Default)]
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structure.extend(token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            self.structure.resize(align_token(self.structure.len()), 0);
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structure.extend(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn property(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend(name.as_bytes());
+            self.strings.push(0);
+
+            self.token(FDT_PROP)
+                .token(value.len() as u32)
+                .token(name_offset);
+            self.structure.extend(value);
+            self.pad();
+            self
+        }
+
+        fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            self.property(name, &value)
+        }
+
+        fn string(&mut self, name: &str, value: &str) -> &mut Self {
+            self.property(name, format!("{value}\0").as_bytes())
+        }
+
+        /// Lays out the header, the memory reservation block, the structure block and the strings
+        /// block, in that order.
+        fn finish(&mut self, reservations: &[FdtRegion]) -> Vec<u8> {
+            self.token(FDT_END);
+
+            let header_size = 10 * mem::size_of::<u32>();
+            let reservations_offset = header_size.next_multiple_of(8);
+            let structure_offset = reservations_offset + (reservations.len() + 1) * 16;
+            let strings_offset = structure_offset + self.structure.len();
+            let total_size = strings_offset + self.strings.len();
+
+            let header = [
+                FDT_MAGIC,
+                total_size as u32,
+                structure_offset as u32,
+                strings_offset as u32,
+                reservations_offset as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ];
+
+            let mut data: Vec<u8> = header
+                .iter()
+                .flat_map(|field| field.to_be_bytes())
+                .collect();
+            data.resize(reservations_offset, 0);
+            for region in reservations.iter().chain(&[FdtRegion {
+                address: 0,
+                size: 0,
+            }]) {
+                data.extend(region.address.to_be_bytes());
+                data.extend(region.size.to_be_bytes());
+            }
+            data.extend(&self.structure);
+            data.extend(&self.strings);
+            data
+        }
+    }
+
+    /// A tree laid out like the one QEMU generates for the riscv64 `virt` machine, cut down to
+    /// the nodes the bootloaders look at. `/platform` has no cell properties so that its children
+    /// fall back to the defaults, and the console is found through `/aliases`.
+    fn virt(stdout_path: &str) -> Vec<u8> {
+        let mut builder = Builder::default();
+        builder
+            .begin("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .string("compatible", "riscv-virtio")
+            .begin("chosen")
+            .string("bootargs", "console=ttyS0 quiet")
+            .string("stdout-path", stdout_path)
+            .end()
+            .begin("aliases")
+            .string("serial0", SERIAL)
+            .end()
+            .begin("memory@80000000")
+            .string("device_type", "memory")
+            .cells("reg", &[0, 0x8000_0000, 0, 0x4000_0000])
+            .end()
+            .begin("memory@100000000")
+            .string("device_type", "memory")
+            .cells("reg", &[1, 0, 0, 0x1000_0000])
+            .end()
+            .begin("cpus")
+            .cells("#address-cells", &[1])
+            .cells("#size-cells", &[0])
+            .begin("cpu@0")
+            .string("device_type", "cpu")
+            .cells("reg", &[0])
+            .string("status", "okay")
+            .end()
+            .begin("cpu@1")
+            .string("device_type", "cpu")
+            .cells("reg", &[1])
+            .string("status", "disabled")
+            .end()
+            .begin("cpu-map")
+            .end()
+            .begin("cpu@2")
+            .string("device_type", "cpu")
+            .cells("reg", &[2])
+            .end()
+            .end()
+            .begin("soc")
+            .cells("#address-cells", &[1])
+            .cells("#size-cells", &[1])
+            .string("compatible", "simple-bus")
+            .begin("plic@c000000")
+            .property("compatible", b"sifive,plic-1.0.0\0riscv,plic0\0")
+            .cells("phandle", &[3])
+            .cells("reg", &[0xc00_0000, 0x60_0000])
+            .end()
+            .begin("serial@10000000")
+            .string("compatible", "ns16550a")
+            .cells("reg", &[0x1000_0000, 0x100])
+            .end()
+            .end()
+            .begin("platform")
+            .begin("device@20")
+            .cells("reg", &[0, 0x20, 0x1000])
+            .end()
+            .end()
+            .end();
+
+        builder.finish(&[FdtRegion {
+            address: 0x8000_0000,
+            size: 0x20_0000,
+        }])
+    }
+
+    #[test]
+    fn rejects_a_bad_header() {
+        let mut data = virt(SERIAL);
+        assert!(Fdt::new(&data).is_ok());
+
+        assert_eq!(
+            Fdt::new(&data[..20]).err(),
+            Some("Device tree is too short")
+        );
+        assert_eq!(
+            Fdt::new(&data[..data.len() - 1]).err(),
+            Some("Device tree is too short")
+        );
+
+        data[20..24].copy_from_slice(&15u32.to_be_bytes());
+        assert_eq!(
+            Fdt::new(&data).err(),
+            Some("Unsupported device tree version")
+        );
+
+        data[0] = 0;
+        assert_eq!(
+            Fdt::new(&data).err(),
+            Some("Device tree has an invalid magic number")
+        );
+        assert_eq!(
+            unsafe { Fdt::from_ptr(data.as_ptr()) }.err(),
+            Some("Device tree has an invalid magic number")
+        );
+    }
+
+    #[test]
+    fn rejects_blocks_past_the_end_of_the_tree() {
+        let mut data = virt(SERIAL);
+        let strings_offset = u32::from_be_bytes(data[12..16].try_into().unwrap());
+        data[12..16].copy_from_slice(&(strings_offset + 1).to_be_bytes());
+
+        assert_eq!(
+            Fdt::new(&data).err(),
+            Some("Device tree block is out of bounds")
+        );
+    }
+
+    #[test]
+    fn finds_memory_regions_and_reservations() {
+        let data = virt(SERIAL);
+        let fdt = unsafe { Fdt::from_ptr(data.as_ptr()) }.unwrap();
+
+        assert_eq!(fdt.total_size(), data.len());
+        assert_eq!(
+            fdt.memory_regions().collect::<Vec<_>>(),
+            [
+                FdtRegion {
+                    address: 0x8000_0000,
+                    size: 0x4000_0000
+                },
+                FdtRegion {
+                    address: 0x1_0000_0000,
+                    size: 0x1000_0000
+                },
+            ]
+        );
+        assert_eq!(
+            fdt.memory_reservations().collect::<Vec<_>>(),
+            [FdtRegion {
+                address: 0x8000_0000,
+                size: 0x20_0000
+            }]
+        );
+    }
+
+    #[test]
+    fn finds_the_stdout_node() {
+        let data = virt("serial0:115200n8");
+        let fdt = Fdt::new(&data).unwrap();
+
+        assert_eq!(fdt.stdout_path(), Some("serial0"));
+        assert_eq!(fdt.bootargs(), Some("console=ttyS0 quiet"));
+
+        let serial = fdt.stdout_node().unwrap();
+        assert_eq!(serial.name(), "serial@10000000");
+        assert_eq!(serial.base_name(), "serial");
+        assert_eq!(serial.unit_address(), Some("10000000"));
+
+        let data = virt(SERIAL);
+        let fdt = Fdt::new(&data).unwrap();
+        assert_eq!(fdt.stdout_node().unwrap().name(), "serial@10000000");
+
+        let data = virt("serial1");
+        assert!(Fdt::new(&data).unwrap().stdout_node().is_none());
+    }
+
+    #[test]
+    fn reads_reg_with_the_cells_of_the_parent() {
+        let data = virt(SERIAL);
+        let fdt = Fdt::new(&data).unwrap();
+
+        let soc = fdt.find_node("/soc").unwrap();
+        assert_eq!((soc.child_address_cells(), soc.child_size_cells()), (1, 1));
+        assert_eq!(
+            fdt.find_node(SERIAL).unwrap().first_reg(),
+            Some(FdtRegion {
+                address: 0x1000_0000,
+                size: 0x100
+            })
+        );
+
+        // Cell counts are not inherited, so `/platform` uses the defaults of 2 and 1
+        let platform = fdt.find_node("/platform").unwrap();
+        assert_eq!(
+            (platform.child_address_cells(), platform.child_size_cells()),
+            (2, 1)
+        );
+        assert_eq!(
+            fdt.find_node("/platform/device").unwrap().first_reg(),
+            Some(FdtRegion {
+                address: 0x20,
+                size: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn finds_compatible_nodes() {
+        let data = virt(SERIAL);
+        let fdt = Fdt::new(&data).unwrap();
+
+        let plic = fdt.find_compatible("riscv,plic0").unwrap();
+        assert_eq!(plic.name(), "plic@c000000");
+        assert_eq!(
+            plic.compatible().collect::<Vec<_>>(),
+            ["sifive,plic-1.0.0", "riscv,plic0"]
+        );
+        assert_eq!(plic.phandle(), Some(3));
+        assert_eq!(
+            plic.first_reg(),
+            Some(FdtRegion {
+                address: 0xc00_0000,
+                size: 0x60_0000
+            })
+        );
+
+        assert!(fdt.find_compatible("arm,pl011").is_none());
+        assert_eq!(
+            fdt.find_any_compatible(&["arm,pl011", "ns16550a", "riscv,plic0"])
+                .unwrap()
+                .name(),
+            "serial@10000000"
+        );
+    }
+
+    #[test]
+    fn lists_cpus() {
+        let data = virt(SERIAL);
+        let fdt = Fdt::new(&data).unwrap();
+
+        let cpus: Vec<_> = fdt
+            .cpus()
+            .map(|cpu| (cpu.first_reg().unwrap().address, cpu.is_enabled()))
+            .collect();
+        assert_eq!(cpus, [(0, true), (1, false), (2, true)]);
+    }
+}
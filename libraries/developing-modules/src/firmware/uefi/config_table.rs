@@ -0,0 +1,73 @@
+use core::ffi::c_void;
+
+use crate::firmware::uefi::memory_map::{UefiGuid, UefiSystemTable};
+
+pub const ACPI_20_TABLE_GUID: UefiGuid = UefiGuid::new(
+    0x8868e871,
+    0xe4f1,
+    0x11d3,
+    [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81],
+);
+pub const ACPI_TABLE_GUID: UefiGuid = UefiGuid::new(
+    0xeb9d2d30,
+    0x2d88,
+    0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+);
+pub const SMBIOS_TABLE_GUID: UefiGuid = UefiGuid::new(
+    0xeb9d2d31,
+    0x2d88,
+    0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+);
+pub const SMBIOS3_TABLE_GUID: UefiGuid = UefiGuid::new(
+    0xf2fd1544,
+    0x9794,
+    0x4a2c,
+    [0x99, 0x2e, 0xe5, 0xbb, 0xcf, 0x20, 0xe3, 0x94],
+);
+pub const DEVICE_TREE_GUID: UefiGuid = UefiGuid::new(
+    0xb1b621d5,
+    0xf19c,
+    0x41a5,
+    [0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0],
+);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiConfigurationTable {
+    pub vendor_guid: UefiGuid,
+    pub vendor_table: *const c_void,
+}
+
+impl UefiSystemTable {
+    /// Returns the physical address of the configuration table identified by `guid`.
+    pub fn find_config_table(&self, guid: &UefiGuid) -> Option<u64> {
+        self.config_tables()
+            .iter()
+            .find(|table| table.vendor_guid == *guid)
+            .map(|table| table.vendor_table as u64)
+    }
+
+    /// Returns the physical address of the ACPI RSDP, preferring the ACPI 2.0 revision which has
+    /// the XSDT address.
+    pub fn acpi_rsdp(&self) -> Option<u64> {
+        self.find_config_table(&ACPI_20_TABLE_GUID)
+            .or_else(|| self.find_config_table(&ACPI_TABLE_GUID))
+    }
+
+    /// Returns the physical address of the 32 bit SMBIOS entry point.
+    pub fn smbios_entry_point(&self) -> Option<u64> {
+        self.find_config_table(&SMBIOS_TABLE_GUID)
+    }
+
+    /// Returns the physical address of the 64 bit SMBIOS 3 entry point.
+    pub fn smbios3_entry_point(&self) -> Option<u64> {
+        self.find_config_table(&SMBIOS3_TABLE_GUID)
+    }
+
+    /// Returns the physical address of the flattened device tree blob.
+    pub fn device_tree(&self) -> Option<u64> {
+        self.find_config_table(&DEVICE_TREE_GUID)
+    }
+}
@@ -0,0 +1,304 @@
+use core::{ffi::c_void, fmt};
+
+use crate::firmware::uefi::memory_map::{UefiStatus, NOT_READY, SUCCESS, UEFI_ERROR_BIT};
+
+/// Number of UCS-2 characters, including the null terminator, converted per `OutputString` call.
+const OUTPUT_CHUNK_LENGTH: usize = 128;
+
+#[repr(C)]
+pub struct UefiSimpleTextInputProtocol {
+    reset: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextInputProtocol,
+        extended_verification: bool,
+    ) -> UefiStatus,
+    read_key_stroke: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextInputProtocol,
+        key: *mut UefiInputKey,
+    ) -> UefiStatus,
+    wait_for_key: *const c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UefiInputKey {
+    pub scan_code: u16,
+    pub unicode_char: u16,
+}
+
+#[repr(C)]
+pub struct UefiSimpleTextOutputProtocol {
+    reset: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        extended_verification: bool,
+    ) -> UefiStatus,
+    output_string: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        string: *const u16,
+    ) -> UefiStatus,
+    test_string: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        string: *const u16,
+    ) -> UefiStatus,
+    query_mode: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        mode_number: u64,
+        columns: *mut u64,
+        rows: *mut u64,
+    ) -> UefiStatus,
+    set_mode: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        mode_number: u64,
+    ) -> UefiStatus,
+    set_attribute: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        attribute: u64,
+    ) -> UefiStatus,
+    clear_screen: unsafe extern "efiapi" fn(this: *mut UefiSimpleTextOutputProtocol) -> UefiStatus,
+    set_cursor_position: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        column: u64,
+        row: u64,
+    ) -> UefiStatus,
+    enable_cursor: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleTextOutputProtocol,
+        visible: bool,
+    ) -> UefiStatus,
+    mode: *const UefiSimpleTextOutputMode,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiSimpleTextOutputMode {
+    pub max_mode: i32,
+    pub mode: i32,
+    pub attribute: i32,
+    pub cursor_column: i32,
+    pub cursor_row: i32,
+    pub cursor_visible: bool,
+}
+
+/// Text colors. Only the first eight can be used as a background color.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UefiColor {
+    Black = 0x0,
+    Blue = 0x1,
+    Green = 0x2,
+    Cyan = 0x3,
+    Red = 0x4,
+    Magenta = 0x5,
+    Brown = 0x6,
+    LightGray = 0x7,
+    DarkGray = 0x8,
+    LightBlue = 0x9,
+    LightGreen = 0xa,
+    LightCyan = 0xb,
+    LightRed = 0xc,
+    LightMagenta = 0xd,
+    Yellow = 0xe,
+    White = 0xf,
+}
+
+/// A key read from the console. `Special` holds the UEFI scan code of keys that do not produce
+/// a character, such as the arrow and function keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UefiKey {
+    Printable(char),
+    Special(u16),
+}
+
+/// A console output device, such as `ConOut` or `StdErr` from the system table.
+pub struct UefiConsoleOut {
+    protocol: *mut UefiSimpleTextOutputProtocol,
+}
+
+/// A console input device, such as `ConIn` from the system table.
+pub struct UefiConsoleIn {
+    protocol: *mut UefiSimpleTextInputProtocol,
+}
+
+fn status_to_result(status: UefiStatus) -> Result<(), UefiStatus> {
+    if status != SUCCESS {
+        return Err(status);
+    }
+
+    Ok(())
+}
+
+impl UefiConsoleOut {
+    /// # Safety
+    ///
+    /// `protocol` must point to a valid Simple Text Output protocol, and boot services must not
+    /// be exited while this is in use.
+    pub unsafe fn new(protocol: *mut UefiSimpleTextOutputProtocol) -> Self {
+        Self { protocol }
+    }
+
+    pub fn reset(&mut self, extended_verification: bool) -> Result<(), UefiStatus> {
+        status_to_result(unsafe { ((*self.protocol).reset)(self.protocol, extended_verification) })
+    }
+
+    /// Writes `s` to the console, converting it to UCS-2 and expanding `\n` to `\r\n`. Characters
+    /// outside of UCS-2 are written as `?`.
+    pub fn output_str(&mut self, s: &str) -> Result<(), UefiStatus> {
+        let mut buffer = [0u16; OUTPUT_CHUNK_LENGTH];
+        let mut length = 0;
+
+        for c in s.chars() {
+            // Leave room for "\r\n" and the null terminator
+            if length + 3 > OUTPUT_CHUNK_LENGTH {
+                self.output_ucs2(&mut buffer, length)?;
+                length = 0;
+            }
+
+            if c == '\n' {
+                buffer[length] = '\r' as u16;
+                length += 1;
+            }
+
+            let mut encoded = [0u16; 2];
+            let encoded = c.encode_utf16(&mut encoded);
+            buffer[length] = if encoded.len() == 1 {
+                encoded[0]
+            } else {
+                '?' as u16
+            };
+            length += 1;
+        }
+
+        if length > 0 {
+            self.output_ucs2(&mut buffer, length)?;
+        }
+
+        Ok(())
+    }
+
+    fn output_ucs2(
+        &mut self,
+        buffer: &mut [u16; OUTPUT_CHUNK_LENGTH],
+        length: usize,
+    ) -> Result<(), UefiStatus> {
+        buffer[length] = 0;
+        let status = unsafe { ((*self.protocol).output_string)(self.protocol, buffer.as_ptr()) };
+
+        // Warnings, such as an unknown glyph, still print the rest of the string
+        if status & UEFI_ERROR_BIT != 0 {
+            return Err(status);
+        }
+
+        Ok(())
+    }
+
+    /// Clears the screen with the current background color and moves the cursor to (0, 0).
+    pub fn clear_screen(&mut self) -> Result<(), UefiStatus> {
+        status_to_result(unsafe { ((*self.protocol).clear_screen)(self.protocol) })
+    }
+
+    pub fn set_attribute(
+        &mut self,
+        foreground: UefiColor,
+        background: UefiColor,
+    ) -> Result<(), UefiStatus> {
+        let attribute = (foreground as u64) | (((background as u64) & 0x7) << 4);
+
+        status_to_result(unsafe { ((*self.protocol).set_attribute)(self.protocol, attribute) })
+    }
+
+    pub fn set_cursor_position(&mut self, column: usize, row: usize) -> Result<(), UefiStatus> {
+        status_to_result(unsafe {
+            ((*self.protocol).set_cursor_position)(self.protocol, column as u64, row as u64)
+        })
+    }
+
+    pub fn enable_cursor(&mut self, visible: bool) -> Result<(), UefiStatus> {
+        status_to_result(unsafe { ((*self.protocol).enable_cursor)(self.protocol, visible) })
+    }
+
+    /// Returns the number of columns and rows of text mode `mode`.
+    pub fn query_mode(&self, mode: usize) -> Result<(usize, usize), UefiStatus> {
+        let mut columns = 0;
+        let mut rows = 0;
+
+        status_to_result(unsafe {
+            ((*self.protocol).query_mode)(self.protocol, mode as u64, &mut columns, &mut rows)
+        })?;
+
+        Ok((columns as usize, rows as usize))
+    }
+
+    pub fn set_mode(&mut self, mode: usize) -> Result<(), UefiStatus> {
+        status_to_result(unsafe { ((*self.protocol).set_mode)(self.protocol, mode as u64) })
+    }
+
+    /// Returns the current mode, attribute and cursor state.
+    pub fn mode(&self) -> UefiSimpleTextOutputMode {
+        unsafe { *(*self.protocol).mode }
+    }
+
+    /// Switches to the supported text mode with the most cells and returns its index.
+    pub fn set_largest_mode(&mut self) -> Result<usize, UefiStatus> {
+        let mut largest = (0, 0);
+
+        for mode in 0..self.mode().max_mode as usize {
+            // Modes other than 0 and 1 are not required to be supported
+            if let Ok((columns, rows)) = self.query_mode(mode) {
+                if columns * rows > largest.1 {
+                    largest = (mode, columns * rows);
+                }
+            }
+        }
+
+        self.set_mode(largest.0)?;
+
+        Ok(largest.0)
+    }
+}
+
+impl fmt::Write for UefiConsoleOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.output_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl UefiConsoleIn {
+    /// # Safety
+    ///
+    /// `protocol` must point to a valid Simple Text Input protocol, and boot services must not be
+    /// exited while this is in use.
+    pub unsafe fn new(protocol: *mut UefiSimpleTextInputProtocol) -> Self {
+        Self { protocol }
+    }
+
+    pub fn reset(&mut self, extended_verification: bool) -> Result<(), UefiStatus> {
+        status_to_result(unsafe { ((*self.protocol).reset)(self.protocol, extended_verification) })
+    }
+
+    /// Returns the next key press, or `None` if no key is waiting.
+    pub fn read_key(&mut self) -> Result<Option<UefiKey>, UefiStatus> {
+        let mut key = UefiInputKey::default();
+        let status = unsafe { ((*self.protocol).read_key_stroke)(self.protocol, &mut key) };
+
+        match status {
+            SUCCESS => {}
+            NOT_READY => return Ok(None),
+            _ => return Err(status),
+        }
+
+        if key.unicode_char != 0 {
+            let c = char::from_u32(key.unicode_char as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+            Ok(Some(UefiKey::Printable(c)))
+        } else {
+            Ok(Some(UefiKey::Special(key.scan_code)))
+        }
+    }
+
+    /// Blocks until a key is pressed.
+    pub fn wait_for_key(&mut self) -> Result<UefiKey, UefiStatus> {
+        loop {
+            if let Some(key) = self.read_key()? {
+                return Ok(key);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
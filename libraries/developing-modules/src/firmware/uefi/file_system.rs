@@ -0,0 +1,349 @@
+use core::{ffi::c_void, mem, ptr, slice};
+
+use crate::firmware::uefi::memory_map::{
+    UefiAllocateType, UefiBootServices, UefiGuid, UefiHandle, UefiMemoryType, UefiStatus,
+    UefiSystemTable, BAD_BUFFER_SIZE, BUFFER_TOO_SMALL, END_OF_FILE, INVALID_PARAMETER,
+    LOADER_DATA, SUCCESS, UEFI_PAGE_SIZE,
+};
+
+pub const LOADED_IMAGE_PROTOCOL_GUID: UefiGuid = UefiGuid::new(
+    0x5b1b31a1,
+    0x9562,
+    0x11d2,
+    [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+pub const SIMPLE_FILE_SYSTEM_PROTOCOL_GUID: UefiGuid = UefiGuid::new(
+    0x964e5b22,
+    0x6459,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+pub const FILE_INFO_GUID: UefiGuid = UefiGuid::new(
+    0x09576e92,
+    0x6d3f,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+const FILE_MODE_READ: u64 = 0x1;
+
+/// Longest path, in UCS-2 characters including the null terminator, that can be opened.
+pub const MAX_PATH_LENGTH: usize = 256;
+
+#[repr(C)]
+pub struct UefiLoadedImageProtocol {
+    revision: u32,
+    parent_handle: UefiHandle,
+    system_table: *const UefiSystemTable,
+    device_handle: UefiHandle,
+    file_path: *const c_void,
+    _reserved: *const c_void,
+    load_options_size: u32,
+    load_options: *const c_void,
+    image_base: *const c_void,
+    image_size: u64,
+    image_code_type: UefiMemoryType,
+    image_data_type: UefiMemoryType,
+    unload: unsafe extern "efiapi" fn(image_handle: UefiHandle) -> UefiStatus,
+}
+
+#[repr(C)]
+pub struct UefiSimpleFileSystemProtocol {
+    revision: u64,
+    open_volume: unsafe extern "efiapi" fn(
+        this: *mut UefiSimpleFileSystemProtocol,
+        root: *mut *mut UefiFileProtocol,
+    ) -> UefiStatus,
+}
+
+#[repr(C)]
+pub struct UefiFileProtocol {
+    revision: u64,
+    open: unsafe extern "efiapi" fn(
+        this: *mut UefiFileProtocol,
+        new_handle: *mut *mut UefiFileProtocol,
+        file_name: *const u16,
+        open_mode: u64,
+        attributes: u64,
+    ) -> UefiStatus,
+    close: unsafe extern "efiapi" fn(this: *mut UefiFileProtocol) -> UefiStatus,
+    delete: unsafe extern "efiapi" fn(this: *mut UefiFileProtocol) -> UefiStatus,
+    read: unsafe extern "efiapi" fn(
+        this: *mut UefiFileProtocol,
+        buffer_size: *mut u64,
+        buffer: *mut c_void,
+    ) -> UefiStatus,
+    write: unsafe extern "efiapi" fn(
+        this: *mut UefiFileProtocol,
+        buffer_size: *mut u64,
+        buffer: *const c_void,
+    ) -> UefiStatus,
+    get_position:
+        unsafe extern "efiapi" fn(this: *mut UefiFileProtocol, position: *mut u64) -> UefiStatus,
+    set_position:
+        unsafe extern "efiapi" fn(this: *mut UefiFileProtocol, position: u64) -> UefiStatus,
+    get_info: unsafe extern "efiapi" fn(
+        this: *mut UefiFileProtocol,
+        information_type: *const UefiGuid,
+        buffer_size: *mut u64,
+        buffer: *mut c_void,
+    ) -> UefiStatus,
+    set_info: unsafe extern "efiapi" fn(
+        this: *mut UefiFileProtocol,
+        information_type: *const UefiGuid,
+        buffer_size: u64,
+        buffer: *const c_void,
+    ) -> UefiStatus,
+    flush: unsafe extern "efiapi" fn(this: *mut UefiFileProtocol) -> UefiStatus,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    _pad1: u8,
+    pub nanosecond: u32,
+    pub time_zone: i16,
+    pub daylight: u8,
+    _pad2: u8,
+}
+
+/// The fixed size part of `EFI_FILE_INFO`; the null terminated file name follows it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiFileInfo {
+    pub size: u64,
+    pub file_size: u64,
+    pub physical_size: u64,
+    pub create_time: UefiTime,
+    pub last_access_time: UefiTime,
+    pub modification_time: UefiTime,
+    pub attribute: u64,
+}
+
+/// An open file or directory. The file is closed when this is dropped.
+pub struct UefiFile {
+    protocol: *mut UefiFileProtocol,
+}
+
+impl UefiLoadedImageProtocol {
+    /// Returns the handle of the device that the image was loaded from.
+    pub fn device_handle(&self) -> UefiHandle {
+        self.device_handle
+    }
+
+    pub fn image_base(&self) -> *const c_void {
+        self.image_base
+    }
+
+    pub fn image_size(&self) -> u64 {
+        self.image_size
+    }
+}
+
+/// Converts `path` to a null terminated UCS-2 string, accepting `/` as well as `\` as the
+/// separator.
+fn path_to_ucs2(path: &str, buffer: &mut [u16; MAX_PATH_LENGTH]) -> Result<(), UefiStatus> {
+    let mut length = 0;
+
+    for c in path.chars() {
+        let c = if c == '/' { '\\' } else { c };
+        let mut encoded = [0u16; 2];
+        let encoded = c.encode_utf16(&mut encoded);
+
+        // UCS-2 can not represent surrogate pairs, and the last character must stay null
+        if encoded.len() != 1 || length >= MAX_PATH_LENGTH - 1 {
+            return Err(INVALID_PARAMETER);
+        }
+
+        buffer[length] = encoded[0];
+        length += 1;
+    }
+    buffer[length] = 0;
+
+    Ok(())
+}
+
+impl UefiFile {
+    /// Opens the root directory of the volume that the image identified by `image_handle` was
+    /// loaded from.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited, and `image_handle` must be the handle that was
+    /// passed to the image entry point.
+    pub unsafe fn open_boot_volume(
+        boot_services: &UefiBootServices,
+        image_handle: UefiHandle,
+    ) -> Result<Self, UefiStatus> {
+        let loaded_image = boot_services.handle_protocol::<UefiLoadedImageProtocol>(
+            image_handle,
+            &LOADED_IMAGE_PROTOCOL_GUID,
+        )?;
+        let file_system = boot_services.handle_protocol::<UefiSimpleFileSystemProtocol>(
+            (*loaded_image).device_handle(),
+            &SIMPLE_FILE_SYSTEM_PROTOCOL_GUID,
+        )?;
+
+        let mut root: *mut UefiFileProtocol = ptr::null_mut();
+        let status = ((*file_system).open_volume)(file_system, &mut root);
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(Self { protocol: root })
+    }
+
+    /// Opens `path` relative to this directory for reading, such as `\EFI\BOOT\KERNEL.ELF`.
+    pub fn open(&self, path: &str) -> Result<Self, UefiStatus> {
+        let mut ucs2_path = [0u16; MAX_PATH_LENGTH];
+        path_to_ucs2(path, &mut ucs2_path)?;
+
+        let mut file: *mut UefiFileProtocol = ptr::null_mut();
+        let status = unsafe {
+            ((*self.protocol).open)(
+                self.protocol,
+                &mut file,
+                ucs2_path.as_ptr(),
+                FILE_MODE_READ,
+                0,
+            )
+        };
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(Self { protocol: file })
+    }
+
+    /// Returns the fixed size part of this file's `EFI_FILE_INFO`.
+    pub fn info(&self, boot_services: &UefiBootServices) -> Result<UefiFileInfo, UefiStatus> {
+        // Leave room for a short file name, and keep the buffer aligned for `UefiFileInfo`
+        let mut buffer = [0u64; 64];
+        let mut buffer_size = mem::size_of_val(&buffer) as u64;
+
+        match unsafe { self.read_info(buffer.as_mut_ptr() as *mut c_void, &mut buffer_size) } {
+            Err(BUFFER_TOO_SMALL) => {}
+            result => return result,
+        }
+
+        // The file name did not fit, so retry with the size that the firmware asked for
+        unsafe {
+            let pool = boot_services.allocate_pool(LOADER_DATA, buffer_size as usize)?;
+            let info = self.read_info(pool, &mut buffer_size);
+            boot_services.free_pool(pool)?;
+            info
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `buffer` must be valid for writes of `buffer_size` bytes and aligned for `UefiFileInfo`.
+    unsafe fn read_info(
+        &self,
+        buffer: *mut c_void,
+        buffer_size: &mut u64,
+    ) -> Result<UefiFileInfo, UefiStatus> {
+        let status =
+            ((*self.protocol).get_info)(self.protocol, &FILE_INFO_GUID, buffer_size, buffer);
+        if status != SUCCESS {
+            return Err(status);
+        }
+        if (*buffer_size as usize) < mem::size_of::<UefiFileInfo>() {
+            return Err(BAD_BUFFER_SIZE);
+        }
+
+        Ok(ptr::read(buffer as *const UefiFileInfo))
+    }
+
+    pub fn size(&self, boot_services: &UefiBootServices) -> Result<u64, UefiStatus> {
+        Ok(self.info(boot_services)?.file_size)
+    }
+
+    /// Reads from the current position into `buffer` and returns the number of bytes read, which
+    /// is 0 at the end of the file.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UefiStatus> {
+        let mut buffer_size = buffer.len() as u64;
+
+        let status = unsafe {
+            ((*self.protocol).read)(
+                self.protocol,
+                &mut buffer_size,
+                buffer.as_mut_ptr() as *mut c_void,
+            )
+        };
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(buffer_size as usize)
+    }
+
+    /// Reads the whole file into a new `LOADER_DATA` page allocation. The returned slice is
+    /// exactly as long as the file; the allocation is rounded up to whole pages.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn read_to_pages(
+        &mut self,
+        boot_services: &UefiBootServices,
+    ) -> Result<&'static mut [u8], UefiStatus> {
+        let size = self.size(boot_services)? as usize;
+        let pages = size.div_ceil(UEFI_PAGE_SIZE).max(1);
+
+        let address =
+            boot_services.allocate_pages(UefiAllocateType::AnyPages, LOADER_DATA, pages, 0)?;
+        let buffer = slice::from_raw_parts_mut(address as *mut u8, size);
+
+        let mut offset = 0;
+        while offset < size {
+            // The file is shorter than its info said, such as when it was truncated
+            let read = match self.read(&mut buffer[offset..]) {
+                Ok(0) => Err(END_OF_FILE),
+                result => result,
+            };
+
+            match read {
+                Ok(read) => offset += read,
+                Err(status) => {
+                    // The read error is more useful to the caller than a failure to free
+                    let _ = boot_services.free_pages(address, pages);
+                    return Err(status);
+                }
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+impl Drop for UefiFile {
+    fn drop(&mut self) {
+        unsafe {
+            ((*self.protocol).close)(self.protocol);
+        }
+    }
+}
+
+/// Reads the file at `path` on the boot volume into a new page allocation.
+///
+/// # Safety
+///
+/// Boot services must not have been exited, and `image_handle` must be the handle that was passed
+/// to the image entry point.
+pub unsafe fn read_boot_file(
+    boot_services: &UefiBootServices,
+    image_handle: UefiHandle,
+    path: &str,
+) -> Result<&'static mut [u8], UefiStatus> {
+    let root = UefiFile::open_boot_volume(boot_services, image_handle)?;
+    let mut file = root.open(path)?;
+
+    file.read_to_pages(boot_services)
+}
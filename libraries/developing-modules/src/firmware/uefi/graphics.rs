@@ -0,0 +1,287 @@
+use core::{ffi::c_void, ptr};
+
+use crate::{
+    boot_info::{Framebuffer, PixelFormat, PixelMask},
+    firmware::uefi::memory_map::{
+        TodoFunction, UefiBootServices, UefiGuid, UefiStatus, NOT_FOUND, SUCCESS,
+    },
+};
+
+pub const GRAPHICS_OUTPUT_PROTOCOL_GUID: UefiGuid = UefiGuid::new(
+    0x9042a9de,
+    0x23dc,
+    0x4a38,
+    [0x96, 0xfb, 0x7a, 0xde, 0xd0, 0x80, 0x51, 0x6a],
+);
+
+#[repr(C)]
+pub struct UefiGraphicsOutputProtocol {
+    query_mode: unsafe extern "efiapi" fn(
+        this: *mut UefiGraphicsOutputProtocol,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *mut UefiGraphicsModeInfo,
+    ) -> UefiStatus,
+    set_mode: unsafe extern "efiapi" fn(
+        this: *mut UefiGraphicsOutputProtocol,
+        mode_number: u32,
+    ) -> UefiStatus,
+    blt: TodoFunction,
+    mode: *const UefiGraphicsOutputMode,
+}
+
+#[repr(C)]
+pub struct UefiGraphicsOutputMode {
+    max_mode: u32,
+    mode: u32,
+    info: *const UefiGraphicsModeInfo,
+    size_of_info: usize,
+    frame_buffer_base: u64,
+    frame_buffer_size: usize,
+}
+
+/// The pixel format of a mode. It is read from firmware memory, so it can hold values that are
+/// not listed here, which are treated as having no framebuffer.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UefiPixelFormat(pub u32);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiPixelBitmask {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct UefiGraphicsModeInfo {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: UefiPixelFormat,
+    pub pixel_bitmask: UefiPixelBitmask,
+    pub pixels_per_scan_line: u32,
+}
+
+/// Which graphics mode to pick. Modes without a linear framebuffer are never picked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UefiResolution {
+    /// Keep the mode that the firmware set up.
+    Current,
+    /// The mode with the most pixels.
+    Highest,
+    /// The mode with the most pixels that fits in `width` x `height`.
+    AtMost { width: u32, height: u32 },
+    /// Exactly `width` x `height`, falling back to `AtMost` with the same size.
+    Exact { width: u32, height: u32 },
+}
+
+impl UefiPixelFormat {
+    pub const RED_GREEN_BLUE_RESERVED: Self = Self(0);
+    pub const BLUE_GREEN_RED_RESERVED: Self = Self(1);
+    pub const BIT_MASK: Self = Self(2);
+    /// There is no linear framebuffer; the mode can only be drawn to with `Blt`.
+    pub const BLT_ONLY: Self = Self(3);
+}
+
+/// A graphics output device.
+pub struct UefiGraphicsOutput<'a> {
+    protocol: *mut UefiGraphicsOutputProtocol,
+    boot_services: &'a UefiBootServices,
+}
+
+impl UefiGraphicsModeInfo {
+    pub fn has_framebuffer(&self) -> bool {
+        matches!(
+            self.pixel_format,
+            UefiPixelFormat::RED_GREEN_BLUE_RESERVED
+                | UefiPixelFormat::BLUE_GREEN_RED_RESERVED
+                | UefiPixelFormat::BIT_MASK
+        )
+    }
+
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+impl<'a> UefiGraphicsOutput<'a> {
+    /// Finds the first graphics output device.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not be exited while this is in use.
+    pub unsafe fn locate(boot_services: &'a UefiBootServices) -> Result<Self, UefiStatus> {
+        let protocol = boot_services
+            .locate_protocol::<UefiGraphicsOutputProtocol>(&GRAPHICS_OUTPUT_PROTOCOL_GUID)?;
+
+        Ok(Self {
+            protocol,
+            boot_services,
+        })
+    }
+
+    pub fn mode_count(&self) -> u32 {
+        unsafe { (*(*self.protocol).mode).max_mode }
+    }
+
+    pub fn current_mode(&self) -> u32 {
+        unsafe { (*(*self.protocol).mode).mode }
+    }
+
+    pub fn current_mode_info(&self) -> UefiGraphicsModeInfo {
+        unsafe { *(*(*self.protocol).mode).info }
+    }
+
+    pub fn query_mode(&self, mode: u32) -> Result<UefiGraphicsModeInfo, UefiStatus> {
+        let mut size = 0;
+        let mut info: *mut UefiGraphicsModeInfo = ptr::null_mut();
+
+        let status =
+            unsafe { ((*self.protocol).query_mode)(self.protocol, mode, &mut size, &mut info) };
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        // The firmware allocates the info from pool memory
+        unsafe {
+            let mode_info = *info;
+            self.boot_services.free_pool(info as *mut c_void)?;
+            Ok(mode_info)
+        }
+    }
+
+    /// Iterates over every mode that can be queried, along with its number.
+    pub fn modes(&self) -> impl Iterator<Item = (u32, UefiGraphicsModeInfo)> + '_ {
+        (0..self.mode_count()).filter_map(|mode| Some((mode, self.query_mode(mode).ok()?)))
+    }
+
+    /// Switches to `mode`. This clears the screen and moves the framebuffer.
+    pub fn set_mode(&mut self, mode: u32) -> Result<(), UefiStatus> {
+        let status = unsafe { ((*self.protocol).set_mode)(self.protocol, mode) };
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of the mode that best matches `resolution`.
+    pub fn find_mode(&self, resolution: UefiResolution) -> Result<u32, UefiStatus> {
+        let (max_width, max_height) = match resolution {
+            UefiResolution::Current => {
+                if !self.current_mode_info().has_framebuffer() {
+                    return Err(NOT_FOUND);
+                }
+                return Ok(self.current_mode());
+            }
+            UefiResolution::Highest => (u32::MAX, u32::MAX),
+            UefiResolution::AtMost { width, height } => (width, height),
+            UefiResolution::Exact { width, height } => {
+                let exact = self.modes().find(|(_, info)| {
+                    info.has_framebuffer() && info.width == width && info.height == height
+                });
+                if let Some((mode, _)) = exact {
+                    return Ok(mode);
+                }
+                (width, height)
+            }
+        };
+
+        self.modes()
+            .filter(|(_, info)| {
+                info.has_framebuffer() && info.width <= max_width && info.height <= max_height
+            })
+            .max_by_key(|(_, info)| info.pixel_count())
+            .map(|(mode, _)| mode)
+            .ok_or(NOT_FOUND)
+    }
+
+    /// Switches to the mode that best matches `resolution`, unless it is already current.
+    pub fn select_mode(&mut self, resolution: UefiResolution) -> Result<u32, UefiStatus> {
+        let mode = self.find_mode(resolution)?;
+
+        if mode != self.current_mode() {
+            self.set_mode(mode)?;
+        }
+
+        Ok(mode)
+    }
+
+    /// Describes the framebuffer of the current mode, or returns `None` if the mode does not have
+    /// one. The framebuffer stays valid after exiting boot services.
+    pub fn framebuffer(&self) -> Option<Framebuffer> {
+        let (base, size, info) = unsafe {
+            let mode = &*(*self.protocol).mode;
+            (mode.frame_buffer_base, mode.frame_buffer_size, *mode.info)
+        };
+
+        let (format, mask) = match info.pixel_format {
+            UefiPixelFormat::RED_GREEN_BLUE_RESERVED => (
+                PixelFormat::RGB,
+                PixelMask {
+                    red: 0x0000ff,
+                    green: 0x00ff00,
+                    blue: 0xff0000,
+                    reserved: 0xff000000,
+                },
+            ),
+            UefiPixelFormat::BLUE_GREEN_RED_RESERVED => (
+                PixelFormat::BGR,
+                PixelMask {
+                    red: 0xff0000,
+                    green: 0x00ff00,
+                    blue: 0x0000ff,
+                    reserved: 0xff000000,
+                },
+            ),
+            UefiPixelFormat::BIT_MASK => {
+                let mask = info.pixel_bitmask;
+                (
+                    PixelFormat::BITMASK,
+                    PixelMask {
+                        red: mask.red,
+                        green: mask.green,
+                        blue: mask.blue,
+                        reserved: mask.reserved,
+                    },
+                )
+            }
+            _ => return None,
+        };
+
+        // Round the highest used bit up to whole bytes
+        let used_bits = mask.red | mask.green | mask.blue | mask.reserved;
+        let bytes_per_pixel = (32 - used_bits.leading_zeros()).div_ceil(8);
+
+        Some(Framebuffer::new(
+            base,
+            size as u64,
+            info.width,
+            info.height,
+            info.pixels_per_scan_line,
+            bytes_per_pixel,
+            format,
+            mask,
+        ))
+    }
+}
+
+/// Switches the first graphics output device to the mode that best matches `resolution` and
+/// returns its framebuffer.
+///
+/// # Safety
+///
+/// Boot services must not have been exited.
+pub unsafe fn acquire_framebuffer(
+    boot_services: &UefiBootServices,
+    resolution: UefiResolution,
+) -> Result<Framebuffer, UefiStatus> {
+    let mut graphics = UefiGraphicsOutput::locate(boot_services)?;
+    graphics.select_mode(resolution)?;
+
+    graphics.framebuffer().ok_or(NOT_FOUND)
+}
@@ -1,20 +1,55 @@
 // Much of this is inspired by the uefi-rs crate: https://github.com/rust-osdev/uefi-rs
 
-use core::{mem, ptr, slice, ffi::c_void};
+use core::{ffi::c_void, mem, ptr, slice};
+
+use crate::firmware::uefi::{
+    config_table::UefiConfigurationTable,
+    console::{
+        UefiConsoleIn, UefiConsoleOut, UefiSimpleTextInputProtocol, UefiSimpleTextOutputProtocol,
+    },
+};
 
 pub type UefiStatus = u64;
 pub type UefiHandle = *const c_void;
 
-const UEFI_ERROR_BIT: UefiStatus = 1 << (core::mem::size_of::<u64>() * 8 - 1);
+pub(crate) const UEFI_ERROR_BIT: UefiStatus = 1 << (core::mem::size_of::<u64>() * 8 - 1);
 pub const SUCCESS: UefiStatus = 0;
+pub const INVALID_PARAMETER: UefiStatus = UEFI_ERROR_BIT | 2;
+pub const UNSUPPORTED: UefiStatus = UEFI_ERROR_BIT | 3;
+pub const BAD_BUFFER_SIZE: UefiStatus = UEFI_ERROR_BIT | 4;
 pub const BUFFER_TOO_SMALL: UefiStatus = UEFI_ERROR_BIT | 5;
+pub const NOT_READY: UefiStatus = UEFI_ERROR_BIT | 6;
+pub const DEVICE_ERROR: UefiStatus = UEFI_ERROR_BIT | 7;
+pub const OUT_OF_RESOURCES: UefiStatus = UEFI_ERROR_BIT | 9;
+pub const NOT_FOUND: UefiStatus = UEFI_ERROR_BIT | 14;
+pub const END_OF_FILE: UefiStatus = UEFI_ERROR_BIT | 31;
 
 // These are types that will be filled in later, as they are unused for now
 type TodoStruct = *const c_void;
-type TodoFunction = unsafe extern "efiapi" fn();
+pub(crate) type TodoFunction = unsafe extern "efiapi" fn();
 type PhysicalAddr = *const c_void;
 type VirtualAddr = *const c_void;
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UefiGuid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+impl UefiGuid {
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        Self {
+            data1,
+            data2,
+            data3,
+            data4,
+        }
+    }
+}
+
 #[repr(C)]
 pub struct UefiTableHeader {
     signature: u64,
@@ -30,21 +65,52 @@ pub struct UefiSystemTable {
     vendor: *const u16,
     revision: u32,
     console_in_handle: UefiHandle,
-    console_in: TodoStruct,
+    console_in: *mut UefiSimpleTextInputProtocol,
     console_out_handle: UefiHandle,
-    console_out: TodoStruct,
+    console_out: *mut UefiSimpleTextOutputProtocol,
     stderr_handle: UefiHandle,
-    stderr: TodoStruct,
+    stderr: *mut UefiSimpleTextOutputProtocol,
     runtime_services: TodoStruct,
     boot_services: *const UefiBootServices,
     table_entry_count: u64,
-    config_table: TodoStruct,
+    config_table: *const UefiConfigurationTable,
 }
 
 impl UefiSystemTable {
     pub fn boot_services(&self) -> *const UefiBootServices {
         self.boot_services
     }
+
+    /// Returns the configuration tables that the firmware installed, which point to platform tables
+    /// such as ACPI and SMBIOS.
+    pub fn config_tables(&self) -> &[UefiConfigurationTable] {
+        if self.config_table.is_null() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts(self.config_table, self.table_entry_count as usize) }
+    }
+
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn console_in(&self) -> UefiConsoleIn {
+        UefiConsoleIn::new(self.console_in)
+    }
+
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn console_out(&self) -> UefiConsoleOut {
+        UefiConsoleOut::new(self.console_out)
+    }
+
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn stderr(&self) -> UefiConsoleOut {
+        UefiConsoleOut::new(self.stderr)
+    }
 }
 
 #[repr(C)]
@@ -52,8 +118,13 @@ pub struct UefiBootServices {
     header: UefiTableHeader,
     raise_tpl: TodoFunction,
     restore_tpl: TodoFunction,
-    allocate_pages: TodoFunction,
-    free_pages: TodoFunction,
+    allocate_pages: unsafe extern "efiapi" fn(
+        allocate_type: UefiAllocateType,
+        memory_type: UefiMemoryType,
+        pages: u64,
+        memory: *mut u64,
+    ) -> UefiStatus,
+    free_pages: unsafe extern "efiapi" fn(memory: u64, pages: u64) -> UefiStatus,
     get_memory_map: unsafe extern "efiapi" fn(
         map_size: &mut u32,
         memory_map: *mut UefiMemoryDescriptor,
@@ -66,7 +137,7 @@ pub struct UefiBootServices {
         size: u64,
         buffer: *mut *mut c_void,
     ) -> UefiStatus,
-    free_pool: TodoFunction,
+    free_pool: unsafe extern "efiapi" fn(buffer: *mut c_void) -> UefiStatus,
     create_event: TodoFunction,
     set_timer: TodoFunction,
     wait_for_event: TodoFunction,
@@ -76,7 +147,11 @@ pub struct UefiBootServices {
     install_protocol_interface: TodoFunction,
     reinstall_protocol_interface: TodoFunction,
     uninstall_protocol_interface: TodoFunction,
-    handle_protocol: TodoFunction,
+    handle_protocol: unsafe extern "efiapi" fn(
+        handle: UefiHandle,
+        protocol: *const UefiGuid,
+        interface: *mut *mut c_void,
+    ) -> UefiStatus,
     register_protocol_notify: TodoFunction,
     locate_handle: TodoFunction,
     locate_device_path: TodoFunction,
@@ -96,7 +171,11 @@ pub struct UefiBootServices {
     open_protocol_information: TodoFunction,
     protocols_per_handle: TodoFunction,
     locate_handle_buffer: TodoFunction,
-    locate_protocol: TodoFunction,
+    locate_protocol: unsafe extern "efiapi" fn(
+        protocol: *const UefiGuid,
+        registration: *const c_void,
+        interface: *mut *mut c_void,
+    ) -> UefiStatus,
     install_multiple_protocol_interfaces: TodoFunction,
     uninstall_multiple_protocol_interfaces: TodoFunction,
     calculate_crc32: TodoFunction,
@@ -116,10 +195,21 @@ pub struct UefiMemoryDescriptor {
 }
 
 #[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct UefiMemoryType(pub u32);
 
 pub const LOADER_DATA: UefiMemoryType = UefiMemoryType(2);
 
+#[repr(u32)]
+#[derive(Copy, Clone, Debug)]
+pub enum UefiAllocateType {
+    AnyPages = 0,
+    MaxAddress = 1,
+    Address = 2,
+}
+
+pub const UEFI_PAGE_SIZE: usize = 0x1000;
+
 #[derive(Debug)]
 pub struct UefiMemoryMap<'buf> {
     map_key: u64,
@@ -148,7 +238,10 @@ impl UefiBootServices {
         Ok(map_size as usize)
     }
 
-    pub unsafe fn retrieve_memory_map<'buf>(&self, buf: &'buf mut [u8]) -> Result<UefiMemoryMap<'buf>, UefiStatus> {
+    pub unsafe fn retrieve_memory_map<'buf>(
+        &self,
+        buf: &'buf mut [u8],
+    ) -> Result<UefiMemoryMap<'buf>, UefiStatus> {
         let mut map_size: u32 = buf.len() as u32;
         let mut map_key: u64 = 0;
         let mut descriptor_size: u64 = 0;
@@ -168,7 +261,116 @@ impl UefiBootServices {
 
         Ok(UefiMemoryMap {
             map_key,
-            map: buf.align_to_mut().1
+            map: buf.align_to_mut().1,
         })
     }
+
+    /// Allocates `pages` pages of `UEFI_PAGE_SIZE` bytes and returns their physical address.
+    ///
+    /// `address` is the highest acceptable address for `MaxAddress`, the exact address for
+    /// `Address`, and ignored for `AnyPages`.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn allocate_pages(
+        &self,
+        allocate_type: UefiAllocateType,
+        memory_type: UefiMemoryType,
+        pages: usize,
+        address: u64,
+    ) -> Result<u64, UefiStatus> {
+        let mut memory = address;
+        let status = (self.allocate_pages)(allocate_type, memory_type, pages as u64, &mut memory);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(memory)
+    }
+
+    /// # Safety
+    ///
+    /// Boot services must not have been exited, and the memory must not be used after it is
+    /// freed.
+    pub unsafe fn free_pages(&self, address: u64, pages: usize) -> Result<(), UefiStatus> {
+        let status = (self.free_pages)(address, pages as u64);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(())
+    }
+
+    /// Allocates `size` bytes of pool memory, which is 8 byte aligned.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn allocate_pool(
+        &self,
+        memory_type: UefiMemoryType,
+        size: usize,
+    ) -> Result<*mut c_void, UefiStatus> {
+        let mut buffer = ptr::null_mut();
+        let status = (self.allocate_pool)(memory_type, size as u64, &mut buffer);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(buffer)
+    }
+
+    /// # Safety
+    ///
+    /// Boot services must not have been exited, and the memory must not be used after it is
+    /// freed.
+    pub unsafe fn free_pool(&self, buffer: *mut c_void) -> Result<(), UefiStatus> {
+        let status = (self.free_pool)(buffer);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the interface of protocol `guid` installed on `handle`.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn handle_protocol<T>(
+        &self,
+        handle: UefiHandle,
+        guid: &UefiGuid,
+    ) -> Result<*mut T, UefiStatus> {
+        let mut interface: *mut c_void = ptr::null_mut();
+        let status = (self.handle_protocol)(handle, guid, &mut interface);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(interface as *mut T)
+    }
+
+    /// Returns the interface of the first handle that supports protocol `guid`.
+    ///
+    /// # Safety
+    ///
+    /// Boot services must not have been exited.
+    pub unsafe fn locate_protocol<T>(&self, guid: &UefiGuid) -> Result<*mut T, UefiStatus> {
+        let mut interface: *mut c_void = ptr::null_mut();
+        let status = (self.locate_protocol)(guid, ptr::null(), &mut interface);
+
+        if status != SUCCESS {
+            return Err(status);
+        }
+
+        Ok(interface as *mut T)
+    }
 }
@@ -1 +1,5 @@
+pub mod config_table;
+pub mod console;
+pub mod file_system;
+pub mod graphics;
 pub mod memory_map;
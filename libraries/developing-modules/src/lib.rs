@@ -1,10 +1,25 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[cfg(target_arch = "aarch64")]
 pub mod aarch64;
 
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+
 #[cfg(target_arch = "x86_64")]
 pub mod x86_64;
 
+// Needs the symbols of the aarch64 and riscv64 linker scripts
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+pub mod sections;
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+mod smp;
+
+pub mod acpi;
+pub mod boot_info;
+pub mod console;
+pub mod elf;
+pub mod fdt;
 pub mod firmware;
+pub mod memory;
 pub mod serial;
@@ -0,0 +1,238 @@
+pub type Error = &'static str;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// Hands out physical page frames that are `PAGE_SIZE` bytes long and `PAGE_SIZE` aligned.
+pub trait FrameAllocator {
+    /// Allocates `count` physically contiguous frames and returns the address of the first one.
+    fn allocate_frames(&mut self, count: usize) -> Option<u64>;
+
+    fn allocate_frame(&mut self) -> Option<u64> {
+        self.allocate_frames(1)
+    }
+}
+
+/// A frame allocator that hands out frames from a single region and never frees them.
+#[derive(Copy, Clone, Debug)]
+pub struct BumpFrameAllocator {
+    next: u64,
+    end: u64,
+}
+
+pub fn align_down(value: u64, alignment: u64) -> u64 {
+    value & !(alignment - 1)
+}
+
+pub fn align_up(value: u64, alignment: u64) -> u64 {
+    align_down(value + alignment - 1, alignment)
+}
+
+impl BumpFrameAllocator {
+    /// Creates an allocator over the physical region `start..end`, which must not be used by
+    /// anything else.
+    pub fn new(start: u64, end: u64) -> Self {
+        Self {
+            next: align_up(start, PAGE_SIZE),
+            end: align_down(end, PAGE_SIZE),
+        }
+    }
+
+    /// Returns the number of frames that have not been handed out yet.
+    pub fn remaining_frames(&self) -> usize {
+        (self.end.saturating_sub(self.next) / PAGE_SIZE) as usize
+    }
+}
+
+impl FrameAllocator for BumpFrameAllocator {
+    fn allocate_frames(&mut self, count: usize) -> Option<u64> {
+        if count > self.remaining_frames() {
+            return None;
+        }
+
+        let frame = self.next;
+        self.next += count as u64 * PAGE_SIZE;
+
+        Some(frame)
+    }
+}
+
+/// The page sizes that a single page table entry can map, on every architecture with 4 KiB pages
+/// and 512 entry tables.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PageSize {
+    Size4KiB,
+    Size2MiB,
+    Size1GiB,
+}
+
+impl PageSize {
+    pub fn bytes(&self) -> u64 {
+        match self {
+            Self::Size4KiB => 0x1000,
+            Self::Size2MiB => 0x20_0000,
+            Self::Size1GiB => 0x4000_0000,
+        }
+    }
+
+    /// Returns the level of the table that holds entries of this size; 1 is the lowest level
+    /// (level 3 in the Arm naming).
+    pub(crate) fn level(&self) -> usize {
+        match self {
+            Self::Size4KiB => 1,
+            Self::Size2MiB => 2,
+            Self::Size1GiB => 3,
+        }
+    }
+
+    pub(crate) fn from_level(level: usize) -> Self {
+        match level {
+            3 => Self::Size1GiB,
+            2 => Self::Size2MiB,
+            _ => Self::Size4KiB,
+        }
+    }
+}
+
+/// A page table hierarchy. Each architecture supplies the entry encoding and the table walk, and
+/// the range helpers are built on top of them.
+pub trait PageMap {
+    /// The attributes of a mapping, such as the flags of a page table entry.
+    type Flags: Copy;
+
+    /// Returns the first address of the higher half of the virtual address space.
+    fn higher_half_start(&self) -> u64;
+
+    /// Returns true if a single entry can map a page of `size`.
+    fn supports_page_size(&self, _size: PageSize) -> bool {
+        true
+    }
+
+    /// Maps a single page of `size` bytes from `virt` to `phys`.
+    ///
+    /// # Safety
+    ///
+    /// Changing the mappings of the active address space can invalidate any reference into the
+    /// affected memory.
+    unsafe fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: PageSize,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error>;
+
+    /// Removes the page that contains `virt` and returns the physical address and size it mapped.
+    /// Page tables that become empty are not freed.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may access the page after it is unmapped.
+    unsafe fn unmap(&mut self, virt: u64) -> Result<(u64, PageSize), Error>;
+
+    /// Returns the physical address that `virt` is mapped to.
+    fn translate(&self, virt: u64) -> Option<u64>;
+
+    /// Maps `length` bytes from `virt` to `phys`, using the largest pages that the alignment of
+    /// both addresses allows.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`PageMap::map`].
+    unsafe fn map_range(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        length: u64,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        if !virt.is_multiple_of(PAGE_SIZE) || !phys.is_multiple_of(PAGE_SIZE) {
+            return Err("Address is not aligned to the page size");
+        }
+
+        let mut offset = 0;
+        while offset < length {
+            let remaining = length - offset;
+            let size = [PageSize::Size1GiB, PageSize::Size2MiB]
+                .into_iter()
+                .filter(|size| self.supports_page_size(*size))
+                .find(|size| {
+                    (virt + offset).is_multiple_of(size.bytes())
+                        && (phys + offset).is_multiple_of(size.bytes())
+                        && remaining >= size.bytes()
+                })
+                .unwrap_or(PageSize::Size4KiB);
+
+            self.map(virt + offset, phys + offset, size, flags, allocator)?;
+            offset += size.bytes();
+        }
+
+        Ok(())
+    }
+
+    /// Maps `length` bytes starting at `start` to the same virtual addresses.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`PageMap::map`].
+    unsafe fn identity_map(
+        &mut self,
+        start: u64,
+        length: u64,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        self.map_range(start, start, length, flags, allocator)
+    }
+
+    /// Maps a kernel that was loaded at `phys_start` to `virt_start` in the higher half.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`PageMap::map`].
+    unsafe fn map_higher_half(
+        &mut self,
+        phys_start: u64,
+        virt_start: u64,
+        length: u64,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        if virt_start < self.higher_half_start() {
+            return Err("Kernel address is not in the higher half");
+        }
+
+        self.map_range(virt_start, phys_start, length, flags, allocator)
+    }
+}
+
+/// Gives access to physical memory, such as firmware tables, from the current address space.
+pub trait PhysicalMapper {
+    /// Returns a pointer through which `size` bytes starting at physical address `phys` can be
+    /// read.
+    ///
+    /// # Safety
+    ///
+    /// The physical memory must exist, and must stay mapped for as long as the mapper is borrowed.
+    unsafe fn map_physical(&self, phys: u64, size: usize) -> *const u8;
+}
+
+/// A mapper for when all of physical memory is mapped at a fixed offset, such as the identity
+/// mapping that firmware sets up (an offset of 0) or a higher half direct map.
+#[derive(Copy, Clone, Debug)]
+pub struct OffsetMapper {
+    offset: u64,
+}
+
+impl OffsetMapper {
+    pub fn new(offset: u64) -> Self {
+        Self { offset }
+    }
+}
+
+impl PhysicalMapper for OffsetMapper {
+    unsafe fn map_physical(&self, phys: u64, _size: usize) -> *const u8 {
+        (phys + self.offset) as *const u8
+    }
+}
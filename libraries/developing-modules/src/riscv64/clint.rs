@@ -0,0 +1,138 @@
+// The core-local interruptor, which holds the machine timer and the machine software interrupt
+// of every hart. QEMU's virt machine has a SiFive CLINT unless it is started with `aclint=on`,
+// which splits the same registers into separate ACLINT MSWI and MTIMER devices.
+//
+// These registers are for M-mode. Under SBI firmware they are usually protected, so S-mode uses
+// the `time` CSR with `sbi::set_timer` and `sbi::send_ipi` instead, and the firmware programs
+// the CLINT on its behalf.
+
+use core::ptr;
+
+use crate::fdt::Fdt;
+
+pub type Error = &'static str;
+
+const CLINT_COMPATIBLES: &[&str] = &["sifive,clint0", "riscv,clint0"];
+const MSWI_COMPATIBLES: &[&str] = &["riscv,aclint-mswi"];
+const MTIMER_COMPATIBLES: &[&str] = &["riscv,aclint-mtimer"];
+
+const CLINT_MSWI: u64 = 0x0;
+const CLINT_MTIMECMP: u64 = 0x4000;
+const CLINT_MTIME: u64 = 0xbff8;
+
+/// The number of harts that one CLINT or ACLINT device can serve.
+pub const MAX_HARTS: u64 = 4095;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Clint {
+    mswi: Option<u64>,
+    mtime: u64,
+    mtimecmp: u64,
+}
+
+/// Returns the frequency of `mtime` and the `time` CSR, from `/cpus/timebase-frequency`.
+pub fn timebase_frequency(fdt: &Fdt) -> Option<u64> {
+    fdt.find_node("/cpus")?
+        .property("timebase-frequency")?
+        .as_u64()
+}
+
+impl Clint {
+    /// Uses the SiFive CLINT layout at `base`.
+    pub fn new(base: u64) -> Self {
+        Self {
+            mswi: Some(base + CLINT_MSWI),
+            mtime: base + CLINT_MTIME,
+            mtimecmp: base + CLINT_MTIMECMP,
+        }
+    }
+
+    /// Uses separate ACLINT devices. `mtimecmp` is the first hart's compare register.
+    pub fn from_aclint(mswi: Option<u64>, mtime: u64, mtimecmp: u64) -> Self {
+        Self {
+            mswi,
+            mtime,
+            mtimecmp,
+        }
+    }
+
+    /// Finds a CLINT, or ACLINT MTIMER and MSWI devices, in the device tree. The MTIMER's first
+    /// `reg` range is `mtime` and its second is the compare registers.
+    pub fn from_device_tree(fdt: &Fdt) -> Option<Self> {
+        let enabled = |compatibles| {
+            fdt.find_any_compatible(compatibles)
+                .filter(|node| node.is_enabled())
+        };
+
+        if let Some(node) = enabled(CLINT_COMPATIBLES) {
+            return Some(Self::new(node.first_reg()?.address));
+        }
+
+        let mut reg = enabled(MTIMER_COMPATIBLES)?.reg()?;
+        let mtime = reg.next()?.address;
+        let mtimecmp = reg.next()?.address;
+        let mswi = enabled(MSWI_COMPATIBLES)
+            .and_then(|node| node.first_reg())
+            .map(|reg| reg.address);
+
+        Some(Self::from_aclint(mswi, mtime, mtimecmp))
+    }
+
+    fn check_hart(hart_id: u64) -> Result<(), Error> {
+        if hart_id < MAX_HARTS {
+            Ok(())
+        } else {
+            Err("Hart ID is too large for the CLINT")
+        }
+    }
+
+    /// Reads the machine timer, which counts at the timebase frequency.
+    pub fn mtime(&self) -> u64 {
+        unsafe { ptr::read_volatile(self.mtime as *const u64) }
+    }
+
+    pub fn mtimecmp(&self, hart_id: u64) -> Result<u64, Error> {
+        Self::check_hart(hart_id)?;
+        Ok(unsafe { ptr::read_volatile((self.mtimecmp + hart_id * 8) as *const u64) })
+    }
+
+    /// Raises a machine timer interrupt on `hart_id` once `mtime` reaches `value`.
+    ///
+    /// # Safety
+    ///
+    /// Must run in M-mode, and nothing else may be using the timer of `hart_id`, which includes
+    /// SBI firmware.
+    pub unsafe fn set_mtimecmp(&self, hart_id: u64, value: u64) -> Result<(), Error> {
+        Self::check_hart(hart_id)?;
+        ptr::write_volatile((self.mtimecmp + hart_id * 8) as *mut u64, value);
+        Ok(())
+    }
+
+    fn msip(&self, hart_id: u64) -> Result<*mut u32, Error> {
+        Self::check_hart(hart_id)?;
+        let mswi = self.mswi.ok_or("No machine software interrupt device")?;
+        Ok((mswi + hart_id * 4) as *mut u32)
+    }
+
+    /// Raises a machine software interrupt on `hart_id`.
+    ///
+    /// # Safety
+    ///
+    /// Must run in M-mode, and `hart_id` must handle the interrupt.
+    pub unsafe fn set_msip(&self, hart_id: u64) -> Result<(), Error> {
+        ptr::write_volatile(self.msip(hart_id)?, 1);
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// Must run in M-mode.
+    pub unsafe fn clear_msip(&self, hart_id: u64) -> Result<(), Error> {
+        ptr::write_volatile(self.msip(hart_id)?, 0);
+        Ok(())
+    }
+
+    pub fn msip_is_set(&self, hart_id: u64) -> Result<bool, Error> {
+        Ok(unsafe { ptr::read_volatile(self.msip(hart_id)?) } & 1 != 0)
+    }
+}
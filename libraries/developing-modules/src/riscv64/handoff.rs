@@ -0,0 +1,28 @@
+use core::arch::asm;
+
+use crate::{boot_info::BootInfo, riscv64::registers::Satp};
+
+/// Switches `satp` to the kernel's page table and the stack to `stack_top`, then jumps to the
+/// entry point with `boot_info` in a0. Supervisor interrupts are disabled before anything is
+/// switched.
+///
+/// # Safety
+///
+/// The page table selected by `satp` must map this function at its current address, the kernel
+/// entry point, the whole stack, and the boot info along with everything it points to.
+pub unsafe fn handoff(entry: u64, stack_top: u64, satp: Satp, boot_info: *const BootInfo) -> ! {
+    asm!(
+        "csrci sstatus, 0x2",
+        "csrw satp, t2",
+        "sfence.vma",
+        "mv sp, t1",
+        "mv s0, zero",
+        "mv ra, zero",
+        "jr t0",
+        in("t0") entry,
+        in("t1") stack_top & !0xf,
+        in("t2") satp.0,
+        in("a0") boot_info,
+        options(noreturn),
+    );
+}
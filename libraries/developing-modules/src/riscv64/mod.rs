@@ -0,0 +1,8 @@
+pub mod clint;
+pub mod handoff;
+pub mod paging;
+pub mod plic;
+pub mod registers;
+pub mod sbi;
+pub mod smp;
+pub mod trap;
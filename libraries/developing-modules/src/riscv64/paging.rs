@@ -0,0 +1,348 @@
+use core::{
+    fmt,
+    ops::{Index, IndexMut},
+};
+
+use crate::{
+    memory::{Error, FrameAllocator, PageMap, PageSize},
+    riscv64::registers::{sfence_vma_address, sfence_vma_all, Satp, SatpMode},
+};
+
+pub const ENTRY_COUNT: usize = 512;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PagingMode {
+    Sv39,
+    Sv48,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PageTableEntry(u64);
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    entries: [PageTableEntry; ENTRY_COUNT],
+}
+
+/// Builds and walks Sv39 or Sv48 page tables.
+///
+/// Page tables are accessed through `phys_offset + physical address`, so an identity mapped
+/// bootloader uses an offset of 0.
+#[derive(Debug)]
+pub struct PageMapper {
+    root: u64,
+    phys_offset: u64,
+    mode: PagingMode,
+}
+
+impl PagingMode {
+    pub fn levels(&self) -> usize {
+        match self {
+            Self::Sv39 => 3,
+            Self::Sv48 => 4,
+        }
+    }
+
+    pub fn virtual_address_bits(&self) -> u64 {
+        match self {
+            Self::Sv39 => 39,
+            Self::Sv48 => 48,
+        }
+    }
+
+    /// Returns the first address of the upper half of the virtual address space.
+    pub fn higher_half_start(&self) -> u64 {
+        !0 << (self.virtual_address_bits() - 1)
+    }
+
+    pub fn satp_mode(&self) -> SatpMode {
+        match self {
+            Self::Sv39 => SatpMode::Sv39,
+            Self::Sv48 => SatpMode::Sv48,
+        }
+    }
+
+    /// Returns true if all bits above the highest translated bit are copies of it.
+    fn is_canonical(&self, virt: u64) -> bool {
+        let upper = (virt as i64) >> (self.virtual_address_bits() - 1);
+        upper == 0 || upper == -1
+    }
+}
+
+impl PageTableEntry {
+    pub const VALID: u64 = 1 << 0;
+    pub const READ: u64 = 1 << 1;
+    pub const WRITE: u64 = 1 << 2;
+    pub const EXECUTE: u64 = 1 << 3;
+    pub const USER: u64 = 1 << 4;
+    pub const GLOBAL: u64 = 1 << 5;
+    pub const ACCESSED: u64 = 1 << 6;
+    pub const DIRTY: u64 = 1 << 7;
+
+    const FLAGS_MASK: u64 = 0x3ff;
+    const PPN_SHIFT: u64 = 10;
+    const PPN_MASK: u64 = 0xfff_ffff_ffff;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn new(address: u64, flags: u64) -> Self {
+        Self((((address >> 12) & Self::PPN_MASK) << Self::PPN_SHIFT) | (flags & Self::FLAGS_MASK))
+    }
+
+    pub fn address(&self) -> u64 {
+        ((self.0 >> Self::PPN_SHIFT) & Self::PPN_MASK) << 12
+    }
+
+    pub fn flags(&self) -> u64 {
+        self.0 & Self::FLAGS_MASK
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.contains(Self::VALID)
+    }
+
+    /// Returns true if this entry maps memory rather than pointing to the next level table.
+    pub fn is_leaf(&self) -> bool {
+        (self.0 & (Self::READ | Self::WRITE | Self::EXECUTE)) != 0
+    }
+
+    pub fn set(&mut self, address: u64, flags: u64) {
+        *self = Self::new(address, flags);
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl PageTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: [PageTableEntry::empty(); ENTRY_COUNT],
+        }
+    }
+
+    pub fn zero(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.clear();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PageTableEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Default for PageTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for PageTable {
+    type Output = PageTableEntry;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.entries[index]
+    }
+}
+
+impl IndexMut<usize> for PageTable {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.entries[index]
+    }
+}
+
+/// Returns the index into the table at `level` (1 is the lowest) that `virt` uses.
+fn table_index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * (level - 1))) & 0x1ff) as usize
+}
+
+impl PageMapper {
+    /// Allocates and zeroes a new root page table.
+    pub fn new(
+        mode: PagingMode,
+        allocator: &mut impl FrameAllocator,
+        phys_offset: u64,
+    ) -> Result<Self, Error> {
+        let root = allocator
+            .allocate_frame()
+            .ok_or("Failed to allocate a frame for the root page table")?;
+
+        let mapper = unsafe { Self::from_root(mode, root, phys_offset) };
+        unsafe { mapper.table_at(root).zero() };
+
+        Ok(mapper)
+    }
+
+    /// # Safety
+    ///
+    /// `root` must be the physical address of a valid root page table for `mode`, and every
+    /// table it references must be accessible at `phys_offset + physical address`.
+    pub unsafe fn from_root(mode: PagingMode, root: u64, phys_offset: u64) -> Self {
+        Self {
+            root,
+            phys_offset,
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> PagingMode {
+        self.mode
+    }
+
+    /// Returns the physical address of the root page table.
+    pub fn root_address(&self) -> u64 {
+        self.root
+    }
+
+    unsafe fn table_at<'a>(&self, address: u64) -> &'a mut PageTable {
+        &mut *((address + self.phys_offset) as *mut PageTable)
+    }
+
+    /// Returns the `satp` value that selects this page table with `asid`.
+    pub fn satp(&self, asid: u16) -> Satp {
+        Satp::new(self.mode.satp_mode(), asid, self.root)
+    }
+
+    /// Writes this page table into `satp` and flushes all stale translations, including global
+    /// ones left behind by the previous page table.
+    ///
+    /// # Safety
+    ///
+    /// The new mappings must cover the running code, its stack, and all memory that is used
+    /// after the switch.
+    pub unsafe fn activate(&self, asid: u16) {
+        self.satp(asid).write();
+        sfence_vma_all();
+    }
+}
+
+impl PageMap for PageMapper {
+    type Flags = u64;
+
+    fn higher_half_start(&self) -> u64 {
+        self.mode.higher_half_start()
+    }
+
+    /// `flags` must include at least one of READ, WRITE or EXECUTE. The valid and accessed bits
+    /// are always set, and the dirty bit is set for writable pages, so that hardware which does
+    /// not manage A/D bits does not fault on first access.
+    unsafe fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: PageSize,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        if !self.mode.is_canonical(virt) {
+            return Err("Virtual address is not canonical");
+        }
+        if !virt.is_multiple_of(size.bytes()) || !phys.is_multiple_of(size.bytes()) {
+            return Err("Address is not aligned to the page size");
+        }
+        if (flags & (PageTableEntry::READ | PageTableEntry::WRITE | PageTableEntry::EXECUTE)) == 0 {
+            return Err("Leaf page table entries need at least one of R, W or X");
+        }
+        if (flags & (PageTableEntry::READ | PageTableEntry::WRITE)) == PageTableEntry::WRITE {
+            return Err("Writable pages must also be readable");
+        }
+
+        let mut table = self.table_at(self.root);
+        for level in (size.level() + 1..=self.mode.levels()).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                let frame = allocator
+                    .allocate_frame()
+                    .ok_or("Failed to allocate a frame for a page table")?;
+                self.table_at(frame).zero();
+                entry.set(frame, PageTableEntry::VALID);
+            } else if entry.is_leaf() {
+                return Err("Virtual address is already mapped by a larger page");
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        let entry = &mut table[table_index(virt, size.level())];
+        if entry.is_valid() {
+            return Err("Virtual address is already mapped");
+        }
+
+        let mut leaf_flags = flags | PageTableEntry::VALID | PageTableEntry::ACCESSED;
+        if (flags & PageTableEntry::WRITE) != 0 {
+            leaf_flags |= PageTableEntry::DIRTY;
+        }
+        entry.set(phys, leaf_flags);
+
+        Ok(())
+    }
+
+    unsafe fn unmap(&mut self, virt: u64) -> Result<(u64, PageSize), Error> {
+        let mut table = self.table_at(self.root);
+        for level in (1..=self.mode.levels()).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                return Err("Virtual address is not mapped");
+            }
+
+            if entry.is_leaf() {
+                let phys = entry.address();
+                entry.clear();
+                sfence_vma_address(virt);
+                return Ok((phys, PageSize::from_level(level)));
+            }
+
+            if level == 1 {
+                break;
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        Err("Page table entry at the lowest level is not a leaf")
+    }
+
+    fn translate(&self, virt: u64) -> Option<u64> {
+        let mut table = unsafe { self.table_at(self.root) };
+        for level in (1..=self.mode.levels()).rev() {
+            let entry = &table[table_index(virt, level)];
+
+            if !entry.is_valid() {
+                return None;
+            }
+
+            if entry.is_leaf() {
+                let size = PageSize::from_level(level).bytes();
+                return Some(entry.address() + (virt & (size - 1)));
+            }
+
+            if level == 1 {
+                break;
+            }
+
+            table = unsafe { self.table_at(entry.address()) };
+        }
+
+        None
+    }
+}
+
+impl fmt::Debug for PageTableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageTableEntry")
+            .field("address", &format_args!("{:#x}", self.address()))
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .finish()
+    }
+}
@@ -0,0 +1,250 @@
+// The platform-level interrupt controller, which routes external interrupts such as the UART's to
+// contexts. A context is one privilege mode of one hart, and it takes a source's interrupt when
+// the source is enabled for it and has a priority above its threshold. The handler claims the
+// interrupt, which returns the source, and completes it when the source has been serviced.
+//
+// QEMU's virt machine gives every hart an M-mode and an S-mode context, but the order of the
+// contexts comes from the PLIC's `interrupts-extended` property.
+//
+// `install_dispatch` connects one context to the supervisor external interrupt of the trap
+// module. Each claimed source is passed to the handler that `set_handler` gave it, and completed
+// once that returns.
+
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::{
+    fdt::Fdt,
+    riscv64::trap::{self, Interrupt, TrapFrame},
+};
+
+pub type Error = &'static str;
+
+const COMPATIBLES: &[&str] = &["sifive,plic-1.0.0", "riscv,plic0"];
+
+const PRIORITY: u64 = 0x0;
+const PENDING: u64 = 0x1000;
+const ENABLE: u64 = 0x2000;
+const ENABLE_STRIDE: u64 = 0x80;
+const CONTEXT: u64 = 0x20_0000;
+const CONTEXT_STRIDE: u64 = 0x1000;
+const THRESHOLD: u64 = 0x0;
+const CLAIM_COMPLETE: u64 = 0x4;
+
+/// The interrupt that a hart's interrupt controller raises for a supervisor external interrupt.
+const SUPERVISOR_EXTERNAL_INTERRUPT: u32 = 9;
+
+/// The most sources a PLIC can have, including the reserved source 0.
+pub const MAX_SOURCES: u32 = 1024;
+
+/// Handles an interrupt from `source`. It must make the device stop requesting the interrupt,
+/// such as by reading every byte that a UART received, before the source is completed.
+pub type SourceHandler = fn(source: u32);
+
+// The PLIC and context that `install_dispatch` was last called with
+static DISPATCH_BASE: AtomicU64 = AtomicU64::new(0);
+static DISPATCH_SOURCE_COUNT: AtomicU32 = AtomicU32::new(0);
+static DISPATCH_CONTEXT: AtomicU32 = AtomicU32::new(0);
+
+static SOURCE_HANDLERS: [AtomicUsize; MAX_SOURCES as usize] =
+    [const { AtomicUsize::new(0) }; MAX_SOURCES as usize];
+
+#[derive(Copy, Clone, Debug)]
+pub struct Plic {
+    base: u64,
+    source_count: u32,
+}
+
+impl Plic {
+    /// `source_count` includes the reserved source 0.
+    pub fn new(base: u64, source_count: u32) -> Self {
+        Self {
+            base,
+            source_count: source_count.min(MAX_SOURCES),
+        }
+    }
+
+    /// Finds the PLIC in the device tree, using `riscv,ndev` for the number of sources.
+    pub fn from_device_tree(fdt: &Fdt) -> Option<Self> {
+        let node = fdt
+            .find_any_compatible(COMPATIBLES)
+            .filter(|node| node.is_enabled())?;
+        let base = node.first_reg()?.address;
+        let source_count = node
+            .property("riscv,ndev")
+            .and_then(|property| property.as_u32())
+            .map_or(MAX_SOURCES, |count| count.saturating_add(1));
+
+        Some(Self::new(base, source_count))
+    }
+
+    /// Returns the S-mode context of `hart_id`, by finding its interrupt controller in the PLIC's
+    /// `interrupts-extended`.
+    pub fn supervisor_context(fdt: &Fdt, hart_id: u64) -> Option<u32> {
+        let plic = fdt.find_any_compatible(COMPATIBLES)?;
+        let intc = fdt
+            .cpus()
+            .find(|cpu| cpu.first_reg().map(|reg| reg.address) == Some(hart_id))?
+            .child("interrupt-controller")?
+            .phandle()?;
+
+        // Hart interrupt controllers have one interrupt cell, so each context is two cells
+        let mut cells = plic.property("interrupts-extended")?.as_cells();
+        let mut context = 0;
+        while let (Some(phandle), Some(interrupt)) = (cells.next(), cells.next()) {
+            if phandle == intc && interrupt == SUPERVISOR_EXTERNAL_INTERRUPT {
+                return Some(context);
+            }
+            context += 1;
+        }
+
+        None
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn source_count(&self) -> u32 {
+        self.source_count
+    }
+
+    fn read(&self, offset: u64) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    unsafe fn write(&self, offset: u64, value: u32) {
+        ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    fn check_source(&self, source: u32) -> Result<(), Error> {
+        if source == 0 || source >= self.source_count {
+            Err("Invalid PLIC interrupt source")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn enable_bit(context: u32, source: u32) -> (u64, u32) {
+        let offset = ENABLE + context as u64 * ENABLE_STRIDE + (source / 32) as u64 * 4;
+        (offset, 1 << (source % 32))
+    }
+
+    fn context_register(context: u32, register: u64) -> u64 {
+        CONTEXT + context as u64 * CONTEXT_STRIDE + register
+    }
+
+    /// Sets the priority of `source`, where 0 means it never interrupts.
+    pub fn set_priority(&self, source: u32, priority: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        unsafe { self.write(PRIORITY + source as u64 * 4, priority) };
+        Ok(())
+    }
+
+    pub fn priority(&self, source: u32) -> Result<u32, Error> {
+        self.check_source(source)?;
+        Ok(self.read(PRIORITY + source as u64 * 4))
+    }
+
+    pub fn is_pending(&self, source: u32) -> Result<bool, Error> {
+        self.check_source(source)?;
+        let pending = self.read(PENDING + (source / 32) as u64 * 4);
+        Ok(pending & (1 << (source % 32)) != 0)
+    }
+
+    /// # Safety
+    ///
+    /// The hart of `context` must handle external interrupts from `source`.
+    pub unsafe fn enable(&self, context: u32, source: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        let (offset, bit) = Self::enable_bit(context, source);
+        self.write(offset, self.read(offset) | bit);
+        Ok(())
+    }
+
+    pub fn disable(&self, context: u32, source: u32) -> Result<(), Error> {
+        self.check_source(source)?;
+        let (offset, bit) = Self::enable_bit(context, source);
+        unsafe { self.write(offset, self.read(offset) & !bit) };
+        Ok(())
+    }
+
+    /// Interrupts with a priority at or below `threshold` are not delivered to `context`.
+    pub fn set_threshold(&self, context: u32, threshold: u32) {
+        unsafe { self.write(Self::context_register(context, THRESHOLD), threshold) };
+    }
+
+    /// Disables every source for `context` and sets its threshold to 0, so that it takes any
+    /// source that is enabled later.
+    pub fn init_context(&self, context: u32) {
+        for word in 0..self.source_count.div_ceil(32) {
+            let (offset, _) = Self::enable_bit(context, word * 32);
+            unsafe { self.write(offset, 0) };
+        }
+        self.set_threshold(context, 0);
+    }
+
+    /// Claims the highest priority pending interrupt of `context`, returning its source.
+    pub fn claim(&self, context: u32) -> Option<u32> {
+        match self.read(Self::context_register(context, CLAIM_COMPLETE)) {
+            0 => None,
+            source => Some(source),
+        }
+    }
+
+    /// Completes an interrupt that was returned by [`Self::claim`], which lets `source`
+    /// interrupt again.
+    pub fn complete(&self, context: u32, source: u32) {
+        unsafe { self.write(Self::context_register(context, CLAIM_COMPLETE), source) };
+    }
+
+    /// Sets the handler that is called for `source` once [`Self::install_dispatch`] was called.
+    pub fn set_handler(&self, source: u32, handler: SourceHandler) -> Result<(), Error> {
+        self.check_source(source)?;
+        SOURCE_HANDLERS[source as usize].store(handler as usize, Ordering::Release);
+        Ok(())
+    }
+
+    /// Makes supervisor external interrupts claim every pending source of `context`, call its
+    /// handler, and complete it. A source without a handler is disabled for `context` instead, so
+    /// that it does not interrupt again. Only one context is dispatched, which should be the
+    /// S-mode context of the hart that enables external interrupts.
+    ///
+    /// # Safety
+    ///
+    /// Supervisor external interrupts must be disabled, and must only be enabled on the hart of
+    /// `context`.
+    pub unsafe fn install_dispatch(&self, context: u32) -> Result<(), Error> {
+        DISPATCH_SOURCE_COUNT.store(self.source_count, Ordering::Relaxed);
+        DISPATCH_CONTEXT.store(context, Ordering::Relaxed);
+        DISPATCH_BASE.store(self.base, Ordering::Release);
+
+        trap::set_interrupt_handler(Interrupt::SupervisorExternal, dispatch)
+    }
+}
+
+fn dispatch(_frame: &mut TrapFrame) {
+    let base = DISPATCH_BASE.load(Ordering::Acquire);
+    let plic = Plic::new(base, DISPATCH_SOURCE_COUNT.load(Ordering::Relaxed));
+    let context = DISPATCH_CONTEXT.load(Ordering::Relaxed);
+
+    while let Some(source) = plic.claim(context) {
+        let handler = SOURCE_HANDLERS
+            .get(source as usize)
+            .map_or(0, |handler| handler.load(Ordering::Acquire));
+
+        match handler {
+            0 => {
+                let _ = plic.disable(context, source);
+            }
+            handler => {
+                let handler: SourceHandler = unsafe { mem::transmute(handler) };
+                handler(source);
+            }
+        }
+
+        plic.complete(context, source);
+    }
+}
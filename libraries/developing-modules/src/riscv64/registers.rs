@@ -0,0 +1,250 @@
+use core::arch::asm;
+
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SatpMode {
+    Bare = 0,
+    Sv39 = 8,
+    Sv48 = 9,
+    Sv57 = 10,
+}
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Satp(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sstatus(pub u64);
+
+/// The supervisor interrupt enable register, which has a bit per interrupt cause.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sie(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stvec(pub u64);
+
+impl Satp {
+    const MODE_SHIFT: u64 = 60;
+    const ASID_SHIFT: u64 = 44;
+    const ASID_MASK: u64 = 0xffff;
+    const PPN_MASK: u64 = 0xfff_ffff_ffff;
+
+    /// Creates a value that selects `mode` with the root page table at physical address `root`.
+    pub fn new(mode: SatpMode, asid: u16, root: u64) -> Self {
+        Self(
+            ((mode as u64) << Self::MODE_SHIFT)
+                | ((asid as u64) << Self::ASID_SHIFT)
+                | ((root >> 12) & Self::PPN_MASK),
+        )
+    }
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("csrr {}, satp", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The new root page table must map the running code, its stack, and all memory used after
+    /// the switch. Stale translations must be flushed with [`sfence_vma_all`] or
+    /// [`sfence_vma_asid`].
+    pub unsafe fn write(self) {
+        asm!("csrw satp, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+
+    pub fn mode(&self) -> Option<SatpMode> {
+        match self.0 >> Self::MODE_SHIFT {
+            0 => Some(SatpMode::Bare),
+            8 => Some(SatpMode::Sv39),
+            9 => Some(SatpMode::Sv48),
+            10 => Some(SatpMode::Sv57),
+            _ => None,
+        }
+    }
+
+    pub fn asid(&self) -> u16 {
+        ((self.0 >> Self::ASID_SHIFT) & Self::ASID_MASK) as u16
+    }
+
+    /// Returns the physical address of the root page table.
+    pub fn root_address(&self) -> u64 {
+        (self.0 & Self::PPN_MASK) << 12
+    }
+}
+
+impl Sstatus {
+    /// Supervisor interrupts are enabled.
+    pub const SIE: u64 = 1 << 1;
+    /// The value of SIE before the last trap.
+    pub const SPIE: u64 = 1 << 5;
+    /// The last trap came from S-mode instead of U-mode.
+    pub const SPP: u64 = 1 << 8;
+    /// S-mode may access user pages.
+    pub const SUM: u64 = 1 << 18;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("csrr {}, sstatus", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// Sets the given bits.
+    ///
+    /// # Safety
+    ///
+    /// Setting SIE lets interrupts run, so their handlers must be ready.
+    pub unsafe fn set(bits: u64) {
+        asm!("csrs sstatus, {}", in(reg) bits, options(nostack, preserves_flags));
+    }
+
+    /// Clears the given bits.
+    ///
+    /// # Safety
+    ///
+    /// Clearing SUM while user memory is accessed causes page faults.
+    pub unsafe fn clear(bits: u64) {
+        asm!("csrc sstatus, {}", in(reg) bits, options(nostack, preserves_flags));
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl Sie {
+    pub const SOFTWARE: u64 = 1 << 1;
+    pub const TIMER: u64 = 1 << 5;
+    pub const EXTERNAL: u64 = 1 << 9;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("csrr {}, sie", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// Enables the given interrupts.
+    ///
+    /// # Safety
+    ///
+    /// The interrupts are taken once `sstatus.SIE` is set, so their handlers must be ready.
+    pub unsafe fn set(bits: u64) {
+        asm!("csrs sie, {}", in(reg) bits, options(nostack, preserves_flags));
+    }
+
+    pub fn clear(bits: u64) {
+        unsafe {
+            asm!("csrc sie, {}", in(reg) bits, options(nostack, preserves_flags));
+        }
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl Stvec {
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("csrr {}, stvec", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// Creates a value that sends every trap to `address`, which must be 4 byte aligned.
+    pub fn direct(address: u64) -> Self {
+        Self(address & !0b11)
+    }
+
+    /// # Safety
+    ///
+    /// The address must point to a trap vector that stays mapped for as long as it is installed.
+    pub unsafe fn write(self) {
+        asm!("csrw stvec, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+}
+
+/// Reads the `time` CSR, which counts at the device tree's `timebase-frequency`. This is the
+/// clock that the SBI timer compares against.
+pub fn time() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("rdtime {}", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the cause of the last trap taken to S-mode.
+pub fn scause() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("csrr {}, scause", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the trap value of the last trap taken to S-mode, such as a faulting address.
+pub fn stval() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("csrr {}, stval", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Flushes every translation for every address space.
+pub fn sfence_vma_all() {
+    unsafe {
+        asm!("sfence.vma", options(nostack, preserves_flags));
+    }
+}
+
+/// Flushes the translations of a single virtual address for every address space.
+pub fn sfence_vma_address(virt: u64) {
+    unsafe {
+        asm!("sfence.vma {}, zero", in(reg) virt, options(nostack, preserves_flags));
+    }
+}
+
+/// Flushes every non-global translation for a single address space.
+pub fn sfence_vma_asid(asid: u16) {
+    unsafe {
+        asm!("sfence.vma zero, {}", in(reg) asid as u64, options(nostack, preserves_flags));
+    }
+}
+
+/// Flushes the non-global translation of a single virtual address in a single address space.
+pub fn sfence_vma(virt: u64, asid: u16) {
+    unsafe {
+        asm!(
+            "sfence.vma {}, {}",
+            in(reg) virt,
+            in(reg) asid as u64,
+            options(nostack, preserves_flags),
+        );
+    }
+}
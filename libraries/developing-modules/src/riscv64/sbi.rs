@@ -0,0 +1,659 @@
+// Calls into the supervisor execution environment (SEE), usually OpenSBI, through the RISC-V
+// Supervisor Binary Interface. Extension and function IDs follow SBI specification v2.0.
+//
+// Firmware that predates SBI v0.2 only implements the legacy extensions, so [`Sbi::probe`]
+// records which extensions exist and its methods fall back to the legacy calls where one exists.
+
+use core::{arch::asm, fmt};
+
+pub const EXTENSION_BASE: u64 = 0x10;
+pub const EXTENSION_TIMER: u64 = 0x5449_4d45;
+pub const EXTENSION_IPI: u64 = 0x73_5049;
+pub const EXTENSION_RFENCE: u64 = 0x5246_4e43;
+pub const EXTENSION_HSM: u64 = 0x48_534d;
+pub const EXTENSION_SYSTEM_RESET: u64 = 0x5352_5354;
+pub const EXTENSION_DEBUG_CONSOLE: u64 = 0x4442_434e;
+
+/// The value returned by every non-legacy SBI call, in a0 and a1.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SbiRet {
+    pub error: i64,
+    pub value: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SbiError {
+    Failed,
+    NotSupported,
+    InvalidParam,
+    Denied,
+    InvalidAddress,
+    AlreadyAvailable,
+    AlreadyStarted,
+    AlreadyStopped,
+    NoSharedMemory,
+    InvalidState,
+    BadRange,
+    Timeout,
+    Io,
+    Unknown(i64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SbiSpecVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// A set of harts, given as a bit mask relative to a base hart ID.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HartMask {
+    pub mask: u64,
+    /// The hart ID of bit 0 of the mask, or `u64::MAX` to select every hart.
+    pub base: u64,
+}
+
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HartState {
+    Started = 0,
+    Stopped = 1,
+    StartPending = 2,
+    StopPending = 3,
+    Suspended = 4,
+    SuspendPending = 5,
+    ResumePending = 6,
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetType {
+    Shutdown = 0,
+    ColdReboot = 1,
+    WarmReboot = 2,
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    NoReason = 0,
+    SystemFailure = 1,
+}
+
+impl SbiRet {
+    pub fn into_result(self) -> Result<u64, SbiError> {
+        match self.error {
+            0 => Ok(self.value),
+            error => Err(SbiError::from_code(error)),
+        }
+    }
+}
+
+impl SbiError {
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            -1 => Self::Failed,
+            -2 => Self::NotSupported,
+            -3 => Self::InvalidParam,
+            -4 => Self::Denied,
+            -5 => Self::InvalidAddress,
+            -6 => Self::AlreadyAvailable,
+            -7 => Self::AlreadyStarted,
+            -8 => Self::AlreadyStopped,
+            -9 => Self::NoSharedMemory,
+            -10 => Self::InvalidState,
+            -11 => Self::BadRange,
+            -12 => Self::Timeout,
+            -13 => Self::Io,
+            code => Self::Unknown(code),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Failed => "SBI call failed",
+            Self::NotSupported => "SBI call is not supported",
+            Self::InvalidParam => "SBI call has an invalid parameter",
+            Self::Denied => "SBI call was denied",
+            Self::InvalidAddress => "SBI call has an invalid address",
+            Self::AlreadyAvailable => "SBI resource is already available",
+            Self::AlreadyStarted => "SBI hart is already started",
+            Self::AlreadyStopped => "SBI hart is already stopped",
+            Self::NoSharedMemory => "SBI shared memory is not available",
+            Self::InvalidState => "SBI call is invalid in the current state",
+            Self::BadRange => "SBI call has a bad range",
+            Self::Timeout => "SBI call timed out",
+            Self::Io => "SBI call had an I/O error",
+            Self::Unknown(_) => "SBI call returned an unknown error",
+        }
+    }
+}
+
+impl fmt::Display for SbiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(code) => write!(f, "{} ({})", self.as_str(), code),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl From<SbiError> for &'static str {
+    fn from(error: SbiError) -> Self {
+        error.as_str()
+    }
+}
+
+impl fmt::Display for SbiSpecVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl HartMask {
+    pub fn all() -> Self {
+        Self {
+            mask: 0,
+            base: u64::MAX,
+        }
+    }
+
+    pub fn single(hart_id: u64) -> Self {
+        Self {
+            mask: 1,
+            base: hart_id,
+        }
+    }
+
+    /// Returns the mask relative to hart 0, which is what the legacy calls take, if every
+    /// selected hart fits into it.
+    fn legacy_mask(&self) -> Option<u64> {
+        match self.base {
+            u64::MAX => Some(u64::MAX),
+            0 => Some(self.mask),
+            base if base < 64 && self.mask.leading_zeros() as u64 >= base => {
+                Some(self.mask << base)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl HartState {
+    fn from_value(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(Self::Started),
+            1 => Some(Self::Stopped),
+            2 => Some(Self::StartPending),
+            3 => Some(Self::StopPending),
+            4 => Some(Self::Suspended),
+            5 => Some(Self::SuspendPending),
+            6 => Some(Self::ResumePending),
+            _ => None,
+        }
+    }
+}
+
+/// Performs an SBI call with up to 6 arguments.
+///
+/// # Safety
+///
+/// The call must be valid for the current state of the system; for example, starting a hart
+/// with an invalid start address is undefined behavior.
+pub unsafe fn ecall(extension: u64, function: u64, args: [u64; 6]) -> SbiRet {
+    let error: i64;
+    let value: u64;
+
+    asm!(
+        "ecall",
+        inlateout("a0") args[0] => error,
+        inlateout("a1") args[1] => value,
+        in("a2") args[2],
+        in("a3") args[3],
+        in("a4") args[4],
+        in("a5") args[5],
+        in("a6") function,
+        in("a7") extension,
+        options(nostack),
+    );
+
+    SbiRet { error, value }
+}
+
+fn call(extension: u64, function: u64, args: [u64; 6]) -> Result<u64, SbiError> {
+    unsafe { ecall(extension, function, args) }.into_result()
+}
+
+// Base extension
+
+pub fn get_spec_version() -> Result<SbiSpecVersion, SbiError> {
+    let value = call(EXTENSION_BASE, 0, [0; 6])?;
+
+    Ok(SbiSpecVersion {
+        major: ((value >> 24) & 0x7f) as u32,
+        minor: (value & 0xff_ffff) as u32,
+    })
+}
+
+pub fn get_impl_id() -> Result<u64, SbiError> {
+    call(EXTENSION_BASE, 1, [0; 6])
+}
+
+pub fn get_impl_version() -> Result<u64, SbiError> {
+    call(EXTENSION_BASE, 2, [0; 6])
+}
+
+/// Returns true if the SEE implements `extension`.
+pub fn probe_extension(extension: u64) -> Result<bool, SbiError> {
+    call(EXTENSION_BASE, 3, [extension, 0, 0, 0, 0, 0]).map(|value| value != 0)
+}
+
+pub fn get_mvendorid() -> Result<u64, SbiError> {
+    call(EXTENSION_BASE, 4, [0; 6])
+}
+
+pub fn get_marchid() -> Result<u64, SbiError> {
+    call(EXTENSION_BASE, 5, [0; 6])
+}
+
+pub fn get_mimpid() -> Result<u64, SbiError> {
+    call(EXTENSION_BASE, 6, [0; 6])
+}
+
+/// Returns the name of a known SBI implementation ID.
+pub fn impl_name(impl_id: u64) -> Option<&'static str> {
+    match impl_id {
+        0 => Some("Berkeley Boot Loader"),
+        1 => Some("OpenSBI"),
+        2 => Some("Xvisor"),
+        3 => Some("KVM"),
+        4 => Some("RustSBI"),
+        5 => Some("Diosix"),
+        6 => Some("Coffer"),
+        7 => Some("Xen Project"),
+        8 => Some("PolarFire Hart Software Services"),
+        9 => Some("coreboot"),
+        10 => Some("oreboot"),
+        11 => Some("bhyve"),
+        _ => None,
+    }
+}
+
+// Timer extension
+
+/// Programs the next timer interrupt for the calling hart at absolute time `stime_value`, and
+/// clears the pending supervisor timer interrupt.
+pub fn set_timer(stime_value: u64) -> Result<(), SbiError> {
+    call(EXTENSION_TIMER, 0, [stime_value, 0, 0, 0, 0, 0]).map(|_| ())
+}
+
+// IPI extension
+
+/// Sends a supervisor software interrupt to the harts in `harts`.
+pub fn send_ipi(harts: HartMask) -> Result<(), SbiError> {
+    call(EXTENSION_IPI, 0, [harts.mask, harts.base, 0, 0, 0, 0]).map(|_| ())
+}
+
+// RFENCE extension
+
+/// Makes the harts in `harts` execute `fence.i`.
+pub fn remote_fence_i(harts: HartMask) -> Result<(), SbiError> {
+    call(EXTENSION_RFENCE, 0, [harts.mask, harts.base, 0, 0, 0, 0]).map(|_| ())
+}
+
+/// Makes the harts in `harts` execute `sfence.vma` for `size` bytes starting at `start`. A start
+/// and size of 0, or a size of `u64::MAX`, flushes every address.
+pub fn remote_sfence_vma(harts: HartMask, start: u64, size: u64) -> Result<(), SbiError> {
+    call(
+        EXTENSION_RFENCE,
+        1,
+        [harts.mask, harts.base, start, size, 0, 0],
+    )
+    .map(|_| ())
+}
+
+/// Same as [`remote_sfence_vma`], but only for the address space `asid`.
+pub fn remote_sfence_vma_asid(
+    harts: HartMask,
+    start: u64,
+    size: u64,
+    asid: u16,
+) -> Result<(), SbiError> {
+    call(
+        EXTENSION_RFENCE,
+        2,
+        [harts.mask, harts.base, start, size, asid as u64, 0],
+    )
+    .map(|_| ())
+}
+
+// Hart state management extension
+
+/// Starts a stopped hart in S-mode at the physical address `start_address`, with its hart ID in
+/// a0, `opaque` in a1, and the MMU and interrupts disabled.
+///
+/// # Safety
+///
+/// `start_address` must be the physical address of code that can run with the MMU disabled and
+/// sets up its own stack.
+pub unsafe fn hart_start(hart_id: u64, start_address: u64, opaque: u64) -> Result<(), SbiError> {
+    ecall(EXTENSION_HSM, 0, [hart_id, start_address, opaque, 0, 0, 0])
+        .into_result()
+        .map(|_| ())
+}
+
+/// Stops the calling hart, returning only if the hart could not be stopped.
+///
+/// # Safety
+///
+/// Nothing may depend on the calling hart making progress after this call.
+pub unsafe fn hart_stop() -> SbiError {
+    match ecall(EXTENSION_HSM, 1, [0; 6]).into_result() {
+        Ok(_) => SbiError::Failed,
+        Err(err) => err,
+    }
+}
+
+pub fn hart_get_status(hart_id: u64) -> Result<HartState, SbiError> {
+    let value = call(EXTENSION_HSM, 2, [hart_id, 0, 0, 0, 0, 0])?;
+    HartState::from_value(value).ok_or(SbiError::Unknown(value as i64))
+}
+
+// System reset extension
+
+/// Resets or shuts down the system, returning only if the request failed.
+pub fn system_reset(reset_type: ResetType, reason: ResetReason) -> SbiError {
+    let args = [reset_type as u64, reason as u64, 0, 0, 0, 0];
+
+    match call(EXTENSION_SYSTEM_RESET, 0, args) {
+        Ok(_) => SbiError::Failed,
+        Err(err) => err,
+    }
+}
+
+// Debug console extension
+//
+// Buffers are passed by physical address, so they must be identity mapped when paging is on.
+
+/// Writes as much of `bytes` as the console accepts, and returns the number of bytes written.
+pub fn debug_console_write(bytes: &[u8]) -> Result<usize, SbiError> {
+    let args = [bytes.len() as u64, bytes.as_ptr() as u64, 0, 0, 0, 0];
+    call(EXTENSION_DEBUG_CONSOLE, 0, args).map(|written| written as usize)
+}
+
+/// Reads up to `bytes.len()` bytes without blocking, and returns the number of bytes read.
+pub fn debug_console_read(bytes: &mut [u8]) -> Result<usize, SbiError> {
+    let args = [bytes.len() as u64, bytes.as_mut_ptr() as u64, 0, 0, 0, 0];
+    call(EXTENSION_DEBUG_CONSOLE, 1, args).map(|read| read as usize)
+}
+
+pub fn debug_console_write_byte(byte: u8) -> Result<(), SbiError> {
+    call(EXTENSION_DEBUG_CONSOLE, 2, [byte as u64, 0, 0, 0, 0, 0]).map(|_| ())
+}
+
+/// The legacy extensions of SBI v0.1, which are deprecated but are all that old firmware has.
+/// They return a single value in a0 instead of an [`SbiRet`].
+pub mod legacy {
+    use core::arch::asm;
+
+    pub const SET_TIMER: u64 = 0x00;
+    pub const CONSOLE_PUTCHAR: u64 = 0x01;
+    pub const CONSOLE_GETCHAR: u64 = 0x02;
+    pub const CLEAR_IPI: u64 = 0x03;
+    pub const SEND_IPI: u64 = 0x04;
+    pub const REMOTE_FENCE_I: u64 = 0x05;
+    pub const REMOTE_SFENCE_VMA: u64 = 0x06;
+    pub const REMOTE_SFENCE_VMA_ASID: u64 = 0x07;
+    pub const SHUTDOWN: u64 = 0x08;
+
+    fn call(extension: u64, args: [u64; 4]) -> i64 {
+        let value: i64;
+
+        unsafe {
+            asm!(
+                "ecall",
+                inlateout("a0") args[0] => value,
+                inlateout("a1") args[1] => _,
+                in("a2") args[2],
+                in("a3") args[3],
+                in("a7") extension,
+                options(nostack),
+            );
+        }
+
+        value
+    }
+
+    // Legacy calls take the hart mask by address. A null address selects every hart.
+    fn hart_mask_address(hart_mask: &u64) -> u64 {
+        match *hart_mask {
+            u64::MAX => 0,
+            _ => hart_mask as *const u64 as u64,
+        }
+    }
+
+    pub fn set_timer(stime_value: u64) {
+        call(SET_TIMER, [stime_value, 0, 0, 0]);
+    }
+
+    pub fn console_putchar(byte: u8) {
+        call(CONSOLE_PUTCHAR, [byte as u64, 0, 0, 0]);
+    }
+
+    /// Returns the next byte from the console, or `None` if there is none.
+    pub fn console_getchar() -> Option<u8> {
+        match call(CONSOLE_GETCHAR, [0; 4]) {
+            value @ 0..=0xff => Some(value as u8),
+            _ => None,
+        }
+    }
+
+    pub fn clear_ipi() {
+        call(CLEAR_IPI, [0; 4]);
+    }
+
+    /// Sends a software interrupt to the harts whose bit is set in `hart_mask`.
+    pub fn send_ipi(hart_mask: u64) {
+        call(SEND_IPI, [hart_mask_address(&hart_mask), 0, 0, 0]);
+    }
+
+    pub fn remote_fence_i(hart_mask: u64) {
+        call(REMOTE_FENCE_I, [hart_mask_address(&hart_mask), 0, 0, 0]);
+    }
+
+    pub fn remote_sfence_vma(hart_mask: u64, start: u64, size: u64) {
+        call(
+            REMOTE_SFENCE_VMA,
+            [hart_mask_address(&hart_mask), start, size, 0],
+        );
+    }
+
+    pub fn remote_sfence_vma_asid(hart_mask: u64, start: u64, size: u64, asid: u16) {
+        call(
+            REMOTE_SFENCE_VMA_ASID,
+            [hart_mask_address(&hart_mask), start, size, asid as u64],
+        );
+    }
+
+    pub fn shutdown() -> ! {
+        call(SHUTDOWN, [0; 4]);
+
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// The SBI extensions that the SEE implements, found with [`Sbi::probe`].
+///
+/// Its methods use the v0.2+ extensions when they are available and fall back to the legacy
+/// calls otherwise. Writing to it prints to the SBI console.
+#[derive(Copy, Clone, Debug)]
+pub struct Sbi {
+    /// The implemented version of the specification, which is 0.1 for legacy-only firmware.
+    pub spec_version: SbiSpecVersion,
+    pub impl_id: Option<u64>,
+    pub impl_version: Option<u64>,
+    pub has_timer: bool,
+    pub has_ipi: bool,
+    pub has_rfence: bool,
+    pub has_hsm: bool,
+    pub has_system_reset: bool,
+    pub has_debug_console: bool,
+}
+
+impl Sbi {
+    pub const LEGACY_VERSION: SbiSpecVersion = SbiSpecVersion { major: 0, minor: 1 };
+
+    pub fn probe() -> Self {
+        // The base extension does not exist before v0.2, so any error means legacy firmware
+        let Ok(spec_version) = get_spec_version() else {
+            return Self {
+                spec_version: Self::LEGACY_VERSION,
+                impl_id: None,
+                impl_version: None,
+                has_timer: false,
+                has_ipi: false,
+                has_rfence: false,
+                has_hsm: false,
+                has_system_reset: false,
+                has_debug_console: false,
+            };
+        };
+        let has = |extension| probe_extension(extension).unwrap_or(false);
+
+        Self {
+            spec_version,
+            impl_id: get_impl_id().ok(),
+            impl_version: get_impl_version().ok(),
+            has_timer: has(EXTENSION_TIMER),
+            has_ipi: has(EXTENSION_IPI),
+            has_rfence: has(EXTENSION_RFENCE),
+            has_hsm: has(EXTENSION_HSM),
+            has_system_reset: has(EXTENSION_SYSTEM_RESET),
+            has_debug_console: has(EXTENSION_DEBUG_CONSOLE),
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.spec_version < SbiSpecVersion { major: 0, minor: 2 }
+    }
+
+    pub fn impl_name(&self) -> Option<&'static str> {
+        self.impl_id.and_then(impl_name)
+    }
+
+    pub fn set_timer(&self, stime_value: u64) -> Result<(), SbiError> {
+        if self.has_timer {
+            set_timer(stime_value)
+        } else {
+            legacy::set_timer(stime_value);
+            Ok(())
+        }
+    }
+
+    pub fn send_ipi(&self, harts: HartMask) -> Result<(), SbiError> {
+        if self.has_ipi {
+            send_ipi(harts)
+        } else {
+            legacy::send_ipi(harts.legacy_mask().ok_or(SbiError::InvalidParam)?);
+            Ok(())
+        }
+    }
+
+    pub fn remote_fence_i(&self, harts: HartMask) -> Result<(), SbiError> {
+        if self.has_rfence {
+            remote_fence_i(harts)
+        } else {
+            legacy::remote_fence_i(harts.legacy_mask().ok_or(SbiError::InvalidParam)?);
+            Ok(())
+        }
+    }
+
+    pub fn remote_sfence_vma(
+        &self,
+        harts: HartMask,
+        start: u64,
+        size: u64,
+    ) -> Result<(), SbiError> {
+        if self.has_rfence {
+            remote_sfence_vma(harts, start, size)
+        } else {
+            let hart_mask = harts.legacy_mask().ok_or(SbiError::InvalidParam)?;
+            legacy::remote_sfence_vma(hart_mask, start, size);
+            Ok(())
+        }
+    }
+
+    /// Starts a stopped hart, which needs the HSM extension.
+    ///
+    /// # Safety
+    ///
+    /// See [`hart_start`].
+    pub unsafe fn hart_start(
+        &self,
+        hart_id: u64,
+        start_address: u64,
+        opaque: u64,
+    ) -> Result<(), SbiError> {
+        if !self.has_hsm {
+            return Err(SbiError::NotSupported);
+        }
+
+        hart_start(hart_id, start_address, opaque)
+    }
+
+    pub fn hart_get_status(&self, hart_id: u64) -> Result<HartState, SbiError> {
+        if !self.has_hsm {
+            return Err(SbiError::NotSupported);
+        }
+
+        hart_get_status(hart_id)
+    }
+
+    /// Resets or shuts down the system, returning only if the request failed. Legacy firmware
+    /// can only shut down.
+    pub fn system_reset(&self, reset_type: ResetType, reason: ResetReason) -> SbiError {
+        if self.has_system_reset {
+            system_reset(reset_type, reason)
+        } else if reset_type == ResetType::Shutdown {
+            legacy::shutdown()
+        } else {
+            SbiError::NotSupported
+        }
+    }
+
+    pub fn console_write(&self, mut bytes: &[u8]) -> Result<(), SbiError> {
+        if !self.has_debug_console {
+            bytes.iter().for_each(|&byte| legacy::console_putchar(byte));
+            return Ok(());
+        }
+
+        while !bytes.is_empty() {
+            let written = debug_console_write(bytes)?;
+            bytes = &bytes[written.min(bytes.len())..];
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next byte from the console without blocking.
+    pub fn console_read_byte(&self) -> Option<u8> {
+        if !self.has_debug_console {
+            return legacy::console_getchar();
+        }
+
+        let mut byte = 0;
+        match debug_console_read(core::slice::from_mut(&mut byte)) {
+            Ok(1) => Some(byte),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Write for Sbi {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.console_write(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
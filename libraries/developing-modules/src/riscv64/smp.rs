@@ -0,0 +1,55 @@
+// Starts secondary harts through the SBI HSM extension. A started hart enters `__secondary_entry`
+// in S-mode with its hart ID in a0, the address of its `StartInfo` in a1, and the MMU and
+// interrupts disabled. The trampoline switches to the hart's stack from `sections` and jumps to
+// the Rust entry point.
+
+use core::arch::global_asm;
+
+use crate::{
+    riscv64::sbi::{Sbi, SbiError},
+    smp::StartInfo,
+};
+
+/// The Rust entry point of a secondary hart, which is called with its hart ID and the argument
+/// that was given to [`start_secondary`].
+pub type SecondaryEntry = extern "C" fn(hart_id: u64, arg: u64) -> !;
+
+global_asm!(
+    r#"
+.pushsection .text.smp, "ax"
+.balign 4
+.globl __secondary_entry
+__secondary_entry:
+    ld sp, 0(a1)
+    ld t0, 8(a1)
+    ld a1, 16(a1)
+    jr t0
+
+.popsection
+"#
+);
+
+extern "C" {
+    static __secondary_entry: u8;
+}
+
+/// Starts the stopped hart `hart_id` at `entry` with `arg`, on the stack that
+/// [`crate::sections::cpu_stack_range`] gives for `cpu`. Hart IDs can be sparse, so `cpu` is
+/// numbered separately, such as by the position of the hart among the ones that are started.
+///
+/// # Safety
+///
+/// The hart must not be running, and nothing else may use the stack of `cpu`. `entry` runs with
+/// the MMU off, so it must be identity mapped along with everything that it uses.
+pub unsafe fn start_secondary(
+    sbi: &Sbi,
+    hart_id: u64,
+    cpu: usize,
+    entry: SecondaryEntry,
+    arg: u64,
+) -> Result<(), SbiError> {
+    let info = StartInfo::prepare(cpu, entry as usize as u64, arg).ok_or(SbiError::InvalidParam)?;
+
+    let start_address = &__secondary_entry as *const u8 as u64;
+    sbi.hart_start(hart_id, start_address, info)
+}
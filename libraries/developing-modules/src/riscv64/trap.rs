@@ -0,0 +1,449 @@
+// The S-mode trap vector. It runs in direct mode, so every exception and interrupt enters at the
+// same address, saves the general purpose registers and trap CSRs into a `TrapFrame` on the
+// current stack, and calls `dispatch`. The (possibly modified) frame is restored before `sret`.
+//
+// Floating point registers are not saved, which is fine for the bootloaders since their targets
+// do not use them.
+
+use core::{
+    arch::global_asm,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::riscv64::registers::{Sie, Sstatus, Stvec};
+
+pub type InterruptHandler = fn(&mut TrapFrame);
+pub type ExceptionHandler = fn(&mut TrapFrame, Exception);
+
+/// The registers of the interrupted code, saved by the trap vector.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct TrapFrame {
+    /// The general purpose registers, indexed by register number. `x[0]` is always 0 and `x[2]`
+    /// is the stack pointer before the frame was pushed; changing either has no effect.
+    pub x: [u64; 32],
+    pub sepc: u64,
+    pub sstatus: u64,
+    pub scause: Scause,
+    pub stval: u64,
+}
+
+/// The supervisor trap cause register.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scause(pub u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trap {
+    Interrupt(Interrupt),
+    Exception(Exception),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware,
+    SupervisorTimer,
+    SupervisorExternal,
+    CounterOverflow,
+    Other(u64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exception {
+    InstructionMisaligned,
+    InstructionAccessFault,
+    IllegalInstruction,
+    Breakpoint,
+    LoadMisaligned,
+    LoadAccessFault,
+    StoreMisaligned,
+    StoreAccessFault,
+    UserEcall,
+    SupervisorEcall,
+    InstructionPageFault,
+    LoadPageFault,
+    StorePageFault,
+    SoftwareCheck,
+    HardwareError,
+    Other(u64),
+}
+
+// Handlers are stored as a `usize` so that they can be swapped atomically; 0 means none
+static SOFTWARE_HANDLER: AtomicUsize = AtomicUsize::new(0);
+static TIMER_HANDLER: AtomicUsize = AtomicUsize::new(0);
+static EXTERNAL_HANDLER: AtomicUsize = AtomicUsize::new(0);
+static EXCEPTION_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// The offsets in the trap vector below depend on this layout
+const TRAP_FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>();
+const _: () = assert!(TRAP_FRAME_SIZE == 288);
+
+global_asm!(
+    r#"
+.pushsection .text.trap, "ax"
+.balign 4
+.globl __trap_vector
+__trap_vector:
+    addi sp, sp, -{frame_size}
+    sd x1, 8(sp)
+    sd x3, 24(sp)
+    sd x4, 32(sp)
+    sd x5, 40(sp)
+    sd x6, 48(sp)
+    sd x7, 56(sp)
+    sd x8, 64(sp)
+    sd x9, 72(sp)
+    sd x10, 80(sp)
+    sd x11, 88(sp)
+    sd x12, 96(sp)
+    sd x13, 104(sp)
+    sd x14, 112(sp)
+    sd x15, 120(sp)
+    sd x16, 128(sp)
+    sd x17, 136(sp)
+    sd x18, 144(sp)
+    sd x19, 152(sp)
+    sd x20, 160(sp)
+    sd x21, 168(sp)
+    sd x22, 176(sp)
+    sd x23, 184(sp)
+    sd x24, 192(sp)
+    sd x25, 200(sp)
+    sd x26, 208(sp)
+    sd x27, 216(sp)
+    sd x28, 224(sp)
+    sd x29, 232(sp)
+    sd x30, 240(sp)
+    sd x31, 248(sp)
+
+    sd zero, 0(sp)
+    addi t0, sp, {frame_size}
+    sd t0, 16(sp)
+    csrr t0, sepc
+    sd t0, 256(sp)
+    csrr t0, sstatus
+    sd t0, 264(sp)
+    csrr t0, scause
+    sd t0, 272(sp)
+    csrr t0, stval
+    sd t0, 280(sp)
+
+    mv a0, sp
+    call {dispatch}
+
+    ld t0, 256(sp)
+    csrw sepc, t0
+    ld t0, 264(sp)
+    csrw sstatus, t0
+    ld x1, 8(sp)
+    ld x3, 24(sp)
+    ld x4, 32(sp)
+    ld x5, 40(sp)
+    ld x6, 48(sp)
+    ld x7, 56(sp)
+    ld x8, 64(sp)
+    ld x9, 72(sp)
+    ld x10, 80(sp)
+    ld x11, 88(sp)
+    ld x12, 96(sp)
+    ld x13, 104(sp)
+    ld x14, 112(sp)
+    ld x15, 120(sp)
+    ld x16, 128(sp)
+    ld x17, 136(sp)
+    ld x18, 144(sp)
+    ld x19, 152(sp)
+    ld x20, 160(sp)
+    ld x21, 168(sp)
+    ld x22, 176(sp)
+    ld x23, 184(sp)
+    ld x24, 192(sp)
+    ld x25, 200(sp)
+    ld x26, 208(sp)
+    ld x27, 216(sp)
+    ld x28, 224(sp)
+    ld x29, 232(sp)
+    ld x30, 240(sp)
+    ld x31, 248(sp)
+    addi sp, sp, {frame_size}
+    sret
+
+.popsection
+"#,
+    frame_size = const TRAP_FRAME_SIZE,
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    static __trap_vector: u8;
+}
+
+extern "C" fn dispatch(frame: &mut TrapFrame) {
+    match frame.scause.trap() {
+        Trap::Interrupt(interrupt) => {
+            let handler = match interrupt {
+                Interrupt::SupervisorSoftware => &SOFTWARE_HANDLER,
+                Interrupt::SupervisorTimer => &TIMER_HANDLER,
+                Interrupt::SupervisorExternal => &EXTERNAL_HANDLER,
+                _ => return default_interrupt_handler(frame),
+            };
+
+            match handler.load(Ordering::Acquire) {
+                0 => default_interrupt_handler(frame),
+                handler => {
+                    let handler: InterruptHandler = unsafe { core::mem::transmute(handler) };
+                    handler(frame)
+                }
+            }
+        }
+        Trap::Exception(exception) => match EXCEPTION_HANDLER.load(Ordering::Acquire) {
+            0 => default_exception_handler(frame, exception),
+            handler => {
+                let handler: ExceptionHandler = unsafe { core::mem::transmute(handler) };
+                handler(frame, exception)
+            }
+        },
+    }
+}
+
+/// Points `stvec` at the trap vector. Exceptions go to the default handler until
+/// [`set_exception_handler`] is called, and interrupts stay disabled until they are enabled with
+/// [`enable_interrupt`] and [`enable_interrupts`].
+///
+/// # Safety
+///
+/// Must run in S-mode with a stack that has room for a [`TrapFrame`] and the handlers.
+pub unsafe fn install() {
+    Stvec::direct(trap_vector_address()).write();
+}
+
+pub fn trap_vector_address() -> u64 {
+    unsafe { &__trap_vector as *const u8 as u64 }
+}
+
+pub fn set_exception_handler(handler: ExceptionHandler) {
+    EXCEPTION_HANDLER.store(handler as usize, Ordering::Release);
+}
+
+/// Sets the handler of a software, timer or external interrupt. The handler must clear the
+/// source of the interrupt, or it is taken again as soon as it returns.
+pub fn set_interrupt_handler(
+    interrupt: Interrupt,
+    handler: InterruptHandler,
+) -> Result<(), &'static str> {
+    let slot = match interrupt {
+        Interrupt::SupervisorSoftware => &SOFTWARE_HANDLER,
+        Interrupt::SupervisorTimer => &TIMER_HANDLER,
+        Interrupt::SupervisorExternal => &EXTERNAL_HANDLER,
+        _ => return Err("Interrupt can not have a handler"),
+    };
+
+    slot.store(handler as usize, Ordering::Release);
+
+    Ok(())
+}
+
+/// Enables a single interrupt in `sie`.
+///
+/// # Safety
+///
+/// The interrupt is taken once [`enable_interrupts`] is called, so its handler must be ready.
+pub unsafe fn enable_interrupt(interrupt: Interrupt) {
+    if let Some(bit) = interrupt.sie_bit() {
+        Sie::set(bit);
+    }
+}
+
+pub fn disable_interrupt(interrupt: Interrupt) {
+    if let Some(bit) = interrupt.sie_bit() {
+        Sie::clear(bit);
+    }
+}
+
+/// Sets `sstatus.SIE`, which lets every interrupt that is enabled in `sie` be taken.
+///
+/// # Safety
+///
+/// The trap vector must be installed, and the handlers of every enabled interrupt must be ready.
+pub unsafe fn enable_interrupts() {
+    Sstatus::set(Sstatus::SIE);
+}
+
+pub fn disable_interrupts() {
+    unsafe { Sstatus::clear(Sstatus::SIE) };
+}
+
+/// Panics with a description of the exception, since nothing else can handle it yet.
+pub fn default_exception_handler(frame: &mut TrapFrame, _exception: Exception) {
+    panic!("Unhandled exception\n{}", TrapReport { frame });
+}
+
+fn default_interrupt_handler(frame: &mut TrapFrame) {
+    panic!("Unhandled interrupt\n{}", TrapReport { frame });
+}
+
+/// Formats a trap, its cause and the trap value when there is one.
+pub struct TrapReport<'a> {
+    pub frame: &'a TrapFrame,
+}
+
+impl fmt::Display for TrapReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frame = self.frame;
+        let trap = frame.scause.trap();
+
+        writeln!(f, "{} at {:#x}", trap, frame.sepc)?;
+        if let Trap::Exception(exception) = trap {
+            if exception.stval_is_address() {
+                writeln!(f, "Fault address: {:#x}", frame.stval)?;
+            } else if exception == Exception::IllegalInstruction && frame.stval != 0 {
+                writeln!(f, "Instruction: {:#x}", frame.stval)?;
+            }
+        }
+        writeln!(
+            f,
+            "scause: {:#x}, sstatus: {:#x}",
+            frame.scause.0, frame.sstatus
+        )?;
+
+        for (i, pair) in frame.x.chunks(2).enumerate() {
+            if let [a, b] = pair {
+                writeln!(
+                    f,
+                    "x{:<2} {:#018x}  x{:<2} {:#018x}",
+                    i * 2,
+                    a,
+                    i * 2 + 1,
+                    b
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Scause {
+    const INTERRUPT: u64 = 1 << 63;
+
+    pub fn is_interrupt(&self) -> bool {
+        (self.0 & Self::INTERRUPT) != 0
+    }
+
+    pub fn code(&self) -> u64 {
+        self.0 & !Self::INTERRUPT
+    }
+
+    pub fn trap(&self) -> Trap {
+        if self.is_interrupt() {
+            Trap::Interrupt(Interrupt::from_code(self.code()))
+        } else {
+            Trap::Exception(Exception::from_code(self.code()))
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interrupt(interrupt) => write!(f, "{}", interrupt),
+            Self::Exception(exception) => write!(f, "{}", exception),
+        }
+    }
+}
+
+impl Interrupt {
+    pub fn from_code(code: u64) -> Self {
+        match code {
+            1 => Self::SupervisorSoftware,
+            5 => Self::SupervisorTimer,
+            9 => Self::SupervisorExternal,
+            13 => Self::CounterOverflow,
+            code => Self::Other(code),
+        }
+    }
+
+    fn sie_bit(&self) -> Option<u64> {
+        match self {
+            Self::SupervisorSoftware => Some(Sie::SOFTWARE),
+            Self::SupervisorTimer => Some(Sie::TIMER),
+            Self::SupervisorExternal => Some(Sie::EXTERNAL),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SupervisorSoftware => write!(f, "Supervisor software interrupt"),
+            Self::SupervisorTimer => write!(f, "Supervisor timer interrupt"),
+            Self::SupervisorExternal => write!(f, "Supervisor external interrupt"),
+            Self::CounterOverflow => write!(f, "Counter overflow interrupt"),
+            Self::Other(code) => write!(f, "Interrupt {}", code),
+        }
+    }
+}
+
+impl Exception {
+    pub fn from_code(code: u64) -> Self {
+        match code {
+            0 => Self::InstructionMisaligned,
+            1 => Self::InstructionAccessFault,
+            2 => Self::IllegalInstruction,
+            3 => Self::Breakpoint,
+            4 => Self::LoadMisaligned,
+            5 => Self::LoadAccessFault,
+            6 => Self::StoreMisaligned,
+            7 => Self::StoreAccessFault,
+            8 => Self::UserEcall,
+            9 => Self::SupervisorEcall,
+            12 => Self::InstructionPageFault,
+            13 => Self::LoadPageFault,
+            15 => Self::StorePageFault,
+            18 => Self::SoftwareCheck,
+            19 => Self::HardwareError,
+            code => Self::Other(code),
+        }
+    }
+
+    /// Returns true if `stval` holds the faulting virtual address.
+    pub fn stval_is_address(&self) -> bool {
+        matches!(
+            self,
+            Self::InstructionMisaligned
+                | Self::InstructionAccessFault
+                | Self::LoadMisaligned
+                | Self::LoadAccessFault
+                | Self::StoreMisaligned
+                | Self::StoreAccessFault
+                | Self::InstructionPageFault
+                | Self::LoadPageFault
+                | Self::StorePageFault
+        )
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InstructionMisaligned => write!(f, "Instruction address misaligned"),
+            Self::InstructionAccessFault => write!(f, "Instruction access fault"),
+            Self::IllegalInstruction => write!(f, "Illegal instruction"),
+            Self::Breakpoint => write!(f, "Breakpoint"),
+            Self::LoadMisaligned => write!(f, "Load address misaligned"),
+            Self::LoadAccessFault => write!(f, "Load access fault"),
+            Self::StoreMisaligned => write!(f, "Store address misaligned"),
+            Self::StoreAccessFault => write!(f, "Store access fault"),
+            Self::UserEcall => write!(f, "Environment call from U-mode"),
+            Self::SupervisorEcall => write!(f, "Environment call from S-mode"),
+            Self::InstructionPageFault => write!(f, "Instruction page fault"),
+            Self::LoadPageFault => write!(f, "Load page fault"),
+            Self::StorePageFault => write!(f, "Store page fault"),
+            Self::SoftwareCheck => write!(f, "Software check"),
+            Self::HardwareError => write!(f, "Hardware error"),
+            Self::Other(code) => write!(f, "Exception {}", code),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// Boundaries of the bootloader image and its stacks, using the symbols that the aarch64 and
+// riscv64 linker scripts define. A stack guard is a region below a stack that is filled with a
+// canary, so that overflowing the stack changes the canary before it corrupts what comes below,
+// such as `.bss` or the stack of another CPU.
+
+use core::{ops::Range, ptr};
+
+pub type Error = &'static str;
+
+/// The value that fills the stack guard region.
+pub const STACK_CANARY: u64 = 0x57ac_c0de_57ac_c0de;
+
+extern "C" {
+    static _PROGRAM_START: u8;
+    static _PROGRAM_END: u8;
+    static _DATA_START: u8;
+    static _DATA_END: u8;
+    static _BSS_START: u8;
+    static _BSS_END: u8;
+    static _STACK_GUARD_START: u8;
+    static _STACK_GUARD_END: u8;
+    static _STACK_END: u8;
+    static _STACK_START: u8;
+    static _CPU_STACKS_START: u8;
+    static _CPU_STACKS_END: u8;
+    // An absolute symbol, so its address is the count
+    static _CPU_STACK_COUNT: u8;
+}
+
+// Absolute symbols near 0 can be out of reach of PC-relative addressing, such as with the riscv64
+// medany code model, so the address is stored with an absolute relocation and read from here
+static CPU_STACK_COUNT: &u8 = unsafe { &_CPU_STACK_COUNT };
+
+fn range(start: &u8, end: &u8) -> Range<u64> {
+    (start as *const u8 as u64)..(end as *const u8 as u64)
+}
+
+/// Returns the addresses of the code and data of the bootloader, not including its stack.
+pub fn program_range() -> Range<u64> {
+    unsafe { range(&_PROGRAM_START, &_PROGRAM_END) }
+}
+
+pub fn data_range() -> Range<u64> {
+    unsafe { range(&_DATA_START, &_DATA_END) }
+}
+
+pub fn bss_range() -> Range<u64> {
+    unsafe { range(&_BSS_START, &_BSS_END) }
+}
+
+/// Returns the addresses of the stack. It grows down from the end of the range.
+pub fn stack_range() -> Range<u64> {
+    unsafe { range(&_STACK_END, &_STACK_START) }
+}
+
+/// Returns the number of secondary CPUs that the linker script reserves stacks for. The boot CPU
+/// uses [`stack_range`] instead.
+pub fn cpu_stack_count() -> usize {
+    let count = unsafe { ptr::read_volatile(&CPU_STACK_COUNT) };
+    count as *const u8 as usize
+}
+
+/// Returns the stack of secondary CPU `cpu` together with its guard region below it.
+fn cpu_stack_slot(cpu: usize) -> Option<Range<u64>> {
+    let count = cpu_stack_count();
+    if cpu >= count {
+        return None;
+    }
+
+    let stacks = unsafe { range(&_CPU_STACKS_START, &_CPU_STACKS_END) };
+    let size = (stacks.end - stacks.start) / count as u64;
+    let start = stacks.start + cpu as u64 * size;
+
+    Some(start..start + size)
+}
+
+/// Returns the stack of a secondary CPU, or `None` if `cpu` is not below [`cpu_stack_count`].
+pub fn cpu_stack_range(cpu: usize) -> Option<Range<u64>> {
+    let slot = cpu_stack_slot(cpu)?;
+    Some(slot.start + stack_guard_size()..slot.end)
+}
+
+/// Returns the guard region below the stack of a secondary CPU, which is as large as the one of
+/// the boot stack.
+pub fn cpu_stack_guard_range(cpu: usize) -> Option<Range<u64>> {
+    let slot = cpu_stack_slot(cpu)?;
+    Some(slot.start..slot.start + stack_guard_size())
+}
+
+pub fn stack_guard_range() -> Range<u64> {
+    unsafe { range(&_STACK_GUARD_START, &_STACK_GUARD_END) }
+}
+
+fn stack_guard_size() -> u64 {
+    let guard = stack_guard_range();
+    guard.end - guard.start
+}
+
+unsafe fn fill_stack_guard(guard: Range<u64>) {
+    for address in guard.step_by(8) {
+        ptr::write_volatile(address as *mut u64, STACK_CANARY);
+    }
+}
+
+fn is_stack_guard_intact(guard: Range<u64>) -> bool {
+    guard
+        .step_by(8)
+        .all(|address| unsafe { ptr::read_volatile(address as *const u64) } == STACK_CANARY)
+}
+
+/// Fills the stack guard region with [`STACK_CANARY`].
+///
+/// # Safety
+///
+/// Must be called before anything could use the stack guard region, which is before the stack
+/// could have overflowed.
+pub unsafe fn init_stack_guard() {
+    fill_stack_guard(stack_guard_range());
+}
+
+/// Returns an error if the stack overflowed into the stack guard region since
+/// [`init_stack_guard`] was called.
+pub fn check_stack_guard() -> Result<(), Error> {
+    if is_stack_guard_intact(stack_guard_range()) {
+        Ok(())
+    } else {
+        Err("Stack overflowed into the stack guard")
+    }
+}
+
+/// Fills the guard region of the stack of secondary CPU `cpu` with [`STACK_CANARY`]. Does nothing
+/// if `cpu` is not below [`cpu_stack_count`].
+///
+/// # Safety
+///
+/// Nothing may be using the stack of `cpu`, such as before the CPU is started.
+pub unsafe fn init_cpu_stack_guard(cpu: usize) {
+    if let Some(guard) = cpu_stack_guard_range(cpu) {
+        fill_stack_guard(guard);
+    }
+}
+
+/// Returns an error if the stack of secondary CPU `cpu` overflowed into its guard region since
+/// [`init_cpu_stack_guard`] was called.
+pub fn check_cpu_stack_guard(cpu: usize) -> Result<(), Error> {
+    let guard = cpu_stack_guard_range(cpu).ok_or("CPU has no stack")?;
+
+    if is_stack_guard_intact(guard) {
+        Ok(())
+    } else {
+        Err("CPU stack overflowed into its stack guard")
+    }
+}
@@ -1,3 +1,7 @@
+pub mod pl011;
+pub mod probe;
+pub mod uart16550;
+
 pub type Error = &'static str;
 
 pub trait Serial {
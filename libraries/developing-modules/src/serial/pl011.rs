@@ -0,0 +1,134 @@
+use core::{fmt, ptr};
+
+use crate::serial::{Error, Serial};
+
+// Register offsets
+const DR: u64 = 0x00;
+const FR: u64 = 0x18;
+const IBRD: u64 = 0x24;
+const FBRD: u64 = 0x28;
+const LCR_H: u64 = 0x2c;
+const CR: u64 = 0x30;
+const IMSC: u64 = 0x38;
+const ICR: u64 = 0x44;
+
+const FR_BUSY: u32 = 1 << 3;
+const FR_RX_EMPTY: u32 = 1 << 4;
+const FR_TX_FULL: u32 = 1 << 5;
+
+const LCR_H_FIFO_ENABLE: u32 = 1 << 4;
+const LCR_H_WORD_LENGTH_8: u32 = 0b11 << 5;
+
+const CR_UART_ENABLE: u32 = 1 << 0;
+const CR_TX_ENABLE: u32 = 1 << 8;
+const CR_RX_ENABLE: u32 = 1 << 9;
+
+/// An ARM PrimeCell PL011 UART, such as the one on the QEMU aarch64 virt machine.
+#[derive(Copy, Clone, Debug)]
+pub struct Pl011 {
+    base: u64,
+    clock_frequency: Option<u32>,
+    baud: u32,
+    is_initialized: bool,
+}
+
+impl Pl011 {
+    /// Creates a driver for the UART at `base`. The baud rate is only programmed if the reference
+    /// clock frequency is known; otherwise the firmware settings are kept.
+    pub fn new(base: u64, clock_frequency: Option<u32>, baud: u32) -> Self {
+        Self {
+            base,
+            clock_frequency,
+            baud,
+            is_initialized: false,
+        }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    fn read(&self, offset: u64) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&mut self, offset: u64, value: u32) {
+        unsafe { ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+}
+
+impl Serial for Pl011 {
+    fn init(&mut self) -> Result<(), Error> {
+        if self.is_initialized {
+            return Ok(());
+        }
+
+        // The UART must be disabled and idle while it is reconfigured
+        self.write(CR, 0);
+        while (self.read(FR) & FR_BUSY) != 0 {
+            core::hint::spin_loop();
+        }
+
+        if let Some(clock_frequency) = self.clock_frequency {
+            if self.baud == 0 {
+                return Err("Serial baud rate is 0");
+            }
+
+            // The divisor is clock / (16 * baud) as a fixed point number with 6 fractional bits
+            let divisor = (clock_frequency as u64 * 4 + self.baud as u64 / 2) / self.baud as u64;
+            let integer = divisor >> 6;
+            if integer == 0 || integer > u16::MAX as u64 {
+                return Err("Serial baud rate can not be reached with this clock");
+            }
+
+            self.write(IBRD, integer as u32);
+            self.write(FBRD, (divisor & 0x3f) as u32);
+        }
+
+        // 8 data bits, 1 stop bit, no parity and FIFOs enabled. This also latches the divisors.
+        self.write(LCR_H, LCR_H_WORD_LENGTH_8 | LCR_H_FIFO_ENABLE);
+        // Mask and clear all interrupts
+        self.write(IMSC, 0);
+        self.write(ICR, 0x7ff);
+        self.write(CR, CR_UART_ENABLE | CR_TX_ENABLE | CR_RX_ENABLE);
+
+        self.is_initialized = true;
+
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        if !self.is_initialized {
+            return Err("Tried to read a byte using uninitialized serial port");
+        }
+
+        while (self.read(FR) & FR_RX_EMPTY) != 0 {
+            core::hint::spin_loop();
+        }
+
+        Ok(self.read(DR) as u8)
+    }
+
+    fn write_byte(&mut self, value: u8) -> Result<(), Error> {
+        if !self.is_initialized {
+            return Err("Tried to send a byte using uninitialized serial port");
+        }
+
+        while (self.read(FR) & FR_TX_FULL) != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DR, value as u32);
+
+        Ok(())
+    }
+}
+
+impl fmt::Write for Pl011 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.serial_write_str(s).map_err(|_| fmt::Error)
+    }
+}
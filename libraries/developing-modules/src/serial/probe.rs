@@ -0,0 +1,224 @@
+// Picks the console UART from what the firmware describes, so that the same binary works on
+// boards other than the QEMU virt machines. The device tree is tried first, then the ACPI SPCR,
+// and then a list of well known addresses for the current architecture.
+
+use core::fmt;
+
+#[cfg(target_arch = "x86_64")]
+use crate::x86_64::uart::{
+    UartX86, UartX86Baud, UartX86DataBits, UartX86Parity, UartX86Port, UartX86StopBits,
+};
+use crate::{
+    acpi::{spcr::Spcr, tables::GenericAddress},
+    fdt::{Fdt, FdtNode},
+    serial::{pl011::Pl011, uart16550::Uart16550, Error, Serial},
+};
+
+pub const DEFAULT_BAUD: u32 = 115200;
+
+const PL011_COMPATIBLES: &[&str] = &["arm,pl011", "arm,sbsa-uart"];
+const UART_16550_COMPATIBLES: &[&str] = &["ns16550a", "ns16550", "ns16450", "snps,dw-apb-uart"];
+
+pub enum ConsoleUart {
+    #[cfg(target_arch = "x86_64")]
+    PortIo(UartX86),
+    Uart16550(Uart16550),
+    Pl011(Pl011),
+}
+
+impl ConsoleUart {
+    fn as_serial(&mut self) -> &mut dyn Serial {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::PortIo(uart) => uart,
+            Self::Uart16550(uart) => uart,
+            Self::Pl011(uart) => uart,
+        }
+    }
+
+    fn as_serial_ref(&self) -> &dyn Serial {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::PortIo(uart) => uart,
+            Self::Uart16550(uart) => uart,
+            Self::Pl011(uart) => uart,
+        }
+    }
+
+    /// Returns the UART that `/chosen/stdout-path` points to, or the first enabled UART that has
+    /// a supported `compatible`. Addresses are not translated through `ranges`.
+    pub fn from_device_tree(fdt: &Fdt) -> Option<Self> {
+        if let Some(uart) = fdt
+            .stdout_node()
+            .filter(|node| node.is_enabled())
+            .and_then(|node| Self::from_device_tree_node(&node))
+        {
+            return Some(uart);
+        }
+
+        PL011_COMPATIBLES
+            .iter()
+            .chain(UART_16550_COMPATIBLES)
+            .filter_map(|compatible| fdt.find_compatible(compatible))
+            .filter(|node| node.is_enabled())
+            .find_map(|node| Self::from_device_tree_node(&node))
+    }
+
+    /// Creates a driver for a UART node, if its `compatible` is supported.
+    pub fn from_device_tree_node(node: &FdtNode) -> Option<Self> {
+        let address = node.first_reg()?.address;
+        let u32_property = |name: &str| node.property(name).and_then(|property| property.as_u32());
+
+        let clock_frequency = u32_property("clock-frequency");
+        let baud = u32_property("current-speed").unwrap_or(DEFAULT_BAUD);
+
+        if PL011_COMPATIBLES
+            .iter()
+            .any(|compatible| node.is_compatible(compatible))
+        {
+            return Some(Self::Pl011(Pl011::new(address, clock_frequency, baud)));
+        }
+
+        if UART_16550_COMPATIBLES
+            .iter()
+            .any(|compatible| node.is_compatible(compatible))
+        {
+            let register_shift = u32_property("reg-shift").unwrap_or(0);
+            let uart = Uart16550::new(address, register_shift, clock_frequency, baud);
+
+            return Some(Self::Uart16550(match u32_property("reg-io-width") {
+                Some(4) => uart.with_word_access(),
+                _ => uart,
+            }));
+        }
+
+        None
+    }
+
+    /// Creates a driver for the UART described by the SPCR, if its interface is supported.
+    pub fn from_spcr(spcr: &Spcr) -> Option<Self> {
+        let address = spcr.base_address.address;
+        let baud = spcr.baud.unwrap_or(DEFAULT_BAUD);
+
+        match spcr.base_address.address_space {
+            #[cfg(target_arch = "x86_64")]
+            GenericAddress::SYSTEM_IO if spcr.interface_type.is_16550() => {
+                let port = UartX86Port::from_address(u16::try_from(address).ok()?)?;
+                let baud = match baud {
+                    57600 => UartX86Baud::Baud57600,
+                    38400 => UartX86Baud::Baud38400,
+                    _ => UartX86Baud::Baud115200,
+                };
+
+                Some(Self::PortIo(UartX86::new(
+                    port,
+                    baud,
+                    UartX86DataBits::Bits8,
+                    UartX86StopBits::Bits1,
+                    UartX86Parity::None,
+                )))
+            }
+            GenericAddress::SYSTEM_MEMORY if spcr.interface_type.is_16550() => {
+                let uart =
+                    Uart16550::new(address, spcr.register_shift(), spcr.clock_frequency, baud);
+
+                Some(Self::Uart16550(if spcr.register_shift() >= 2 {
+                    uart.with_word_access()
+                } else {
+                    uart
+                }))
+            }
+            GenericAddress::SYSTEM_MEMORY if spcr.interface_type.is_pl011() => {
+                Some(Self::Pl011(Pl011::new(address, spcr.clock_frequency, baud)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the UARTs to try when the firmware does not describe one.
+    pub fn fallbacks() -> impl Iterator<Item = Self> {
+        #[cfg(target_arch = "x86_64")]
+        let fallbacks = UartX86Port::ALL.map(|port| {
+            Self::PortIo(UartX86::new(
+                port,
+                UartX86Baud::Baud115200,
+                UartX86DataBits::Bits8,
+                UartX86StopBits::Bits1,
+                UartX86Parity::None,
+            ))
+        });
+
+        // The PL011 of the QEMU virt machine
+        #[cfg(target_arch = "aarch64")]
+        let fallbacks = [Self::Pl011(Pl011::new(0x0900_0000, None, DEFAULT_BAUD))];
+
+        // The 16550 of the QEMU virt machine
+        #[cfg(target_arch = "riscv64")]
+        let fallbacks = [Self::Uart16550(Uart16550::new(
+            0x1000_0000,
+            0,
+            None,
+            DEFAULT_BAUD,
+        ))];
+
+        #[cfg(not(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "riscv64"
+        )))]
+        let fallbacks: [Self; 0] = [];
+
+        fallbacks.into_iter()
+    }
+
+    /// Returns the base MMIO address or I/O port of the UART.
+    pub fn base(&self) -> u64 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::PortIo(uart) => uart.port() as u64,
+            Self::Uart16550(uart) => uart.base(),
+            Self::Pl011(uart) => uart.base(),
+        }
+    }
+}
+
+impl Serial for ConsoleUart {
+    fn init(&mut self) -> Result<(), Error> {
+        self.as_serial().init()
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.as_serial_ref().is_initialized()
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        self.as_serial().read_byte()
+    }
+
+    fn write_byte(&mut self, value: u8) -> Result<(), Error> {
+        self.as_serial().write_byte(value)
+    }
+}
+
+impl fmt::Write for ConsoleUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.serial_write_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+/// Finds and initializes the console UART, trying the device tree, then the SPCR, then the
+/// architecture's fallbacks.
+pub fn probe(fdt: Option<&Fdt>, spcr: Option<&Spcr>) -> Result<ConsoleUart, Error> {
+    let from_firmware = fdt
+        .and_then(ConsoleUart::from_device_tree)
+        .into_iter()
+        .chain(spcr.and_then(ConsoleUart::from_spcr));
+
+    for mut uart in from_firmware.chain(ConsoleUart::fallbacks()) {
+        if uart.init().is_ok() {
+            return Ok(uart);
+        }
+    }
+
+    Err("No serial console found")
+}
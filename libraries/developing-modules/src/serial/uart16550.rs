@@ -0,0 +1,182 @@
+use core::{fmt, ptr};
+
+use crate::serial::{Error, Serial};
+
+// Register indices, which are multiplied by the register stride
+const RBR_THR_DLL: usize = 0;
+const IER_DLM: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const MCR: usize = 4;
+const LSR: usize = 5;
+
+const IER_RECEIVED_DATA: u8 = 1 << 0;
+const LCR_DLAB: u8 = 1 << 7;
+const LCR_8N1: u8 = 0x03;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+/// A memory mapped 16550 compatible UART, such as the one on the QEMU riscv64 virt machine.
+#[derive(Copy, Clone, Debug)]
+pub struct Uart16550 {
+    base: u64,
+    /// Registers are `1 << register_shift` bytes apart.
+    register_shift: u32,
+    /// Registers are accessed as 32 bit words instead of bytes.
+    word_access: bool,
+    clock_frequency: Option<u32>,
+    baud: u32,
+    is_initialized: bool,
+}
+
+impl Uart16550 {
+    /// Creates a driver for the UART at `base`. The baud rate is only programmed if the input
+    /// clock frequency is known; otherwise the firmware settings are kept.
+    pub fn new(base: u64, register_shift: u32, clock_frequency: Option<u32>, baud: u32) -> Self {
+        Self {
+            base,
+            register_shift,
+            word_access: false,
+            clock_frequency,
+            baud,
+            is_initialized: false,
+        }
+    }
+
+    /// Makes every register access 32 bits wide, which some SoCs such as the DesignWare APB UART
+    /// require.
+    pub fn with_word_access(mut self) -> Self {
+        self.word_access = true;
+        self
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Raises the UART's interrupt while a received byte is waiting, until
+    /// [`Self::disable_receive_interrupt`] or `init` is called. The interrupt handler has to read
+    /// every waiting byte, such as with [`Self::try_read_byte`], for the interrupt to clear.
+    pub fn enable_receive_interrupt(&mut self) -> Result<(), Error> {
+        if !self.is_initialized {
+            return Err("Tried to enable interrupts of an uninitialized serial port");
+        }
+
+        self.write(IER_DLM, IER_RECEIVED_DATA);
+        Ok(())
+    }
+
+    pub fn disable_receive_interrupt(&mut self) {
+        self.write(IER_DLM, 0x00);
+    }
+
+    /// Returns a received byte if one is waiting, without blocking like `read_byte`.
+    pub fn try_read_byte(&mut self) -> Result<Option<u8>, Error> {
+        if !self.is_initialized {
+            return Err("Tried to read a byte using uninitialized serial port");
+        }
+
+        if (self.read(LSR) & LSR_DATA_READY) == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(self.read(RBR_THR_DLL)))
+    }
+
+    fn register(&self, index: usize) -> u64 {
+        self.base + ((index as u64) << self.register_shift)
+    }
+
+    fn read(&self, index: usize) -> u8 {
+        unsafe {
+            if self.word_access {
+                ptr::read_volatile(self.register(index) as *const u32) as u8
+            } else {
+                ptr::read_volatile(self.register(index) as *const u8)
+            }
+        }
+    }
+
+    fn write(&mut self, index: usize, value: u8) {
+        unsafe {
+            if self.word_access {
+                ptr::write_volatile(self.register(index) as *mut u32, value as u32);
+            } else {
+                ptr::write_volatile(self.register(index) as *mut u8, value);
+            }
+        }
+    }
+}
+
+impl Serial for Uart16550 {
+    fn init(&mut self) -> Result<(), Error> {
+        if self.is_initialized {
+            return Ok(());
+        }
+
+        // Disable interrupts
+        self.write(IER_DLM, 0x00);
+
+        if let Some(clock_frequency) = self.clock_frequency {
+            if self.baud == 0 {
+                return Err("Serial baud rate is 0");
+            }
+
+            let divisor = clock_frequency / (16 * self.baud);
+            if divisor == 0 || divisor > u16::MAX as u32 {
+                return Err("Serial baud rate can not be reached with this clock");
+            }
+
+            let [divisor_lo, divisor_hi] = (divisor as u16).to_le_bytes();
+            self.write(LCR, LCR_DLAB);
+            self.write(RBR_THR_DLL, divisor_lo);
+            self.write(IER_DLM, divisor_hi);
+        }
+
+        // 8 data bits, 1 stop bit and no parity
+        self.write(LCR, LCR_8N1);
+        // Enable and clear FIFOs
+        self.write(FCR, 0x07);
+        // Enable Data Terminal Ready (DTR) and Request To Send (RTS)
+        self.write(MCR, 0x03);
+
+        self.is_initialized = true;
+
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        if !self.is_initialized {
+            return Err("Tried to read a byte using uninitialized serial port");
+        }
+
+        while (self.read(LSR) & LSR_DATA_READY) == 0 {
+            core::hint::spin_loop();
+        }
+
+        Ok(self.read(RBR_THR_DLL))
+    }
+
+    fn write_byte(&mut self, value: u8) -> Result<(), Error> {
+        if !self.is_initialized {
+            return Err("Tried to send a byte using uninitialized serial port");
+        }
+
+        while (self.read(LSR) & LSR_THR_EMPTY) == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(RBR_THR_DLL, value);
+
+        Ok(())
+    }
+}
+
+impl fmt::Write for Uart16550 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.serial_write_str(s).map_err(|_| fmt::Error)
+    }
+}
@@ -0,0 +1,60 @@
+// The start state of secondary CPUs, shared by the aarch64 and riscv64 `smp` modules. Each of
+// those only has the trampoline that reads a `StartInfo` and the firmware call that starts a CPU.
+
+use core::{
+    mem,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{memory::align_down, sections};
+
+// The trampolines read these fields by offset. Each CPU's copy is kept at the top of its own
+// stack, so that there is one for every stack that the linker script reserves.
+#[repr(C)]
+pub(crate) struct StartInfo {
+    stack_top: AtomicU64,
+    entry: AtomicU64,
+    arg: AtomicU64,
+}
+
+impl StartInfo {
+    const fn new() -> Self {
+        Self {
+            stack_top: AtomicU64::new(0),
+            entry: AtomicU64::new(0),
+            arg: AtomicU64::new(0),
+        }
+    }
+
+    /// Places a new `StartInfo` at the top of `stack`, and points the stack below it.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may use the stack.
+    unsafe fn at_top_of(stack: Range<u64>) -> &'static Self {
+        let address = align_down(stack.end - mem::size_of::<Self>() as u64, 16);
+        let info = address as *mut Self;
+        info.write(Self::new());
+        (*info).stack_top.store(address, Ordering::Relaxed);
+
+        &*info
+    }
+
+    /// Sets up the stack that [`sections::cpu_stack_range`] gives for `cpu`, and its guard, so
+    /// that the trampoline calls `entry` with `arg` on it. Returns the address to pass to the
+    /// trampoline, or `None` if there is no stack for `cpu`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may use the stack of `cpu`.
+    pub(crate) unsafe fn prepare(cpu: usize, entry: u64, arg: u64) -> Option<u64> {
+        let info = Self::at_top_of(sections::cpu_stack_range(cpu)?);
+        sections::init_cpu_stack_guard(cpu);
+
+        info.entry.store(entry, Ordering::Relaxed);
+        info.arg.store(arg, Ordering::Release);
+
+        Some(info as *const Self as u64)
+    }
+}
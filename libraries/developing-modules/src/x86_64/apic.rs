@@ -0,0 +1,235 @@
+// The local APIC of the current CPU. It is used in x2APIC mode when the CPU supports it, where its
+// registers are MSRs, and otherwise in xAPIC mode, where they are memory mapped at the address in
+// the APIC base MSR. Both modes use the same register offsets, so callers do not need to know
+// which one was chosen.
+
+use core::ptr;
+
+use crate::{
+    memory::PhysicalMapper,
+    x86_64::{
+        cpuid::Cpuid,
+        msr::{rdmsr, wrmsr, Msr},
+    },
+};
+
+pub type Error = &'static str;
+
+pub const ID: u32 = 0x20;
+pub const VERSION: u32 = 0x30;
+pub const TASK_PRIORITY: u32 = 0x80;
+pub const EOI: u32 = 0xb0;
+pub const SPURIOUS_VECTOR: u32 = 0xf0;
+pub const ERROR_STATUS: u32 = 0x280;
+pub const LVT_TIMER: u32 = 0x320;
+pub const LVT_LINT0: u32 = 0x350;
+pub const LVT_LINT1: u32 = 0x360;
+pub const LVT_ERROR: u32 = 0x370;
+pub const TIMER_INITIAL_COUNT: u32 = 0x380;
+pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+pub const TIMER_DIVIDE: u32 = 0x3e0;
+
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// The time that the local APIC timer is measured against in [`LocalApic::calibrate_timer`].
+const CALIBRATION_US: u32 = 10_000;
+
+/// A timer with a known frequency, used to calibrate the local APIC timer.
+pub trait ReferenceTimer {
+    fn wait_us(&self, microseconds: u32);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ApicMode {
+    XApic,
+    X2Apic,
+}
+
+/// The value that the local APIC timer's input clock is divided by.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerDivide {
+    By1 = 0b1011,
+    By2 = 0b0000,
+    By4 = 0b0001,
+    By8 = 0b0010,
+    By16 = 0b0011,
+    By32 = 0b1000,
+    By64 = 0b1001,
+    By128 = 0b1010,
+}
+
+#[derive(Debug)]
+pub enum LocalApic {
+    XApic { base: *mut u32 },
+    X2Apic,
+}
+
+impl LocalApic {
+    /// Enables the local APIC of the current CPU, in x2APIC mode if it is supported, and sets its
+    /// spurious interrupt vector. The timer and error interrupts start masked.
+    ///
+    /// # Safety
+    ///
+    /// Must be run at CPL 0 with interrupts disabled. The 8259 PICs should be masked first, see
+    /// [`crate::x86_64::pic::ChainedPics::disable`].
+    pub unsafe fn enable(mapper: &impl PhysicalMapper, spurious_vector: u8) -> Result<Self, Error> {
+        let features = Cpuid::new().features();
+        if !features.apic {
+            return Err("CPU does not have a local APIC");
+        }
+
+        let apic_base = Msr::ApicBase.read();
+        let apic = if features.x2apic {
+            // xAPIC mode must be enabled before x2APIC mode
+            Msr::ApicBase.write(apic_base | APIC_BASE_GLOBAL_ENABLE);
+            Msr::ApicBase.write(apic_base | APIC_BASE_GLOBAL_ENABLE | APIC_BASE_X2APIC_ENABLE);
+            Self::X2Apic
+        } else {
+            Msr::ApicBase.write(apic_base | APIC_BASE_GLOBAL_ENABLE);
+            let base = mapper.map_physical(apic_base & APIC_BASE_ADDRESS_MASK, 0x1000);
+            Self::XApic {
+                base: base as *mut u32,
+            }
+        };
+
+        apic.write(LVT_TIMER, LVT_MASKED);
+        apic.write(LVT_ERROR, LVT_MASKED);
+        apic.set_task_priority(0);
+        apic.write(
+            SPURIOUS_VECTOR,
+            SPURIOUS_APIC_ENABLE | spurious_vector as u32,
+        );
+
+        Ok(apic)
+    }
+
+    pub fn mode(&self) -> ApicMode {
+        match self {
+            Self::XApic { .. } => ApicMode::XApic,
+            Self::X2Apic => ApicMode::X2Apic,
+        }
+    }
+
+    /// Reads the register at `offset`, which is its xAPIC MMIO offset.
+    pub fn read(&self, offset: u32) -> u32 {
+        unsafe {
+            match self {
+                Self::XApic { base } => ptr::read_volatile(base.byte_add(offset as usize)),
+                Self::X2Apic => rdmsr(X2APIC_MSR_BASE + (offset >> 4)) as u32,
+            }
+        }
+    }
+
+    /// Writes the register at `offset`, which is its xAPIC MMIO offset.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be valid for the register, and must not deliver interrupts that nothing
+    /// handles.
+    pub unsafe fn write(&self, offset: u32, value: u32) {
+        match self {
+            Self::XApic { base } => ptr::write_volatile(base.byte_add(offset as usize), value),
+            Self::X2Apic => wrmsr(X2APIC_MSR_BASE + (offset >> 4), value as u64),
+        }
+    }
+
+    /// Returns the APIC ID of the current CPU.
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::XApic { .. } => self.read(ID) >> 24,
+            Self::X2Apic => self.read(ID),
+        }
+    }
+
+    /// Returns the version of the local APIC and the number of LVT entries it has.
+    pub fn version(&self) -> (u8, u8) {
+        let version = self.read(VERSION);
+        (version as u8, ((version >> 16) as u8) + 1)
+    }
+
+    /// Acknowledges the interrupt that is being handled.
+    pub fn end_of_interrupt(&self) {
+        unsafe { self.write(EOI, 0) };
+    }
+
+    /// Interrupts with a priority class at or below `priority` are not delivered.
+    pub fn set_task_priority(&self, priority: u8) {
+        unsafe { self.write(TASK_PRIORITY, priority as u32) };
+    }
+
+    /// Returns the error status, which records illegal vectors and failed IPIs.
+    pub fn error_status(&self) -> u32 {
+        // The register is updated by writing to it, and x2APIC mode only allows writing 0
+        unsafe { self.write(ERROR_STATUS, 0) };
+        self.read(ERROR_STATUS)
+    }
+
+    pub fn set_timer_divide(&self, divide: TimerDivide) {
+        unsafe { self.write(TIMER_DIVIDE, divide as u32) };
+    }
+
+    /// Raises `vector` once after `ticks` timer ticks.
+    ///
+    /// # Safety
+    ///
+    /// Something must handle `vector`.
+    pub unsafe fn start_one_shot(&self, vector: u8, ticks: u32) {
+        self.write(LVT_TIMER, vector as u32);
+        self.write(TIMER_INITIAL_COUNT, ticks);
+    }
+
+    /// Raises `vector` every `ticks` timer ticks.
+    ///
+    /// # Safety
+    ///
+    /// Something must handle `vector`.
+    pub unsafe fn start_periodic(&self, vector: u8, ticks: u32) {
+        self.write(LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+        self.write(TIMER_INITIAL_COUNT, ticks);
+    }
+
+    pub fn stop_timer(&self) {
+        unsafe {
+            self.write(TIMER_INITIAL_COUNT, 0);
+            self.write(LVT_TIMER, LVT_MASKED);
+        }
+    }
+
+    pub fn timer_current_count(&self) -> u32 {
+        self.read(TIMER_CURRENT_COUNT)
+    }
+
+    /// Returns the number of timer ticks per millisecond with `divide`, measured against
+    /// `reference`. The timer is left stopped and masked, with `divide` set.
+    pub fn calibrate_timer(
+        &self,
+        divide: TimerDivide,
+        reference: &impl ReferenceTimer,
+    ) -> Result<u32, Error> {
+        self.set_timer_divide(divide);
+
+        // Count down from the maximum with the interrupt masked, so no vector is needed
+        unsafe {
+            self.write(LVT_TIMER, LVT_MASKED);
+            self.write(TIMER_INITIAL_COUNT, u32::MAX);
+        }
+        reference.wait_us(CALIBRATION_US);
+        let remaining = self.timer_current_count();
+        self.stop_timer();
+
+        match remaining {
+            0 => Err("Local APIC timer expired during calibration, use a larger divide"),
+            u32::MAX => Err("Local APIC timer did not count"),
+            remaining => Ok((u32::MAX - remaining) / (CALIBRATION_US / 1000)),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use core::{arch::asm, fmt, str};
+
+const VENDOR_LEAF: u32 = 0x0;
+const FEATURE_LEAF: u32 = 0x1;
+const EXTENDED_BASE_LEAF: u32 = 0x8000_0000;
+const EXTENDED_FEATURE_LEAF: u32 = 0x8000_0001;
+const BRAND_STRING_LEAVES: [u32; 3] = [0x8000_0002, 0x8000_0003, 0x8000_0004];
+const POWER_MANAGEMENT_LEAF: u32 = 0x8000_0007;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CpuVendor {
+    Intel,
+    Amd,
+    Other([u8; 12]),
+}
+
+/// The processor brand string, such as "QEMU Virtual CPU version 2.5+".
+#[derive(Copy, Clone)]
+pub struct BrandString([u8; 48]);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuFeatures {
+    pub apic: bool,
+    pub msr: bool,
+    pub pae: bool,
+    pub pge: bool,
+    pub pat: bool,
+    pub tsc: bool,
+    pub pcid: bool,
+    pub x2apic: bool,
+    pub no_execute: bool,
+    pub pages_1gib: bool,
+    pub invariant_tsc: bool,
+}
+
+/// Information returned by the `cpuid` instruction.
+#[derive(Copy, Clone, Debug)]
+pub struct Cpuid {
+    max_leaf: u32,
+    max_extended_leaf: u32,
+}
+
+pub fn cpuid(leaf: u32, sub_leaf: u32) -> CpuidResult {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+
+    // LLVM reserves rbx, so it has to be preserved manually
+    unsafe {
+        asm!(
+            "mov {rbx_tmp:r}, rbx",
+            "cpuid",
+            "xchg {rbx_tmp:r}, rbx",
+            rbx_tmp = out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") sub_leaf => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    CpuidResult { eax, ebx, ecx, edx }
+}
+
+impl Cpuid {
+    pub fn new() -> Self {
+        let max_leaf = cpuid(VENDOR_LEAF, 0).eax;
+        let max_extended_leaf = cpuid(EXTENDED_BASE_LEAF, 0).eax;
+
+        Self {
+            max_leaf,
+            max_extended_leaf,
+        }
+    }
+
+    /// Returns the result of a leaf, or `None` if the CPU does not implement it.
+    pub fn leaf(&self, leaf: u32, sub_leaf: u32) -> Option<CpuidResult> {
+        let max = if leaf >= EXTENDED_BASE_LEAF {
+            self.max_extended_leaf
+        } else {
+            self.max_leaf
+        };
+
+        if leaf > max {
+            return None;
+        }
+
+        Some(cpuid(leaf, sub_leaf))
+    }
+
+    pub fn vendor(&self) -> CpuVendor {
+        let result = cpuid(VENDOR_LEAF, 0);
+
+        let mut vendor = [0u8; 12];
+        vendor[0..4].copy_from_slice(&result.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&result.edx.to_le_bytes());
+        vendor[8..12].copy_from_slice(&result.ecx.to_le_bytes());
+
+        match &vendor {
+            b"GenuineIntel" => CpuVendor::Intel,
+            b"AuthenticAMD" => CpuVendor::Amd,
+            _ => CpuVendor::Other(vendor),
+        }
+    }
+
+    pub fn brand_string(&self) -> Option<BrandString> {
+        let mut brand = [0u8; 48];
+
+        for (i, leaf) in BRAND_STRING_LEAVES.iter().enumerate() {
+            let result = self.leaf(*leaf, 0)?;
+            let registers = [result.eax, result.ebx, result.ecx, result.edx];
+            for (j, register) in registers.iter().enumerate() {
+                let offset = i * 16 + j * 4;
+                brand[offset..offset + 4].copy_from_slice(&register.to_le_bytes());
+            }
+        }
+
+        Some(BrandString(brand))
+    }
+
+    pub fn features(&self) -> CpuFeatures {
+        let mut features = CpuFeatures::default();
+
+        if let Some(result) = self.leaf(FEATURE_LEAF, 0) {
+            features.tsc = (result.edx & (1 << 4)) != 0;
+            features.msr = (result.edx & (1 << 5)) != 0;
+            features.pae = (result.edx & (1 << 6)) != 0;
+            features.apic = (result.edx & (1 << 9)) != 0;
+            features.pge = (result.edx & (1 << 13)) != 0;
+            features.pat = (result.edx & (1 << 16)) != 0;
+            features.pcid = (result.ecx & (1 << 17)) != 0;
+            features.x2apic = (result.ecx & (1 << 21)) != 0;
+        }
+
+        if let Some(result) = self.leaf(EXTENDED_FEATURE_LEAF, 0) {
+            features.no_execute = (result.edx & (1 << 20)) != 0;
+            features.pages_1gib = (result.edx & (1 << 26)) != 0;
+        }
+
+        if let Some(result) = self.leaf(POWER_MANAGEMENT_LEAF, 0) {
+            features.invariant_tsc = (result.edx & (1 << 8)) != 0;
+        }
+
+        features
+    }
+}
+
+impl Default for Cpuid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuVendor {
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        match self {
+            Self::Intel => b"GenuineIntel",
+            Self::Amd => b"AuthenticAMD",
+            Self::Other(vendor) => vendor,
+        }
+    }
+}
+
+impl BrandString {
+    /// Returns the brand string without its null terminator and padding.
+    pub fn as_str(&self) -> &str {
+        let length = self.0.iter().position(|&c| c == 0).unwrap_or(self.0.len());
+        str::from_utf8(&self.0[..length]).unwrap_or("").trim()
+    }
+}
+
+impl fmt::Debug for BrandString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
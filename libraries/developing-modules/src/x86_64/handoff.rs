@@ -0,0 +1,30 @@
+use core::arch::asm;
+
+use crate::boot_info::BootInfo;
+
+/// Switches to the kernel's page tables and stack, then jumps to its entry point with the System V
+/// calling convention (`boot_info` in rdi). Interrupts are disabled before anything is switched.
+///
+/// `stack_top` is aligned down to 16 bytes and a null return address is pushed, so the kernel
+/// sees the same stack layout as after a `call`.
+///
+/// # Safety
+///
+/// `pml4` must be the physical address of a top level page table that maps this function at its
+/// current address, the kernel entry point, the whole stack, and the boot info along with
+/// everything it points to.
+pub unsafe fn handoff(entry: u64, stack_top: u64, pml4: u64, boot_info: *const BootInfo) -> ! {
+    asm!(
+        "cli",
+        "mov cr3, rcx",
+        "mov rsp, rdx",
+        "xor ebp, ebp",
+        "push 0",
+        "jmp rax",
+        in("rax") entry,
+        in("rcx") pml4,
+        in("rdx") stack_top & !0xf,
+        in("rdi") boot_info,
+        options(noreturn),
+    );
+}
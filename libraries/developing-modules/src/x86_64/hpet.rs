@@ -0,0 +1,85 @@
+// The main counter of the high precision event timer, found through the ACPI HPET table. Only the
+// counter is used, as a reference for calibrating other timers; the comparators are left alone.
+
+use core::ptr;
+
+use crate::{memory::PhysicalMapper, x86_64::apic::ReferenceTimer};
+
+pub type Error = &'static str;
+
+const CAPABILITIES: usize = 0x00;
+const CONFIGURATION: usize = 0x10;
+const MAIN_COUNTER: usize = 0xf0;
+
+const COUNTER_64_BIT: u64 = 1 << 13;
+const ENABLE: u64 = 1 << 0;
+
+/// The largest period that the specification allows, 100 ns in femtoseconds.
+const MAX_PERIOD_FS: u32 = 100_000_000;
+
+#[derive(Debug)]
+pub struct HpetCounter {
+    base: *mut u64,
+    period_fs: u32,
+    is_64_bit: bool,
+}
+
+impl HpetCounter {
+    /// Maps the HPET registers at `address` and starts the main counter.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the base of an HPET, such as [`crate::acpi::hpet::Hpet::address`], and
+    /// nothing else may be using its registers.
+    pub unsafe fn new(mapper: &impl PhysicalMapper, address: u64) -> Result<Self, Error> {
+        let base = mapper.map_physical(address, 0x400) as *mut u64;
+        let capabilities = ptr::read_volatile(base.byte_add(CAPABILITIES));
+        let period_fs = (capabilities >> 32) as u32;
+        if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+            return Err("Invalid HPET counter period");
+        }
+
+        let configuration = base.byte_add(CONFIGURATION);
+        ptr::write_volatile(configuration, ptr::read_volatile(configuration) | ENABLE);
+
+        Ok(Self {
+            base,
+            period_fs,
+            is_64_bit: capabilities & COUNTER_64_BIT != 0,
+        })
+    }
+
+    /// Returns the length of one counter tick in femtoseconds.
+    pub fn period_fs(&self) -> u32 {
+        self.period_fs
+    }
+
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period_fs as u64
+    }
+
+    pub fn counter(&self) -> u64 {
+        let counter = unsafe { ptr::read_volatile(self.base.byte_add(MAIN_COUNTER)) };
+        if self.is_64_bit {
+            counter
+        } else {
+            counter & u32::MAX as u64
+        }
+    }
+}
+
+impl ReferenceTimer for HpetCounter {
+    fn wait_us(&self, microseconds: u32) {
+        let ticks = microseconds as u64 * 1_000_000_000 / self.period_fs as u64;
+        let mask = if self.is_64_bit {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        };
+        let start = self.counter();
+
+        while self.counter().wrapping_sub(start) & mask < ticks {
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// IO APICs route global system interrupts (GSIs) to local APICs. The MADT lists each IO APIC with
+// the first GSI it handles, and its interrupt source overrides say which GSI an ISA IRQ is wired
+// to, with which polarity and trigger mode. ISA IRQs without an override are identity mapped,
+// active high and edge triggered.
+
+use core::ptr;
+
+use crate::{
+    acpi::madt::{Madt, MadtEntry, MpsIntiFlags, Polarity, TriggerMode},
+    memory::PhysicalMapper,
+};
+
+pub type Error = &'static str;
+
+const REGISTER_SELECT: usize = 0x00;
+const REGISTER_WINDOW: usize = 0x10;
+
+pub const IOAPIC_ID: u32 = 0x00;
+pub const IOAPIC_VERSION: u32 = 0x01;
+pub const REDIRECTION_TABLE: u32 = 0x10;
+
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryMode {
+    Fixed = 0b000,
+    LowestPriority = 0b001,
+    Smi = 0b010,
+    Nmi = 0b100,
+    Init = 0b101,
+    ExtInt = 0b111,
+}
+
+/// An entry of the redirection table, which routes one GSI to a vector on a local APIC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    pub delivery_mode: DeliveryMode,
+    pub active_low: bool,
+    pub level_triggered: bool,
+    pub masked: bool,
+    /// The APIC ID of the destination CPU, in physical destination mode.
+    pub destination: u8,
+}
+
+#[derive(Debug)]
+pub struct IoApic {
+    base: *mut u32,
+    id: u8,
+    gsi_base: u32,
+}
+
+impl RedirectionEntry {
+    /// Returns an unmasked, fixed delivery entry for an ISA IRQ with the flags from its interrupt
+    /// source override.
+    pub fn from_isa_flags(vector: u8, flags: MpsIntiFlags, destination: u8) -> Self {
+        Self {
+            vector,
+            delivery_mode: DeliveryMode::Fixed,
+            active_low: flags.polarity() == Polarity::ActiveLow,
+            level_triggered: flags.trigger_mode() == TriggerMode::Level,
+            masked: false,
+            destination,
+        }
+    }
+
+    pub fn to_raw(&self) -> u64 {
+        let mut raw = self.vector as u64 | ((self.delivery_mode as u64) << 8);
+        if self.active_low {
+            raw |= 1 << 13;
+        }
+        if self.level_triggered {
+            raw |= 1 << 15;
+        }
+        if self.masked {
+            raw |= REDIRECTION_MASKED;
+        }
+
+        raw | ((self.destination as u64) << 56)
+    }
+}
+
+impl IoApic {
+    /// # Safety
+    ///
+    /// `address` must be the base of an IO APIC, and nothing else may be using its registers.
+    pub unsafe fn new(mapper: &impl PhysicalMapper, id: u8, address: u64, gsi_base: u32) -> Self {
+        Self {
+            base: mapper.map_physical(address, 0x20) as *mut u32,
+            id,
+            gsi_base,
+        }
+    }
+
+    /// Returns every IO APIC in the MADT.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::new`], for every IO APIC in `madt`.
+    pub unsafe fn from_madt<'a, M: PhysicalMapper>(
+        mapper: &'a M,
+        madt: &Madt<'a>,
+    ) -> impl Iterator<Item = Self> + 'a {
+        madt.entries().filter_map(move |entry| match entry {
+            MadtEntry::IoApic {
+                id,
+                address,
+                gsi_base,
+            } => Some(Self::new(mapper, id, address as u64, gsi_base)),
+            _ => None,
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ptr::write_volatile(self.base.byte_add(REGISTER_SELECT), register);
+            ptr::read_volatile(self.base.byte_add(REGISTER_WINDOW))
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `value` must be valid for the register, and must not deliver interrupts that nothing
+    /// handles.
+    pub unsafe fn write(&self, register: u32, value: u32) {
+        ptr::write_volatile(self.base.byte_add(REGISTER_SELECT), register);
+        ptr::write_volatile(self.base.byte_add(REGISTER_WINDOW), value);
+    }
+
+    pub fn version(&self) -> u8 {
+        self.read(IOAPIC_VERSION) as u8
+    }
+
+    pub fn redirection_count(&self) -> u32 {
+        ((self.read(IOAPIC_VERSION) >> 16) & 0xff) + 1
+    }
+
+    /// Returns the GSIs that this IO APIC handles.
+    pub fn gsi_range(&self) -> core::ops::Range<u32> {
+        self.gsi_base..self.gsi_base + self.redirection_count()
+    }
+
+    fn entry_index(&self, gsi: u32) -> Result<u32, Error> {
+        if self.gsi_range().contains(&gsi) {
+            Ok(gsi - self.gsi_base)
+        } else {
+            Err("GSI is not handled by this IO APIC")
+        }
+    }
+
+    pub fn redirection(&self, gsi: u32) -> Result<u64, Error> {
+        let register = REDIRECTION_TABLE + self.entry_index(gsi)? * 2;
+        let low = self.read(register) as u64;
+        let high = self.read(register + 1) as u64;
+
+        Ok((high << 32) | low)
+    }
+
+    /// # Safety
+    ///
+    /// Something must handle the vector of `entry` if it is unmasked.
+    pub unsafe fn set_redirection(&self, gsi: u32, entry: u64) -> Result<(), Error> {
+        let register = REDIRECTION_TABLE + self.entry_index(gsi)? * 2;
+
+        // Mask the entry while it is being changed, then write the low half with the real mask
+        self.write(register, REDIRECTION_MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+
+        Ok(())
+    }
+
+    pub fn mask(&self, gsi: u32) -> Result<(), Error> {
+        let entry = self.redirection(gsi)?;
+        unsafe { self.set_redirection(gsi, entry | REDIRECTION_MASKED) }
+    }
+
+    /// # Safety
+    ///
+    /// Something must handle the vector of the entry for `gsi`.
+    pub unsafe fn unmask(&self, gsi: u32) -> Result<(), Error> {
+        let entry = self.redirection(gsi)?;
+        self.set_redirection(gsi, entry & !REDIRECTION_MASKED)
+    }
+
+    pub fn mask_all(&self) {
+        for gsi in self.gsi_range() {
+            let _ = self.mask(gsi);
+        }
+    }
+}
+
+/// Routes ISA `irq` to `vector` on the CPU with APIC ID `destination`, following the MADT's
+/// interrupt source overrides. Returns the GSI that it was routed through.
+///
+/// # Safety
+///
+/// Something must handle `vector`.
+pub unsafe fn route_isa_irq(
+    ioapics: &[IoApic],
+    madt: &Madt,
+    irq: u8,
+    vector: u8,
+    destination: u8,
+) -> Result<u32, Error> {
+    let (gsi, flags) = madt.isa_irq_to_gsi(irq);
+    let ioapic = ioapics
+        .iter()
+        .find(|ioapic| ioapic.gsi_range().contains(&gsi))
+        .ok_or("No IO APIC handles the GSI of the ISA IRQ")?;
+
+    let entry = RedirectionEntry::from_isa_flags(vector, flags, destination);
+    ioapic.set_redirection(gsi, entry.to_raw())?;
+
+    Ok(gsi)
+}
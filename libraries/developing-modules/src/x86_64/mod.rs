@@ -1,3 +1,13 @@
+pub mod apic;
+pub mod cpuid;
 pub mod gdt;
+pub mod handoff;
+pub mod hpet;
+pub mod ioapic;
+pub mod msr;
+pub mod paging;
+pub mod pic;
+pub mod pit;
 pub mod port_io;
+pub mod registers;
 pub mod uart;
@@ -0,0 +1,65 @@
+use core::arch::asm;
+
+/// Model specific registers that are used by the bootloader and kernel.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Msr {
+    ApicBase = 0x1b,
+    Pat = 0x277,
+    Efer = 0xc000_0080,
+    FsBase = 0xc000_0100,
+    GsBase = 0xc000_0101,
+    KernelGsBase = 0xc000_0102,
+}
+
+impl Msr {
+    /// # Safety
+    ///
+    /// Must be run at CPL 0 on a CPU that implements this MSR.
+    pub unsafe fn read(self) -> u64 {
+        rdmsr(self as u32)
+    }
+
+    /// # Safety
+    ///
+    /// Must be run at CPL 0 on a CPU that implements this MSR. Writing an MSR can change the
+    /// behavior of the whole CPU, so `value` must be valid for the current state of the system.
+    pub unsafe fn write(self, value: u64) {
+        wrmsr(self as u32, value)
+    }
+}
+
+/// # Safety
+///
+/// Reading an unimplemented MSR or reading from outside of CPL 0 raises a general protection fault.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let lo: u32;
+    let hi: u32;
+
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") lo,
+        out("edx") hi,
+        options(nomem, nostack, preserves_flags),
+    );
+
+    ((hi as u64) << 32) | (lo as u64)
+}
+
+/// # Safety
+///
+/// Writing an unimplemented MSR, writing reserved bits, or writing from outside of CPL 0 raises a
+/// general protection fault.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    let lo = value as u32;
+    let hi = (value >> 32) as u32;
+
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") lo,
+        in("edx") hi,
+        options(nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,314 @@
+use core::{
+    arch::asm,
+    fmt,
+    ops::{Index, IndexMut},
+};
+
+use crate::{
+    memory::{Error, FrameAllocator, PageMap, PageSize},
+    x86_64::{cpuid::Cpuid, registers::Cr3},
+};
+
+pub const ENTRY_COUNT: usize = 512;
+
+/// Start of the higher half of the 48-bit virtual address space.
+pub const HIGHER_HALF_START: u64 = 0xffff_8000_0000_0000;
+
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PageTableEntry(u64);
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    entries: [PageTableEntry; ENTRY_COUNT],
+}
+
+/// Builds and walks a 4-level page table hierarchy.
+///
+/// Page tables are accessed through `phys_offset + physical address`, so an identity mapped
+/// bootloader uses an offset of 0.
+#[derive(Debug)]
+pub struct PageMapper {
+    pml4: u64,
+    phys_offset: u64,
+    supports_1gib_pages: bool,
+}
+
+impl PageTableEntry {
+    pub const PRESENT: u64 = 1 << 0;
+    pub const WRITABLE: u64 = 1 << 1;
+    pub const USER: u64 = 1 << 2;
+    pub const WRITE_THROUGH: u64 = 1 << 3;
+    pub const NO_CACHE: u64 = 1 << 4;
+    pub const ACCESSED: u64 = 1 << 5;
+    pub const DIRTY: u64 = 1 << 6;
+    pub const HUGE_PAGE: u64 = 1 << 7;
+    pub const GLOBAL: u64 = 1 << 8;
+    pub const NO_EXECUTE: u64 = 1 << 63;
+
+    const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn new(address: u64, flags: u64) -> Self {
+        Self((address & Self::ADDRESS_MASK) | (flags & !Self::ADDRESS_MASK))
+    }
+
+    pub fn address(&self) -> u64 {
+        self.0 & Self::ADDRESS_MASK
+    }
+
+    pub fn flags(&self) -> u64 {
+        self.0 & !Self::ADDRESS_MASK
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.contains(Self::PRESENT)
+    }
+
+    pub fn is_huge(&self) -> bool {
+        self.contains(Self::HUGE_PAGE)
+    }
+
+    pub fn set(&mut self, address: u64, flags: u64) {
+        *self = Self::new(address, flags);
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl PageTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: [PageTableEntry::empty(); ENTRY_COUNT],
+        }
+    }
+
+    pub fn zero(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.clear();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PageTableEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Default for PageTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for PageTable {
+    type Output = PageTableEntry;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.entries[index]
+    }
+}
+
+impl IndexMut<usize> for PageTable {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.entries[index]
+    }
+}
+
+/// Returns the index into the table at `level` (1 to 4) that `virt` uses.
+fn table_index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * (level - 1))) & 0x1ff) as usize
+}
+
+fn is_canonical(virt: u64) -> bool {
+    let upper = virt >> 47;
+    upper == 0 || upper == 0x1_ffff
+}
+
+/// Invalidates the TLB entry for a single page.
+pub fn flush(virt: u64) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags));
+    }
+}
+
+impl PageMapper {
+    /// Allocates and zeroes a new top level page table.
+    pub fn new(allocator: &mut impl FrameAllocator, phys_offset: u64) -> Result<Self, Error> {
+        let pml4 = allocator
+            .allocate_frame()
+            .ok_or("Failed to allocate a frame for the PML4")?;
+
+        let mapper = unsafe { Self::from_pml4(pml4, phys_offset) };
+        unsafe { mapper.table_at(pml4).zero() };
+
+        Ok(mapper)
+    }
+
+    /// # Safety
+    ///
+    /// `pml4` must be the physical address of a valid top level page table, and every table it
+    /// references must be accessible at `phys_offset + physical address`.
+    pub unsafe fn from_pml4(pml4: u64, phys_offset: u64) -> Self {
+        Self {
+            pml4,
+            phys_offset,
+            supports_1gib_pages: Cpuid::new().features().pages_1gib,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Every page table in the active hierarchy must be accessible at
+    /// `phys_offset + physical address`.
+    pub unsafe fn from_active(phys_offset: u64) -> Self {
+        Self::from_pml4(Cr3::read().address(), phys_offset)
+    }
+
+    /// Returns the physical address of the top level page table.
+    pub fn pml4_address(&self) -> u64 {
+        self.pml4
+    }
+
+    unsafe fn table_at<'a>(&self, address: u64) -> &'a mut PageTable {
+        &mut *((address + self.phys_offset) as *mut PageTable)
+    }
+
+    /// Loads this page table hierarchy into CR3.
+    ///
+    /// # Safety
+    ///
+    /// The new mappings must cover the running code, its stack, and all memory that is used
+    /// after the switch.
+    pub unsafe fn activate(&self) {
+        Cr3::new(self.pml4, 0).write();
+    }
+}
+
+impl PageMap for PageMapper {
+    type Flags = u64;
+
+    fn higher_half_start(&self) -> u64 {
+        HIGHER_HALF_START
+    }
+
+    fn supports_page_size(&self, size: PageSize) -> bool {
+        size != PageSize::Size1GiB || self.supports_1gib_pages
+    }
+
+    unsafe fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: PageSize,
+        flags: Self::Flags,
+        allocator: &mut impl FrameAllocator,
+    ) -> Result<(), Error> {
+        if !is_canonical(virt) {
+            return Err("Virtual address is not canonical");
+        }
+        if !virt.is_multiple_of(size.bytes()) || !phys.is_multiple_of(size.bytes()) {
+            return Err("Address is not aligned to the page size");
+        }
+        if !self.supports_page_size(size) {
+            return Err("CPU does not support 1 GiB pages");
+        }
+
+        let mut table = self.table_at(self.pml4);
+        for level in (size.level() + 1..=4).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_present() {
+                let frame = allocator
+                    .allocate_frame()
+                    .ok_or("Failed to allocate a frame for a page table")?;
+                self.table_at(frame).zero();
+                entry.set(
+                    frame,
+                    PageTableEntry::PRESENT
+                        | PageTableEntry::WRITABLE
+                        | (flags & PageTableEntry::USER),
+                );
+            } else if entry.is_huge() {
+                return Err("Virtual address is already mapped by a larger page");
+            } else if (flags & PageTableEntry::USER) != 0 {
+                entry.set(entry.address(), entry.flags() | PageTableEntry::USER);
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        let entry = &mut table[table_index(virt, size.level())];
+        if entry.is_present() {
+            return Err("Virtual address is already mapped");
+        }
+
+        let huge = if size == PageSize::Size4KiB {
+            0
+        } else {
+            PageTableEntry::HUGE_PAGE
+        };
+        entry.set(phys, flags | huge | PageTableEntry::PRESENT);
+
+        Ok(())
+    }
+
+    unsafe fn unmap(&mut self, virt: u64) -> Result<(u64, PageSize), Error> {
+        let mut table = self.table_at(self.pml4);
+        for level in (1..=4).rev() {
+            let entry = &mut table[table_index(virt, level)];
+
+            if !entry.is_present() {
+                return Err("Virtual address is not mapped");
+            }
+
+            if level == 1 || entry.is_huge() {
+                let phys = entry.address();
+                entry.clear();
+                flush(virt);
+                return Ok((phys, PageSize::from_level(level)));
+            }
+
+            table = self.table_at(entry.address());
+        }
+
+        unreachable!()
+    }
+
+    fn translate(&self, virt: u64) -> Option<u64> {
+        let mut table = unsafe { self.table_at(self.pml4) };
+        for level in (1..=4).rev() {
+            let entry = &table[table_index(virt, level)];
+
+            if !entry.is_present() {
+                return None;
+            }
+
+            if level == 1 || entry.is_huge() {
+                let size = PageSize::from_level(level).bytes();
+                return Some(entry.address() + (virt & (size - 1)));
+            }
+
+            table = unsafe { self.table_at(entry.address()) };
+        }
+
+        None
+    }
+}
+
+impl fmt::Debug for PageTableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageTableEntry")
+            .field("address", &format_args!("{:#x}", self.address()))
+            .field("flags", &format_args!("{:#x}", self.flags()))
+            .finish()
+    }
+}
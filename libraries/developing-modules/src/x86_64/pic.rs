@@ -0,0 +1,118 @@
+// The pair of 8259 programmable interrupt controllers that PC compatible systems start with. Their
+// default vectors overlap the CPU exceptions, so they are remapped before anything is unmasked.
+// When the APICs handle interrupts instead, they are remapped and then fully masked, so that a
+// spurious interrupt from them cannot be mistaken for an exception.
+
+use crate::x86_64::port_io::{inb, io_wait, outb};
+
+const PRIMARY_COMMAND: u16 = 0x20;
+const PRIMARY_DATA: u16 = 0x21;
+const SECONDARY_COMMAND: u16 = 0xa0;
+const SECONDARY_DATA: u16 = 0xa1;
+
+const ICW1_ICW4: u8 = 1 << 0;
+const ICW1_INIT: u8 = 1 << 4;
+const ICW4_8086: u8 = 1 << 0;
+const OCW2_EOI: u8 = 0x20;
+
+/// The IRQ line of the primary PIC that the secondary PIC is cascaded through.
+pub const CASCADE_IRQ: u8 = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ChainedPics {
+    primary_offset: u8,
+    secondary_offset: u8,
+}
+
+impl ChainedPics {
+    /// Both offsets must be multiples of 8, and must not overlap the CPU exceptions or each other.
+    pub const fn new(primary_offset: u8, secondary_offset: u8) -> Self {
+        Self {
+            primary_offset,
+            secondary_offset,
+        }
+    }
+
+    /// Remaps both PICs to their offsets, keeping the current IRQ masks.
+    ///
+    /// # Safety
+    ///
+    /// Interrupts must be disabled, and the new vectors must not be in use by anything else.
+    pub unsafe fn init(&self) {
+        let masks = self.masks();
+
+        outb(PRIMARY_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+        outb(SECONDARY_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+        outb(PRIMARY_DATA, self.primary_offset);
+        io_wait();
+        outb(SECONDARY_DATA, self.secondary_offset);
+        io_wait();
+        outb(PRIMARY_DATA, 1 << CASCADE_IRQ);
+        io_wait();
+        outb(SECONDARY_DATA, CASCADE_IRQ);
+        io_wait();
+        outb(PRIMARY_DATA, ICW4_8086);
+        io_wait();
+        outb(SECONDARY_DATA, ICW4_8086);
+        io_wait();
+
+        self.set_masks(masks);
+    }
+
+    /// Remaps both PICs and masks every IRQ, so that only the APICs deliver interrupts.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::init`].
+    pub unsafe fn disable(&self) {
+        self.init();
+        self.set_masks(0xffff);
+    }
+
+    /// Returns the IRQ masks, with the primary PIC in the low byte. A set bit masks the IRQ.
+    pub fn masks(&self) -> u16 {
+        u16::from_le_bytes([inb(PRIMARY_DATA), inb(SECONDARY_DATA)])
+    }
+
+    pub fn set_masks(&self, masks: u16) {
+        let [primary, secondary] = masks.to_le_bytes();
+        outb(PRIMARY_DATA, primary);
+        outb(SECONDARY_DATA, secondary);
+    }
+
+    pub fn mask_irq(&self, irq: u8) {
+        self.set_masks(self.masks() | (1 << irq));
+    }
+
+    /// Unmasks `irq`, and the cascade line if `irq` belongs to the secondary PIC.
+    pub fn unmask_irq(&self, irq: u8) {
+        let mut masks = self.masks() & !(1 << irq);
+        if irq >= 8 {
+            masks &= !(1 << CASCADE_IRQ);
+        }
+        self.set_masks(masks);
+    }
+
+    /// Returns the IRQ that `vector` belongs to, if the PICs raise it.
+    pub fn irq_for_vector(&self, vector: u8) -> Option<u8> {
+        match vector {
+            v if (self.primary_offset..self.primary_offset + 8).contains(&v) => {
+                Some(v - self.primary_offset)
+            }
+            v if (self.secondary_offset..self.secondary_offset + 8).contains(&v) => {
+                Some(v - self.secondary_offset + 8)
+            }
+            _ => None,
+        }
+    }
+
+    /// Acknowledges `irq`, which must be sent at the end of its handler.
+    pub fn end_of_interrupt(&self, irq: u8) {
+        if irq >= 8 {
+            outb(SECONDARY_COMMAND, OCW2_EOI);
+        }
+        outb(PRIMARY_COMMAND, OCW2_EOI);
+    }
+}
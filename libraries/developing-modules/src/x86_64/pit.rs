@@ -0,0 +1,87 @@
+// The 8254 programmable interval timer. It runs at a fixed frequency on every PC compatible
+// system, which makes it a reference for calibrating faster timers such as the local APIC timer.
+// Channel 2 can be gated and polled through port 0x61 without raising any interrupts.
+
+use crate::x86_64::{
+    apic::ReferenceTimer,
+    port_io::{inb, outb},
+};
+
+pub type Error = &'static str;
+
+/// The frequency of the PIT's input clock in Hz.
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0_DATA: u16 = 0x40;
+const CHANNEL2_DATA: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+const CHANNEL2_GATE: u16 = 0x61;
+
+// Channel in bits 6-7, low then high byte access in bits 4-5, mode in bits 1-3
+const CHANNEL0_RATE_GENERATOR: u8 = (0b11 << 4) | (2 << 1);
+const CHANNEL2_ONE_SHOT: u8 = (0b10 << 6) | (0b11 << 4);
+
+const GATE_ENABLE: u8 = 1 << 0;
+const SPEAKER_ENABLE: u8 = 1 << 1;
+const CHANNEL2_OUTPUT: u8 = 1 << 5;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Pit(());
+
+impl Pit {
+    pub fn new() -> Self {
+        Self(())
+    }
+
+    /// Busy waits for `ticks` periods of the input clock using channel 2, with the speaker
+    /// disconnected.
+    pub fn wait_ticks(&self, ticks: u16) {
+        let gate = inb(CHANNEL2_GATE) & !(GATE_ENABLE | SPEAKER_ENABLE);
+        outb(CHANNEL2_GATE, gate);
+
+        let [low, high] = ticks.to_le_bytes();
+        outb(COMMAND, CHANNEL2_ONE_SHOT);
+        outb(CHANNEL2_DATA, low);
+        outb(CHANNEL2_DATA, high);
+
+        // The count starts when the gate goes high, and the output goes high when it reaches 0
+        outb(CHANNEL2_GATE, gate | GATE_ENABLE);
+        while inb(CHANNEL2_GATE) & CHANNEL2_OUTPUT == 0 {
+            core::hint::spin_loop();
+        }
+
+        outb(CHANNEL2_GATE, gate);
+    }
+
+    /// Makes channel 0 raise IRQ 0 at `frequency` Hz, which must be between 19 and
+    /// [`PIT_FREQUENCY`].
+    pub fn start_periodic(&self, frequency: u32) -> Result<(), Error> {
+        let divisor = match frequency {
+            0 => return Err("PIT frequency is zero"),
+            frequency => PIT_FREQUENCY / frequency,
+        };
+        let divisor = match u16::try_from(divisor) {
+            Ok(0) | Err(_) => return Err("PIT frequency is out of range"),
+            Ok(divisor) => divisor,
+        };
+
+        let [low, high] = divisor.to_le_bytes();
+        outb(COMMAND, CHANNEL0_RATE_GENERATOR);
+        outb(CHANNEL0_DATA, low);
+        outb(CHANNEL0_DATA, high);
+
+        Ok(())
+    }
+}
+
+impl ReferenceTimer for Pit {
+    fn wait_us(&self, microseconds: u32) {
+        let mut ticks = microseconds as u64 * PIT_FREQUENCY as u64 / 1_000_000;
+
+        while ticks > 0 {
+            let chunk = ticks.min(u16::MAX as u64);
+            self.wait_ticks(chunk as u16);
+            ticks -= chunk;
+        }
+    }
+}
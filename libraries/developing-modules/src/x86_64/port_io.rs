@@ -23,3 +23,9 @@ pub fn outb(port_addr: u16, value: u8) {
         );
     }
 }
+
+/// Waits roughly a microsecond by writing to an unused port, for devices such as the 8259 PIC
+/// that need time between writes.
+pub fn io_wait() {
+    outb(0x80, 0);
+}
@@ -0,0 +1,244 @@
+use core::arch::asm;
+
+use crate::x86_64::msr::Msr;
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cr0(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cr2(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cr3(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cr4(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Efer(pub u64);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rflags(pub u64);
+
+impl Cr0 {
+    pub const PROTECTED_MODE: u64 = 1 << 0;
+    pub const MONITOR_COPROCESSOR: u64 = 1 << 1;
+    pub const EMULATION: u64 = 1 << 2;
+    pub const TASK_SWITCHED: u64 = 1 << 3;
+    pub const EXTENSION_TYPE: u64 = 1 << 4;
+    pub const NUMERIC_ERROR: u64 = 1 << 5;
+    pub const WRITE_PROTECT: u64 = 1 << 16;
+    pub const ALIGNMENT_MASK: u64 = 1 << 18;
+    pub const NOT_WRITE_THROUGH: u64 = 1 << 29;
+    pub const CACHE_DISABLE: u64 = 1 << 30;
+    pub const PAGING: u64 = 1 << 31;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// Changing CR0 can disable paging or protection, so the new value must be compatible with the
+    /// code that is currently running.
+    pub unsafe fn write(self) {
+        asm!("mov cr0, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    pub fn is_paging_enabled(&self) -> bool {
+        self.contains(Self::PAGING)
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.contains(Self::WRITE_PROTECT)
+    }
+}
+
+impl Cr2 {
+    /// Returns the linear address that caused the most recent page fault.
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    pub fn address(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Cr3 {
+    pub const PAGE_WRITE_THROUGH: u64 = 1 << 3;
+    pub const PAGE_CACHE_DISABLE: u64 = 1 << 4;
+
+    const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+    const PCID_MASK: u64 = 0xfff;
+
+    pub fn new(pml4_address: u64, pcid: u16) -> Self {
+        Self((pml4_address & Self::ADDRESS_MASK) | (pcid as u64 & Self::PCID_MASK))
+    }
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// The new top level page table must map the currently running code, the stack, and any other
+    /// memory that is accessed after the switch.
+    pub unsafe fn write(self) {
+        asm!("mov cr3, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+
+    /// Returns the physical address of the top level page table.
+    pub fn address(&self) -> u64 {
+        self.0 & Self::ADDRESS_MASK
+    }
+
+    /// Returns the process context identifier. Only valid when CR4.PCIDE is set.
+    pub fn pcid(&self) -> u16 {
+        (self.0 & Self::PCID_MASK) as u16
+    }
+}
+
+impl Cr4 {
+    pub const VIRTUAL_8086_EXTENSIONS: u64 = 1 << 0;
+    pub const PROTECTED_VIRTUAL_INTERRUPTS: u64 = 1 << 1;
+    pub const TIMESTAMP_DISABLE: u64 = 1 << 2;
+    pub const DEBUGGING_EXTENSIONS: u64 = 1 << 3;
+    pub const PAGE_SIZE_EXTENSION: u64 = 1 << 4;
+    pub const PHYSICAL_ADDRESS_EXTENSION: u64 = 1 << 5;
+    pub const MACHINE_CHECK: u64 = 1 << 6;
+    pub const PAGE_GLOBAL: u64 = 1 << 7;
+    pub const PERFORMANCE_COUNTER: u64 = 1 << 8;
+    pub const OSFXSR: u64 = 1 << 9;
+    pub const OSXMMEXCPT: u64 = 1 << 10;
+    pub const USER_MODE_INSTRUCTION_PREVENTION: u64 = 1 << 11;
+    pub const LEVEL_5_PAGING: u64 = 1 << 12;
+    pub const FSGSBASE: u64 = 1 << 16;
+    pub const PCID: u64 = 1 << 17;
+    pub const OSXSAVE: u64 = 1 << 18;
+    pub const SUPERVISOR_MODE_EXECUTION_PREVENTION: u64 = 1 << 20;
+    pub const SUPERVISOR_MODE_ACCESS_PREVENTION: u64 = 1 << 21;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// Setting a bit for a feature that the CPU does not support raises a general protection fault,
+    /// and changing paging related bits must be compatible with the active page tables.
+    pub unsafe fn write(self) {
+        asm!("mov cr4, {}", in(reg) self.0, options(nostack, preserves_flags));
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl Efer {
+    pub const SYSCALL_ENABLE: u64 = 1 << 0;
+    pub const LONG_MODE_ENABLE: u64 = 1 << 8;
+    pub const LONG_MODE_ACTIVE: u64 = 1 << 10;
+    pub const NO_EXECUTE_ENABLE: u64 = 1 << 11;
+    pub const SECURE_VIRTUAL_MACHINE_ENABLE: u64 = 1 << 12;
+    pub const FAST_FXSAVE_FXRSTOR: u64 = 1 << 14;
+
+    pub fn read() -> Self {
+        Self(unsafe { Msr::Efer.read() })
+    }
+
+    /// # Safety
+    ///
+    /// Long mode bits must not be changed while paging is on, and feature bits must only be set
+    /// when the CPU supports them.
+    pub unsafe fn write(self) {
+        Msr::Efer.write(self.0);
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+}
+
+impl Rflags {
+    pub const CARRY: u64 = 1 << 0;
+    pub const PARITY: u64 = 1 << 2;
+    pub const AUXILIARY_CARRY: u64 = 1 << 4;
+    pub const ZERO: u64 = 1 << 6;
+    pub const SIGN: u64 = 1 << 7;
+    pub const TRAP: u64 = 1 << 8;
+    pub const INTERRUPT_ENABLE: u64 = 1 << 9;
+    pub const DIRECTION: u64 = 1 << 10;
+    pub const OVERFLOW: u64 = 1 << 11;
+    pub const IO_PRIVILEGE_LEVEL: u64 = 3 << 12;
+    pub const NESTED_TASK: u64 = 1 << 14;
+    pub const RESUME: u64 = 1 << 16;
+    pub const VIRTUAL_8086_MODE: u64 = 1 << 17;
+    pub const ALIGNMENT_CHECK: u64 = 1 << 18;
+    pub const VIRTUAL_INTERRUPT: u64 = 1 << 19;
+    pub const VIRTUAL_INTERRUPT_PENDING: u64 = 1 << 20;
+    pub const ID: u64 = 1 << 21;
+
+    pub fn read() -> Self {
+        let value: u64;
+
+        unsafe {
+            asm!("pushfq", "pop {}", out(reg) value, options(nomem, preserves_flags));
+        }
+
+        Self(value)
+    }
+
+    /// # Safety
+    ///
+    /// Changing RFLAGS can enable interrupts or change the direction of string instructions, which
+    /// the surrounding code must be prepared for.
+    pub unsafe fn write(self) {
+        // Not `nomem`, so that memory accesses are not moved across the point where interrupts
+        // may become enabled
+        asm!("push {}", "popfq", in(reg) self.0);
+    }
+
+    pub fn contains(&self, flags: u64) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    pub fn are_interrupts_enabled(&self) -> bool {
+        self.contains(Self::INTERRUPT_ENABLE)
+    }
+}
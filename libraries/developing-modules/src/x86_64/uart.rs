@@ -9,6 +9,18 @@ use crate::{
 #[derive(Copy, Clone, Debug)]
 pub enum UartX86Port {
     Com1 = 0x3f8,
+    Com2 = 0x2f8,
+    Com3 = 0x3e8,
+    Com4 = 0x2e8,
+}
+
+impl UartX86Port {
+    pub const ALL: [Self; 4] = [Self::Com1, Self::Com2, Self::Com3, Self::Com4];
+
+    /// Returns the port whose base I/O port is `address`.
+    pub fn from_address(address: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|port| *port as u16 == address)
+    }
 }
 
 #[repr(u16)]
@@ -72,6 +84,10 @@ impl UartX86 {
             is_initialized: false,
         }
     }
+
+    pub fn port(&self) -> UartX86Port {
+        self.port
+    }
 }
 
 impl Serial for UartX86 {
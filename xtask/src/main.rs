@@ -2,7 +2,7 @@ use xshell::Shell;
 
 mod flags {
     use anyhow::bail;
-    use std::{str::FromStr, vec, vec::Vec};
+    use std::{path::PathBuf, str::FromStr, vec, vec::Vec};
     use xflags;
     use xshell::{cmd, Shell};
 
@@ -23,10 +23,14 @@ mod flags {
             }
             cmd package {
                 required package_type: PackageType
+                optional --kernel path: PathBuf
             }
             cmd clean {}
             cmd run {
                 required package_type: PackageType
+                optional --kernel path: PathBuf
+                optional --smp count: u32
+                optional --gic-version version: u32
             }
         }
     }
@@ -144,6 +148,15 @@ mod flags {
                 Self::X86_64Uefi => Binary::X86_64Uefi,
             }
         }
+
+        /// Returns the cargo features that `run` builds with, as it also starts QEMU with the
+        /// options they need.
+        pub fn run_features(&self) -> &'static [&'static str] {
+            match self {
+                Self::Aarch64Qemu => &["semihosting"],
+                Self::RiscV64Qemu | Self::X86_64Uefi => &[],
+            }
+        }
     }
 
     #[derive(Debug)]
@@ -199,6 +212,7 @@ mod flags {
     #[derive(Debug)]
     pub struct Package {
         pub package_type: PackageType,
+        pub kernel: Option<PathBuf>,
     }
 
     #[derive(Debug)]
@@ -207,6 +221,9 @@ mod flags {
     #[derive(Debug)]
     pub struct Run {
         pub package_type: PackageType,
+        pub kernel: Option<PathBuf>,
+        pub smp: Option<u32>,
+        pub gic_version: Option<u32>,
     }
 
     pub trait Subcommand {
@@ -217,10 +234,13 @@ mod flags {
     fn cargo_run(
         subcommand: &str,
         binary: &Option<Binary>,
+        features: &[&str],
         sh: &Shell,
         _xtask: &Xtask,
         json_message_format: bool,
     ) -> anyhow::Result<()> {
+        let features = features.join(",");
+
         // Run for all binaries if `binary` is none
         let mut binaries = get_binaries(binary);
 
@@ -245,6 +265,11 @@ mod flags {
                 flags.push(binary.target()?);
             }
 
+            if !features.is_empty() {
+                flags.push("--features");
+                flags.push(&features);
+            }
+
             // JSON message format is needed for rust analyzer
             if json_message_format {
                 flags.push(JSON_MESSAGE_FORMAT_FLAG);
@@ -259,26 +284,43 @@ mod flags {
 
     impl Subcommand for Build {
         fn run(&self, sh: &Shell, xtask: &Xtask) -> anyhow::Result<()> {
-            cargo_run("build", &self.binary, sh, xtask, self.json_message_format)
+            cargo_run(
+                "build",
+                &self.binary,
+                &[],
+                sh,
+                xtask,
+                self.json_message_format,
+            )
         }
     }
 
     impl Subcommand for Check {
         fn run(&self, sh: &Shell, xtask: &Xtask) -> anyhow::Result<()> {
-            cargo_run("check", &self.binary, sh, xtask, self.json_message_format)
+            cargo_run(
+                "check",
+                &self.binary,
+                &[],
+                sh,
+                xtask,
+                self.json_message_format,
+            )
         }
     }
 
     impl Subcommand for Package {
         fn run(&self, sh: &Shell, xtask: &Xtask) -> anyhow::Result<()> {
+            self.package(sh, xtask, &[])
+        }
+    }
+
+    impl Package {
+        /// Builds the binary with `features` enabled and packages the distribution.
+        fn package(&self, sh: &Shell, xtask: &Xtask, features: &[&str]) -> anyhow::Result<()> {
             let binary = self.package_type.binary();
 
             // Build the needed binary before packaging the distribution.
-            let build = Build {
-                binary: Some(binary),
-                json_message_format: false,
-            };
-            build.run(sh, xtask)?;
+            cargo_run("build", &Some(binary), features, sh, xtask, false)?;
 
             match self.package_type {
                 PackageType::Aarch64Qemu | PackageType::RiscV64Qemu => {
@@ -304,6 +346,14 @@ mod flags {
                         "mcopy -D o -i {esp_path} {binary_path} '::/EFI/BOOT/BOOTX64.EFI'"
                     )
                     .run()?;
+                    // Copy the kernel to where the bootloader loads it from
+                    if let Some(kernel_path) = &self.kernel {
+                        cmd!(
+                            sh,
+                            "mcopy -D o -i {esp_path} {kernel_path} '::/EFI/BOOT/KERNEL.ELF'"
+                        )
+                        .run()?;
+                    }
 
                     // TODO: Use hdiutil on MacOS instead of parted
                     // Create 66MB disk image
@@ -331,17 +381,25 @@ mod flags {
             // Package the needed distribution before running
             let package = Package {
                 package_type: self.package_type,
+                kernel: self.kernel.clone(),
             };
-            package.run(sh, xtask)?;
+            package.package(sh, xtask, self.package_type.run_features())?;
+
+            let smp = self.smp.unwrap_or(1).to_string();
 
             match self.package_type {
                 PackageType::Aarch64Qemu => {
                     let build_dir = self.package_type.binary().build_directory(xtask.release);
                     let binary_path =
                         format!("{}/{}", build_dir, self.package_type.binary().as_str());
+                    // QEMU picks a GICv2 unless asked for another version
+                    let machine = match self.gic_version {
+                        Some(version) => format!("virt,gic-version={}", version),
+                        None => "virt".to_string(),
+                    };
                     cmd!(
                         sh,
-                        "qemu-system-aarch64 -machine virt -cpu cortex-a57 -kernel {binary_path} -nographic"
+                        "qemu-system-aarch64 -machine {machine} -cpu cortex-a57 -kernel {binary_path} -smp {smp} -nographic -semihosting"
                     )
                     .run()?;
                 }
@@ -351,7 +409,7 @@ mod flags {
                         format!("{}/{}", build_dir, self.package_type.binary().as_str());
                     cmd!(
                         sh,
-                        "qemu-system-riscv64 -machine virt -kernel {binary_path} -nographic"
+                        "qemu-system-riscv64 -machine virt -kernel {binary_path} -smp {smp} -nographic"
                     )
                     .run()?;
                 }
@@ -364,7 +422,7 @@ mod flags {
                     let ovmf_drive = format!("file={},if=pflash,format=raw,readonly=on", ovmf_path);
                     let disk_drive = format!("file={},format=raw", disk_path);
 
-                    cmd!(sh, "qemu-system-x86_64 -drive {ovmf_drive} -drive {disk_drive} -cpu qemu64 -net none -serial stdio").run()?;
+                    cmd!(sh, "qemu-system-x86_64 -drive {ovmf_drive} -drive {disk_drive} -cpu qemu64 -smp {smp} -net none -serial stdio").run()?;
                 }
             }
 